@@ -0,0 +1,91 @@
+//! Compares embedding claims in a single pass over the module, which is what `embed_claims`
+//! does, with hashing the module in one pass and rebuilding it in another
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wascap::{
+    prelude::*,
+    wasm::{self, compute_module_hash_with_scope},
+};
+use wasm_encoder::{
+    CodeSection, ConstExpr, CustomSection, DataSection, Function, FunctionSection, Instruction,
+    MemorySection, MemoryType, Module, TypeSection,
+};
+
+// Builds a module with the given number of small functions and a data segment of the given size,
+// followed by a custom section of the same size, so every kind of hashed section is present
+fn module(functions: u32, data_size: usize) -> Vec<u8> {
+    let mut types = TypeSection::new();
+    types.function(vec![], vec![]);
+    let mut funcs = FunctionSection::new();
+    let mut code = CodeSection::new();
+    for _ in 0..functions {
+        funcs.function(0);
+        let mut body = Function::new(vec![]);
+        body.instruction(&Instruction::Nop);
+        body.instruction(&Instruction::End);
+        code.function(&body);
+    }
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: (data_size as u64 / 65536) + 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+    });
+    let payload: Vec<u8> = (0..data_size).map(|i| (i % 251) as u8).collect();
+    let mut data = DataSection::new();
+    data.active(0, &ConstExpr::i32_const(0), payload.iter().copied());
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&funcs)
+        .section(&memories)
+        .section(&code)
+        .section(&data)
+        .section(&CustomSection {
+            name: "producers",
+            data: &payload,
+        });
+    module.finish()
+}
+
+fn embed(c: &mut Criterion) {
+    let issuer = KeyPair::new_account();
+    let claims = ClaimsBuilder::<Actor>::new()
+        .issuer(&issuer.public_key())
+        .subject(&KeyPair::new_module().public_key())
+        .with_metadata(Actor {
+            name: Some("bench".to_string()),
+            ..Default::default()
+        })
+        .build();
+    let meta = claims.metadata.as_ref().unwrap();
+
+    let mut group = c.benchmark_group("embed");
+    for (functions, data_size) in [(100, 64 * 1024), (10_000, 4 * 1024 * 1024)] {
+        let module = module(functions, data_size);
+        let size = module.len();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("single_pass", size),
+            &module,
+            |b, module| b.iter(|| wasm::embed_claims(black_box(module), &claims, &issuer).unwrap()),
+        );
+        group.bench_with_input(BenchmarkId::new("two_pass", size), &module, |b, module| {
+            b.iter(|| {
+                let hash = compute_module_hash_with_scope(
+                    black_box(module),
+                    meta.hash_algorithm,
+                    &meta.hash_scope,
+                )
+                .unwrap();
+                wasm::embed_claims_with_hash(module, &claims, &issuer, &hash).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, embed);
+criterion_main!(benches);
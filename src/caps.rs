@@ -8,13 +8,41 @@ pub const EVENTSTREAMS: &str = "wasmcloud:eventstreams";
 pub const NUMBERGEN: &str = "wasmcloud:builtin:numbergen";
 pub const LOGGING: &str = "wasmcloud:builtin:logging";
 
-use std::collections::HashMap;
+/// Bundle of the built-in host capabilities a module needs for basic I/O:
+/// `wasmcloud:builtin:logging` and `wasmcloud:builtin:numbergen`
+pub const STANDARD_IO: &str = "standard-io";
+/// Bundle of both HTTP capabilities: `wasmcloud:httpserver` and `wasmcloud:httpclient`
+pub const HTTP: &str = "http";
+
+/// Namespace of the capabilities provided by the host itself rather than by capability providers
+pub const BUILTIN_NAMESPACE: &str = "wasmcloud:builtin";
+
+use crate::{errors, errors::ErrorKind, Error};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    str::FromStr,
+    sync::{PoisonError, RwLock, RwLockWriteGuard},
+};
+
+static KNOWN_CAPABILITIES: &[&str] = &[
+    BLOB,
+    HTTP_CLIENT,
+    HTTP_SERVER,
+    KEY_VALUE,
+    MESSAGING,
+    EVENTSTREAMS,
+    NUMBERGEN,
+    LOGGING,
+];
 
 lazy_static! {
     static ref CAPABILITY_NAMES: HashMap<&'static str, &'static str> = {
         let mut m = HashMap::new();
         m.insert(MESSAGING, "Messaging");
-        m.insert(KEY_VALUE, "K/V Store");
+        m.insert(KEY_VALUE, "Key-Value Store");
         m.insert(HTTP_SERVER, "HTTP Server");
         m.insert(HTTP_CLIENT, "HTTP Client");
         m.insert(BLOB, "Blob Store");
@@ -23,10 +51,175 @@ lazy_static! {
         m.insert(LOGGING, "Logging");
         m
     };
+    static ref CAPABILITY_ALIASES: RwLock<HashMap<String, Vec<String>>> = {
+        let mut m = HashMap::new();
+        m.insert(
+            STANDARD_IO.to_string(),
+            vec![LOGGING.to_string(), NUMBERGEN.to_string()],
+        );
+        m.insert(
+            HTTP.to_string(),
+            vec![HTTP_SERVER.to_string(), HTTP_CLIENT.to_string()],
+        );
+        RwLock::new(m)
+    };
 }
 
-pub fn capability_name(cap: &str) -> String {
+/// Returns the human-friendly display name of a well-known capability, or `None` if the
+/// capability isn't one of the standard ones
+pub fn capability_name(id: &str) -> Option<&'static str> {
+    CAPABILITY_NAMES.get(id).copied()
+}
+
+/// Returns the capability ID corresponding to the display name of a well-known capability
+/// (the reverse of `capability_name`), or `None` if no standard capability has that name
+pub fn capability_id(name: &str) -> Option<&'static str> {
     CAPABILITY_NAMES
-        .get(cap)
-        .map_or(cap.to_string(), |item| item.to_string())
+        .iter()
+        .find(|(_, n)| **n == name)
+        .map(|(id, _)| *id)
+}
+
+/// Indicates whether the given capability is one of the standard, well-known capabilities
+pub fn is_known_capability(cap: &str) -> bool {
+    KNOWN_CAPABILITIES.contains(&cap)
+}
+
+/// Returns all of the standard, well-known capabilities
+pub fn all_known_capabilities() -> &'static [&'static str] {
+    KNOWN_CAPABILITIES
+}
+
+/// Registers a bundle alias that `expand_capability_aliases` replaces with the given capability
+/// IDs, in addition to the built-in `standard-io` and `http` bundles. Registering an alias that
+/// already exists, including a built-in one, replaces its capabilities. The registry is shared
+/// by the whole process, so an alias stays registered until `unregister_capability_alias` is
+/// called for it
+pub fn register_capability_alias(alias: &str, caps: &[&str]) {
+    aliases_mut().insert(
+        alias.to_string(),
+        caps.iter().map(|c| c.to_string()).collect(),
+    );
+}
+
+/// Removes a bundle alias from the registry, returning whether it was registered. Built-in
+/// bundles can be removed in the same way
+pub fn unregister_capability_alias(alias: &str) -> bool {
+    aliases_mut().remove(alias).is_some()
+}
+
+/// Replaces every bundle alias in a list of capabilities with the capability IDs it stands for.
+/// Strings that aren't registered aliases pass through unchanged, and each capability appears
+/// only once in the result, at the position it first occurs
+pub fn expand_capability_aliases(caps: &[String]) -> Vec<String> {
+    let aliases = CAPABILITY_ALIASES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut expanded: Vec<String> = Vec::with_capacity(caps.len());
+    for cap in caps {
+        match aliases.get(cap) {
+            Some(bundle) => {
+                for c in bundle {
+                    if !expanded.contains(c) {
+                        expanded.push(c.clone());
+                    }
+                }
+            }
+            None if !expanded.contains(cap) => expanded.push(cap.clone()),
+            None => {}
+        }
+    }
+    expanded
+}
+
+// Every update to the registry is a single insert or remove, so it's consistent even if a
+// thread panicked while holding the lock
+fn aliases_mut() -> RwLockWriteGuard<'static, HashMap<String, Vec<String>>> {
+    CAPABILITY_ALIASES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Returns the namespace of a capability: everything before its last `:`-separated segment, e.g.
+/// `wasmcloud:builtin` for `wasmcloud:builtin:logging` and `wasmcloud` for `wasmcloud:keyvalue`.
+/// Capabilities that don't follow the `namespace:name` convention have no namespace
+pub fn namespace(cap: &str) -> Option<&str> {
+    match cap.rsplit_once(':') {
+        Some((ns, name)) if !ns.is_empty() && !name.is_empty() => Some(ns),
+        _ => None,
+    }
+}
+
+/// Indicates whether a capability is provided by the host itself, i.e. is in the
+/// `wasmcloud:builtin` namespace
+pub fn is_builtin(cap: &str) -> bool {
+    namespace(cap) == Some(BUILTIN_NAMESPACE)
+}
+
+/// A capability ID of the form `namespace:name`, such as `wasmcloud:keyvalue`. The name may
+/// itself contain further `:`-separated segments, e.g. `wasmcloud:builtin:logging`. Every
+/// segment must be non-empty and made up of ASCII letters, digits, `-`, `_` or `.`, so parsing
+/// catches malformed IDs that a plain string would let through
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CapabilityId(Cow<'static, str>);
+
+impl CapabilityId {
+    pub const BLOB: CapabilityId = CapabilityId(Cow::Borrowed(BLOB));
+    pub const HTTP_CLIENT: CapabilityId = CapabilityId(Cow::Borrowed(HTTP_CLIENT));
+    pub const HTTP_SERVER: CapabilityId = CapabilityId(Cow::Borrowed(HTTP_SERVER));
+    pub const KEY_VALUE: CapabilityId = CapabilityId(Cow::Borrowed(KEY_VALUE));
+    pub const MESSAGING: CapabilityId = CapabilityId(Cow::Borrowed(MESSAGING));
+    pub const EVENTSTREAMS: CapabilityId = CapabilityId(Cow::Borrowed(EVENTSTREAMS));
+    pub const NUMBERGEN: CapabilityId = CapabilityId(Cow::Borrowed(NUMBERGEN));
+    pub const LOGGING: CapabilityId = CapabilityId(Cow::Borrowed(LOGGING));
+
+    /// The capability ID as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for CapabilityId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = s.contains(':')
+            && s.split(':').all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            });
+        if valid {
+            Ok(CapabilityId(Cow::Owned(s.to_string())))
+        } else {
+            Err(errors::new(ErrorKind::InvalidCapability))
+        }
+    }
+}
+
+impl TryFrom<&str> for CapabilityId {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for CapabilityId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for CapabilityId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<CapabilityId> for String {
+    fn from(id: CapabilityId) -> String {
+        id.0.into_owned()
+    }
 }
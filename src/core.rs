@@ -0,0 +1,390 @@
+//! The claim types themselves, with their serde representations and JWT decoding. This module
+//! only needs `alloc`, so it's available without the `std` feature for environments that need to
+//! parse and inspect claims but can't verify signatures or module hashes. Everything else in the
+//! crate requires `std`
+
+use crate::{errors, errors::ErrorKind, Result};
+use alloc::{string::String, vec::Vec};
+use core::{
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::from_str;
+
+// Maps in the claim types are ordered, so they're available without `std` and encoding the same
+// claims always produces the same JWT
+type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+
+/// A structure containing a JWT and its associated decoded claims. Tokens are equal when their
+/// JWTs are, since the claims are decoded from the JWT
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Token<T> {
+    pub jwt: String,
+    pub claims: Claims<T>,
+    /// Indicates whether the module hash in the claims was checked against the module the token
+    /// was extracted from. Tokens signed by older versions of wascap have hashes that can't be
+    /// verified and are accepted with this set to `false`, so callers that want to reject such
+    /// modules should check it. It's only ever set by extracting the token from a module, and
+    /// isn't serialized, so a deserialized token always has it `false`
+    #[serde(skip)]
+    pub hash_verified: bool,
+}
+
+impl<T> PartialEq for Token<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.jwt == other.jwt
+    }
+}
+
+impl<T> Eq for Token<T> {}
+
+impl<T> Hash for Token<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.jwt.hash(state);
+    }
+}
+
+impl<T> Token<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Reassembles a token from a JWT and its claims, such as ones previously taken apart with
+    /// `into_parts` or cached separately. The module hash hasn't been checked against any module,
+    /// so `hash_verified` is always `false`, whatever the token it came from recorded
+    ///
+    /// # Errors
+    /// Returns a `Token` error if the claims don't match the ones encoded in the JWT
+    pub fn from_parts(jwt: String, claims: Claims<T>) -> Result<Token<T>> {
+        let token = Token {
+            jwt,
+            claims,
+            hash_verified: false,
+        };
+        token.check_claims()?;
+        Ok(token)
+    }
+
+    /// Checks that the claims of this token are the ones encoded in its JWT, so that a token
+    /// deserialized from an untrusted cache can't carry claims other than the signed ones. The
+    /// JWT's signature isn't verified
+    ///
+    /// # Errors
+    /// Returns a `Token` error if the claims don't match the ones encoded in the JWT
+    pub fn check_claims(&self) -> Result<()> {
+        let decoded: Claims<T> = Claims::decode_unverified(&self.jwt)?;
+        if serde_json::to_value(&decoded)? == serde_json::to_value(&self.claims)? {
+            Ok(())
+        } else {
+            Err(errors::new(ErrorKind::Token(
+                "claims do not match the JWT".into(),
+            )))
+        }
+    }
+}
+
+impl<T> Token<T> {
+    /// Splits the token into its JWT and decoded claims
+    pub fn into_parts(self) -> (String, Claims<T>) {
+        (self.jwt, self.claims)
+    }
+}
+
+pub(crate) fn default_as_false() -> bool {
+    false
+}
+
+/// The algorithm used to compute the hash of a module's bytes
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    #[serde(rename = "sha256")]
+    Sha256,
+    #[serde(rename = "sha512")]
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == HashAlgorithm::default()
+    }
+}
+
+/// The parts of a module covered by its module hash. By default every custom section other than
+/// the embedded claims is hashed, but sections that differ between otherwise identical builds,
+/// such as `producers` or DWARF debug sections, can be excluded
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct HashScope {
+    /// Names of custom sections left out of the hash. A name ending in `*` matches every section
+    /// starting with the rest of the name, e.g. `.debug_*`
+    #[serde(rename = "exclude", default)]
+    pub excluded_sections: Vec<String>,
+    /// When set, the data section (the module's initial memory) is left out of the hash, so it
+    /// can be patched after signing without invalidating the claims. This means **changes to the
+    /// data section go undetected**: only set it for modules whose initial memory isn't trusted
+    /// to be what was signed. Versions of wascap that don't know about it hash the data section
+    /// anyway, so they reject such modules rather than accepting patched data
+    #[serde(
+        rename = "exclude_data",
+        default,
+        skip_serializing_if = "::core::ops::Not::not"
+    )]
+    pub exclude_data: bool,
+}
+
+impl HashScope {
+    /// Indicates whether the custom section with the given name is left out of the hash
+    pub fn excludes(&self, section: &str) -> bool {
+        self.excluded_sections
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => section.starts_with(prefix),
+                None => section == pattern,
+            })
+    }
+
+    /// A scope covering the code and custom sections of a module but not its data section. See
+    /// `exclude_data` for what this means for verification
+    pub fn code_and_custom_only() -> HashScope {
+        HashScope {
+            exclude_data: true,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.excluded_sections.is_empty() && !self.exclude_data
+    }
+}
+
+/// The metadata that corresponds to an actor module
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Actor {
+    /// A descriptive name for this actor, should not include version information or public key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// A hash of the module's bytes as they exist without the embedded signature. This is stored so wascap
+    /// can determine if a WebAssembly module's bytecode has been altered after it was signed
+    #[serde(rename = "hash")]
+    pub module_hash: String,
+
+    /// The algorithm used to compute `module_hash`. Claims that predate this field were always
+    /// hashed with SHA-256
+    #[serde(
+        rename = "hash_alg",
+        default,
+        skip_serializing_if = "HashAlgorithm::is_default"
+    )]
+    pub hash_algorithm: HashAlgorithm,
+
+    /// The parts of the module covered by `module_hash`. Claims that predate this field covered
+    /// every custom section
+    #[serde(
+        rename = "hash_scope",
+        default,
+        skip_serializing_if = "HashScope::is_default"
+    )]
+    pub hash_scope: HashScope,
+
+    /// List of arbitrary string tags associated with the claims. Tags starting with a reserved
+    /// prefix have a defined meaning: `contract:` followed by a contract ID advertises an
+    /// interface contract the actor implements (see `jwt::interface_tags`). All other tags are
+    /// free-form
+    #[serde(rename = "tags", skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    /// List of capability attestations. Can be standard wascap capabilities or custom namespace capabilities
+    #[serde(rename = "caps", skip_serializing_if = "Option::is_none")]
+    pub caps: Option<Vec<String>>,
+
+    /// Optional constraints on individual capabilities in `caps`, keyed by capability
+    #[serde(rename = "cap_constraints", skip_serializing_if = "Option::is_none")]
+    pub cap_constraints: Option<Map<String, CapConstraint>>,
+
+    /// Grants of capabilities in `caps` that are scoped to a target resource. Each capability
+    /// granted here is also listed in `caps`, so hosts that only read that list still see it
+    #[serde(rename = "caps_detailed", skip_serializing_if = "Option::is_none")]
+    pub caps_detailed: Option<Vec<CapGrant>>,
+
+    /// Indicates a monotonically increasing revision number.  Optional.
+    #[serde(rename = "rev", skip_serializing_if = "Option::is_none")]
+    pub rev: Option<i32>,
+
+    /// Indicates a human-friendly version string
+    #[serde(rename = "ver", skip_serializing_if = "Option::is_none")]
+    pub ver: Option<String>,
+
+    /// An optional, code-friendly alias that can be used instead of a public key or
+    /// OCI reference for invocations
+    #[serde(rename = "call_alias", skip_serializing_if = "Option::is_none")]
+    pub call_alias: Option<String>,
+
+    /// The OCI image reference the module is published under, if it was signed for one.
+    /// See `verify_oci_reference`
+    #[serde(rename = "oci_ref", skip_serializing_if = "Option::is_none")]
+    pub oci_reference: Option<String>,
+
+    /// The host imports the module required when it was signed, e.g. `module::name` for a core
+    /// module. Only recorded when `EmbedOptions::record_imports` is set, see `wasm::scan_imports`
+    #[serde(rename = "imports", skip_serializing_if = "Option::is_none")]
+    pub imports: Option<Vec<String>>,
+
+    /// Indicates whether this module is a capability provider
+    #[serde(rename = "prov", default = "default_as_false")]
+    pub provider: bool,
+}
+
+/// Constraints placed on a single capability granted to an actor
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct CapConstraint {
+    /// Time after which the capability is no longer granted, in _seconds since the epoch_. If
+    /// `None` the capability lasts as long as the token does
+    #[serde(rename = "exp", skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+}
+
+/// A capability granted to an actor, optionally limited to a single target resource
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct CapGrant {
+    /// The ID of the capability, as listed in `caps`
+    pub capability: String,
+    /// The resource the capability is limited to, e.g. the name of a key-value bucket. If `None`
+    /// the capability isn't limited to any particular resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Represents a set of [RFC 7519](https://tools.ietf.org/html/rfc7519) compliant JSON Web Token
+/// claims.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct Claims<T> {
+    /// All timestamps in JWTs are stored in _seconds since the epoch_ format
+    /// as described as `NumericDate` in the RFC. Corresponds to the `exp` field in a JWT.
+    #[serde(rename = "exp", skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+
+    /// Corresponds to the `jti` field in a JWT.
+    #[serde(rename = "jti")]
+    pub id: String,
+
+    /// The `iat` field, stored in _seconds since the epoch_
+    #[serde(rename = "iat")]
+    pub issued_at: u64,
+
+    /// Issuer of the token, by convention usually the public key of the _account_ that
+    /// signed the token
+    #[serde(rename = "iss")]
+    pub issuer: String,
+
+    /// Subject of the token, usually the public key of the _module_ corresponding to the WebAssembly file
+    /// being signed
+    #[serde(rename = "sub")]
+    pub subject: String,
+
+    /// The `nbf` JWT field, indicates the time when the token becomes valid. If `None` token is valid immediately
+    #[serde(rename = "nbf", skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+
+    /// Custom jwt claims in the `wascap` namespace
+    #[serde(rename = "wascap", skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<T>,
+
+    /// Internal revision number used to aid in parsing and validating claims
+    #[serde(rename = "wascap_revision", skip_serializing_if = "Option::is_none")]
+    pub(crate) wascap_revision: Option<u32>,
+}
+
+impl<T> Claims<T>
+where
+    T: DeserializeOwned,
+{
+    /// Decodes the claims in a JWT without verifying its signature or checking its dates, so
+    /// nothing about the claims can be trusted. With the `std` feature, `decode` verifies them
+    pub fn decode_unverified(input: &str) -> Result<Claims<T>> {
+        let segments: Vec<&str> = input.split('.').collect();
+        if segments.len() != 3 {
+            return Err(errors::new(ErrorKind::Token("invalid token format".into())));
+        }
+        let claims: Claims<T> = from_jwt_segment(segments[1])?;
+
+        Ok(claims)
+    }
+}
+
+impl<T> Claims<T> {
+    /// Indicates whether these claims have expired at the given time, in _seconds since the
+    /// epoch_. Claims whose expiration is exactly `now` are still valid
+    pub fn expired_at(&self, now: u64) -> bool {
+        self.expires.is_some_and(|exp| exp < now)
+    }
+
+    /// Indicates whether these claims are _not yet_ valid at the given time, in _seconds since
+    /// the epoch_. Claims whose "not before" time is exactly `now` can be used
+    pub fn cannot_use_yet_at(&self, now: u64) -> bool {
+        self.not_before.is_some_and(|nbf| now < nbf)
+    }
+
+    /// The time left before these claims expire at the given time, in _seconds since the
+    /// epoch_. Returns `None` for claims that never expire, and `Duration::ZERO` for claims
+    /// whose expiration is at or before `now`
+    pub fn time_until_expiry_at(&self, now: u64) -> Option<Duration> {
+        self.expires
+            .map(|exp| Duration::from_secs(exp.saturating_sub(now)))
+    }
+
+    /// Indicates whether these claims expire within the given duration of the given time, in
+    /// _seconds since the epoch_, i.e. whether `expired_at` holds once the duration has passed.
+    /// Claims that have already expired expire within any duration, claims that never expire
+    /// within none
+    pub fn expires_within_at(&self, d: Duration, now: u64) -> bool {
+        // Claims are still valid during the second of their expiration
+        self.expired_at(now) || self.time_until_expiry_at(now).is_some_and(|left| left < d)
+    }
+}
+
+pub(crate) fn from_jwt_segment<B: AsRef<str>, T: DeserializeOwned>(encoded: B) -> Result<T> {
+    let decoded = base64::decode_config(encoded.as_ref(), base64::URL_SAFE_NO_PAD)?;
+    let s = String::from_utf8(decoded)?;
+
+    Ok(from_str(&s)?)
+}
+
+#[cfg(test)]
+mod test {
+    // Only uses what's available without `std`, so it also runs with `--no-default-features`
+    use super::*;
+    use alloc::{format, string::ToString, vec};
+
+    fn segment(json: &str) -> String {
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    #[test]
+    fn decode_without_std() {
+        let header = segment(r#"{"typ":"jwt","alg":"Ed25519"}"#);
+        let claims = segment(
+            r#"{"jti":"1234","iat":1700000000,"iss":"ACCOUNT","sub":"MODULE","exp":1700003600,
+                "wascap":{"name":"test","hash":"","caps":["wasmcloud:messaging"],
+                "cap_constraints":{"wasmcloud:messaging":{"exp":1700001800}}}}"#,
+        );
+        let jwt = format!("{}.{}.{}", header, claims, segment("signature"));
+
+        let claims = Claims::<Actor>::decode_unverified(&jwt).unwrap();
+        assert_eq!(claims.issuer, "ACCOUNT");
+        assert_eq!(claims.subject, "MODULE");
+        let meta = claims.metadata.as_ref().unwrap();
+        assert_eq!(meta.name.as_deref(), Some("test"));
+        assert_eq!(meta.caps, Some(vec!["wasmcloud:messaging".to_string()]));
+        assert_eq!(
+            meta.cap_constraints.as_ref().unwrap()["wasmcloud:messaging"].expires,
+            Some(1_700_001_800)
+        );
+        assert!(!claims.expired_at(1_700_003_600));
+        assert!(claims.expired_at(1_700_003_601));
+
+        let token = Token::from_parts(jwt, claims).unwrap();
+        assert!(!token.hash_verified);
+        assert!(Claims::<Actor>::decode_unverified("not.a jwt").is_err());
+    }
+}
@@ -12,8 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{error::Error as StdError, fmt};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error as StdError;
 
+#[cfg(feature = "std")]
 use wasmparser::BinaryReaderError;
 
 /// An error that can contain wascap-specific context
@@ -27,19 +31,64 @@ pub(crate) fn new(kind: ErrorKind) -> Error {
 #[derive(Debug)]
 pub enum ErrorKind {
     Serialize(serde_json::error::Error),
+    #[cfg(feature = "std")]
     Encryption(nkeys::error::Error),
     Decode(base64::DecodeError),
-    UTF8(std::string::FromUtf8Error),
+    UTF8(alloc::string::FromUtf8Error),
     Token(String),
     InvalidCapability,
     WasmElement(String),
+    #[cfg(feature = "std")]
     IO(std::io::Error),
     InvalidModuleHash,
-    ExpiredToken,
+    TokenExpired(u64),
     TokenTooEarly,
     InvalidAlgorithm,
     MissingIssuer,
     MissingSubject,
+    MultipleTokens,
+    InvalidKeyType,
+    UnknownCapability(String),
+    ClockError,
+    InvalidJwtEncoding(String, alloc::string::FromUtf8Error),
+    SignatureInvalid,
+    SignatureMissing,
+    UntrustedIssuer(String),
+    Revoked,
+    IssuedInFuture(u64),
+    InvalidSeed,
+    InvalidMnemonic(String),
+    MalformedModule {
+        offset: usize,
+        detail: String,
+    },
+    AlreadyExpired(u64),
+    Decompression(String),
+    NoEmbeddedClaims,
+    MissingMetadata,
+    JwtSectionTooLarge {
+        size: usize,
+        max: usize,
+    },
+    MalformedHash(String),
+    EmptyModule,
+    NotAWasmModule(Vec<u8>),
+    StartFunctionForbidden,
+    RevisionTooOld {
+        revision: u32,
+        min: u32,
+    },
+    MissingEnvVar(String),
+    SubjectNotModuleKey(String),
+    HashMismatch,
+    ArtifactTooLarge(usize),
+    InconsistentClaims(String),
+    NotInTransparencyLog(String),
+    DecompressedTooLarge(usize),
+    InclusionProofInvalid(String),
+    TimestampOverflow,
+    #[cfg(feature = "reqwest")]
+    Http(reqwest::Error),
 }
 
 impl Error {
@@ -50,44 +99,128 @@ impl Error {
     pub fn into_kind(self) -> ErrorKind {
         *self.0
     }
+
+    /// The kind of the underlying I/O error, if this error was caused by one. Callers can use
+    /// this to retry transient failures, such as `TimedOut` or `Interrupted`, when reading a
+    /// module from a stream
+    #[cfg(feature = "std")]
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match *self.0 {
+            ErrorKind::IO(ref err) => Some(err.kind()),
+            _ => None,
+        }
+    }
 }
 
+#[cfg(feature = "std")]
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self.0 {
             ErrorKind::Serialize(_) => "Serialization failure",
+            #[cfg(feature = "std")]
             ErrorKind::Encryption(_) => "Encryption failure",
             ErrorKind::Decode(_) => "Decode failure",
             ErrorKind::UTF8(_) => "UTF8 failure",
             ErrorKind::Token(_) => "JWT failure",
             ErrorKind::InvalidCapability => "Invalid Capability",
             ErrorKind::WasmElement(_) => "WebAssembly element",
+            #[cfg(feature = "std")]
             ErrorKind::IO(_) => "I/O error",
             ErrorKind::InvalidModuleHash => "Invalid Module Hash",
-            ErrorKind::ExpiredToken => "Token has expired",
+            ErrorKind::TokenExpired(_) => "Token has expired",
             ErrorKind::TokenTooEarly => "Token cannot be used yet",
             ErrorKind::InvalidAlgorithm => "Invalid JWT algorithm",
             ErrorKind::MissingIssuer => "Missing issuer claim",
             ErrorKind::MissingSubject => "Missing sub claim",
+            ErrorKind::MultipleTokens => "Multiple conflicting tokens",
+            ErrorKind::InvalidKeyType => "Invalid key type",
+            ErrorKind::UnknownCapability(_) => "Unknown capability",
+            ErrorKind::ClockError => "System clock error",
+            ErrorKind::InvalidJwtEncoding(..) => "Invalid JWT encoding",
+            ErrorKind::SignatureInvalid => "Invalid JWT signature",
+            ErrorKind::SignatureMissing => "Missing JWT signature",
+            ErrorKind::UntrustedIssuer(_) => "Untrusted issuer",
+            ErrorKind::Revoked => "Revoked claims",
+            ErrorKind::IssuedInFuture(_) => "Token issued in the future",
+            ErrorKind::InvalidSeed => "Invalid seed",
+            ErrorKind::InvalidMnemonic(_) => "Invalid mnemonic",
+            ErrorKind::MalformedModule { .. } => "Malformed WebAssembly module",
+            ErrorKind::AlreadyExpired(_) => "Token expires before it is issued",
+            ErrorKind::Decompression(_) => "Decompression failure",
+            ErrorKind::NoEmbeddedClaims => "No embedded claims",
+            ErrorKind::MissingMetadata => "Missing metadata",
+            ErrorKind::JwtSectionTooLarge { .. } => "JWT section too large",
+            ErrorKind::MalformedHash(_) => "Malformed module hash",
+            ErrorKind::EmptyModule => "Empty module",
+            ErrorKind::NotAWasmModule(_) => "Not a WebAssembly module",
+            ErrorKind::StartFunctionForbidden => "Start function forbidden",
+            ErrorKind::RevisionTooOld { .. } => "Wascap revision too old",
+            ErrorKind::MissingEnvVar(_) => "Missing environment variable",
+            ErrorKind::SubjectNotModuleKey(_) => "Subject is not a module key",
+            ErrorKind::HashMismatch => "Module hash mismatch",
+            ErrorKind::ArtifactTooLarge(_) => "Artifact too large",
+            ErrorKind::InconsistentClaims(_) => "Inconsistent claims",
+            ErrorKind::NotInTransparencyLog(_) => "Not in transparency log",
+            ErrorKind::DecompressedTooLarge(_) => "Decompressed module too large",
+            ErrorKind::InclusionProofInvalid(_) => "Invalid inclusion proof",
+            ErrorKind::TimestampOverflow => "Timestamp overflow",
+            #[cfg(feature = "reqwest")]
+            ErrorKind::Http(_) => "HTTP error",
         }
     }
 
     fn cause(&self) -> Option<&dyn StdError> {
         match *self.0 {
             ErrorKind::Serialize(ref err) => Some(err),
+            #[cfg(feature = "std")]
             ErrorKind::Encryption(ref err) => Some(err),
             ErrorKind::Decode(ref err) => Some(err),
             ErrorKind::UTF8(ref err) => Some(err),
             ErrorKind::Token(_) => None,
             ErrorKind::InvalidCapability => None,
             ErrorKind::WasmElement(_) => None,
+            #[cfg(feature = "std")]
             ErrorKind::IO(ref err) => Some(err),
             ErrorKind::InvalidModuleHash => None,
-            ErrorKind::ExpiredToken => None,
+            ErrorKind::TokenExpired(_) => None,
             ErrorKind::TokenTooEarly => None,
             ErrorKind::InvalidAlgorithm => None,
             ErrorKind::MissingIssuer => None,
             ErrorKind::MissingSubject => None,
+            ErrorKind::MultipleTokens => None,
+            ErrorKind::InvalidKeyType => None,
+            ErrorKind::UnknownCapability(_) => None,
+            ErrorKind::ClockError => None,
+            ErrorKind::InvalidJwtEncoding(_, ref err) => Some(err),
+            ErrorKind::SignatureInvalid => None,
+            ErrorKind::SignatureMissing => None,
+            ErrorKind::UntrustedIssuer(_) => None,
+            ErrorKind::Revoked => None,
+            ErrorKind::IssuedInFuture(_) => None,
+            ErrorKind::InvalidSeed => None,
+            ErrorKind::InvalidMnemonic(_) => None,
+            ErrorKind::MalformedModule { .. } => None,
+            ErrorKind::AlreadyExpired(_) => None,
+            ErrorKind::Decompression(_) => None,
+            ErrorKind::NoEmbeddedClaims => None,
+            ErrorKind::MissingMetadata => None,
+            ErrorKind::JwtSectionTooLarge { .. } => None,
+            ErrorKind::MalformedHash(_) => None,
+            ErrorKind::EmptyModule => None,
+            ErrorKind::NotAWasmModule(_) => None,
+            ErrorKind::StartFunctionForbidden => None,
+            ErrorKind::RevisionTooOld { .. } => None,
+            ErrorKind::MissingEnvVar(_) => None,
+            ErrorKind::SubjectNotModuleKey(_) => None,
+            ErrorKind::HashMismatch => None,
+            ErrorKind::ArtifactTooLarge(_) => None,
+            ErrorKind::InconsistentClaims(_) => None,
+            ErrorKind::NotInTransparencyLog(_) => None,
+            ErrorKind::DecompressedTooLarge(_) => None,
+            ErrorKind::InclusionProofInvalid(_) => None,
+            ErrorKind::TimestampOverflow => None,
+            #[cfg(feature = "reqwest")]
+            ErrorKind::Http(ref err) => Some(err),
         }
     }
 }
@@ -96,15 +229,17 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
             ErrorKind::Serialize(ref err) => write!(f, "Serialization error: {}", err),
+            #[cfg(feature = "std")]
             ErrorKind::Encryption(ref err) => write!(f, "Encryption error: {}", err),
             ErrorKind::Decode(ref err) => write!(f, "Decode error: {}", err),
             ErrorKind::UTF8(ref err) => write!(f, "UTF8 error: {}", err),
             ErrorKind::Token(ref err) => write!(f, "JWT error: {}", err),
             ErrorKind::InvalidCapability => write!(f, "Invalid capability"),
             ErrorKind::WasmElement(ref err) => write!(f, "Wasm Element error: {}", err),
+            #[cfg(feature = "std")]
             ErrorKind::IO(ref err) => write!(f, "I/O error: {}", err),
             ErrorKind::InvalidModuleHash => write!(f, "Invalid module hash"),
-            ErrorKind::ExpiredToken => write!(f, "Module token has expired"),
+            ErrorKind::TokenExpired(exp) => write!(f, "Module token expired at {}", exp),
             ErrorKind::TokenTooEarly => write!(f, "Module cannot be used yet"),
             ErrorKind::InvalidAlgorithm => {
                 write!(f, "Invalid JWT algorithm. WASCAP only supports Ed25519")
@@ -118,20 +253,138 @@ impl fmt::Display for Error {
             ErrorKind::MissingSubject => {
                 write!(f, "Invalid JWT. WASCAP requires a sub claim to be present")
             }
+            ErrorKind::MultipleTokens => {
+                write!(f, "Module contains multiple conflicting embedded tokens")
+            }
+            ErrorKind::InvalidKeyType => {
+                write!(
+                    f,
+                    "Invalid key type. Issuer must be an account key and subject a module key"
+                )
+            }
+            ErrorKind::UnknownCapability(ref cap) => write!(f, "Unknown capability: {}", cap),
+            ErrorKind::ClockError => write!(f, "System clock is set before the UNIX epoch"),
+            ErrorKind::InvalidJwtEncoding(ref section, ref err) => write!(
+                f,
+                "Custom section '{}' does not contain a valid UTF-8 JWT: {}",
+                section, err
+            ),
+            ErrorKind::SignatureInvalid => {
+                write!(f, "JWT signature does not match the issuer's key")
+            }
+            ErrorKind::SignatureMissing => write!(f, "JWT is not signed"),
+            ErrorKind::UntrustedIssuer(ref iss) => {
+                write!(f, "Issuer {} is not a trusted signer", iss)
+            }
+            ErrorKind::Revoked => write!(f, "Module or issuer key has been revoked"),
+            ErrorKind::IssuedInFuture(iat) => {
+                write!(
+                    f,
+                    "Module token claims to be issued in the future, at {}",
+                    iat
+                )
+            }
+            ErrorKind::InvalidSeed => write!(f, "File does not contain a valid nkeys seed"),
+            ErrorKind::InvalidMnemonic(ref reason) => write!(f, "Invalid mnemonic: {}", reason),
+            ErrorKind::MalformedModule { offset, ref detail } => {
+                write!(f, "Malformed module at byte {}: {}", offset, detail)
+            }
+            ErrorKind::AlreadyExpired(exp) => write!(
+                f,
+                "Token would already be expired when issued, expiring at {}",
+                exp
+            ),
+            ErrorKind::Decompression(ref reason) => write!(f, "Decompression error: {}", reason),
+            ErrorKind::NoEmbeddedClaims => write!(f, "Module does not contain any embedded claims"),
+            ErrorKind::MissingMetadata => {
+                write!(f, "JWT does not contain any metadata for its entity type")
+            }
+            ErrorKind::JwtSectionTooLarge { size, max } => write!(
+                f,
+                "JWT section of {} bytes exceeds the maximum of {} bytes",
+                size, max
+            ),
+            ErrorKind::MalformedHash(ref hash) => {
+                write!(f, "Module hash '{}' is not a valid digest", hash)
+            }
+            ErrorKind::EmptyModule => write!(f, "Module is empty"),
+            ErrorKind::NotAWasmModule(ref preamble) => write!(
+                f,
+                "Input is not a WebAssembly module, found preamble {:02x?}",
+                preamble
+            ),
+            ErrorKind::StartFunctionForbidden => {
+                write!(f, "Module declares a start function, which policy forbids")
+            }
+            ErrorKind::RevisionTooOld { revision, min } => write!(
+                f,
+                "Token was signed with wascap revision {}, below the minimum of {}",
+                revision, min
+            ),
+            ErrorKind::MissingEnvVar(ref name) => {
+                write!(f, "Environment variable {} is not set", name)
+            }
+            ErrorKind::SubjectNotModuleKey(ref subject) => {
+                write!(f, "Subject {} is not a module public key", subject)
+            }
+            ErrorKind::HashMismatch => {
+                write!(f, "Module hash in the token doesn't match the module")
+            }
+            ErrorKind::ArtifactTooLarge(max) => {
+                write!(f, "Artifact is larger than the limit of {} bytes", max)
+            }
+            ErrorKind::InconsistentClaims(ref reason) => {
+                write!(f, "Inconsistent claims: {}", reason)
+            }
+            ErrorKind::NotInTransparencyLog(ref digest) => {
+                write!(f, "No transparency log entry for {}", digest)
+            }
+            ErrorKind::DecompressedTooLarge(max) => write!(
+                f,
+                "Decompressed module is larger than the limit of {} bytes",
+                max
+            ),
+            ErrorKind::InclusionProofInvalid(ref reason) => {
+                write!(f, "Invalid transparency log inclusion proof: {}", reason)
+            }
+            ErrorKind::TimestampOverflow => {
+                write!(f, "Timestamp is too far in the future to be represented")
+            }
+            #[cfg(feature = "reqwest")]
+            ErrorKind::Http(ref err) => write!(f, "HTTP error: {}", err),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(source: std::io::Error) -> Error {
+        // Errors raised by the crate's own readers, such as the gzip decoder, are carried through
+        // `Read` inside an I/O error, and come back out as they were
+        if source.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            if let Some(Ok(err)) = source.into_inner().map(|inner| inner.downcast::<Error>()) {
+                return *err;
+            }
+            unreachable!("the I/O error was checked to hold a wascap error");
+        }
         Error(Box::new(ErrorKind::IO(source)))
     }
 }
 
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for Error {
+    fn from(source: reqwest::Error) -> Error {
+        Error(Box::new(ErrorKind::Http(source)))
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<BinaryReaderError> for Error {
     fn from(source: BinaryReaderError) -> Error {
-        let io_error = ::std::io::Error::new(::std::io::ErrorKind::Other, source.to_string());
-        Error(Box::new(ErrorKind::IO(io_error)))
+        Error(Box::new(ErrorKind::MalformedModule {
+            offset: source.offset(),
+            detail: source.message().to_string(),
+        }))
     }
 }
 
@@ -147,14 +400,15 @@ impl From<base64::DecodeError> for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<nkeys::error::Error> for Error {
     fn from(source: nkeys::error::Error) -> Error {
         Error(Box::new(ErrorKind::Encryption(source)))
     }
 }
 
-impl From<std::string::FromUtf8Error> for Error {
-    fn from(source: std::string::FromUtf8Error) -> Error {
+impl From<alloc::string::FromUtf8Error> for Error {
+    fn from(source: alloc::string::FromUtf8Error) -> Error {
         Error(Box::new(ErrorKind::UTF8(source)))
     }
 }
@@ -0,0 +1,238 @@
+//! Gzip (RFC 1952) and raw DEFLATE (RFC 1951) support for compressed modules and compact tokens,
+//! built on `flate2`. Gzip streams are decompressed as they're read, and never to more than a
+//! given number of bytes, so a small input can't expand into an arbitrarily large module
+
+use crate::{errors, errors::ErrorKind, Result};
+use flate2::{bufread::GzDecoder, read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+    mem,
+};
+
+/// A reader over the decompressed contents of a gzip stream, including one made of several
+/// concatenated members. Zero bytes after the last member are taken as padding, as written by
+/// tools that pad to a block size. Reading fails with a `DecompressedTooLarge` error once more
+/// than the given number of bytes have been decompressed, and with a `Decompression` error if
+/// the stream is corrupt
+pub(crate) struct GzipReader<R> {
+    state: State<R>,
+    remaining: usize,
+    max_size: usize,
+}
+
+enum State<R> {
+    Member(GzDecoder<R>),
+    // After a member, where the next one, padding or the end of the stream can follow
+    Between(R),
+    Padding(R),
+    // After an error
+    Done,
+}
+
+impl<R: BufRead> GzipReader<R> {
+    pub(crate) fn new(reader: R, max_size: usize) -> GzipReader<R> {
+        GzipReader {
+            state: State::Member(GzDecoder::new(reader)),
+            remaining: max_size,
+            max_size,
+        }
+    }
+}
+
+impl<R: BufRead + Seek> GzipReader<R> {
+    /// Starts decompressing again from the given position of the underlying reader, with the
+    /// full size limit
+    pub(crate) fn restart(&mut self, pos: u64) -> Result<()> {
+        let mut reader = match mem::replace(&mut self.state, State::Done) {
+            State::Member(member) => member.into_inner(),
+            State::Between(reader) | State::Padding(reader) => reader,
+            State::Done => {
+                return Err(errors::new(ErrorKind::Decompression(
+                    "can't restart a gzip stream after an error".to_string(),
+                )))
+            }
+        };
+        reader.seek(SeekFrom::Start(pos))?;
+        *self = GzipReader::new(reader, self.max_size);
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for GzipReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            // Errors are final, so the reader is left done if one is returned
+            match mem::replace(&mut self.state, State::Done) {
+                State::Member(mut member) => {
+                    // Reading one byte past the limit is enough to know it's exceeded
+                    let len = buf.len().min(self.remaining.saturating_add(1));
+                    let n = member.read(&mut buf[..len]).map_err(corrupt)?;
+                    if n == 0 {
+                        self.state = State::Between(member.into_inner());
+                    } else if n > self.remaining {
+                        return Err(to_io(ErrorKind::DecompressedTooLarge(self.max_size)));
+                    } else {
+                        self.remaining -= n;
+                        self.state = State::Member(member);
+                        return Ok(n);
+                    }
+                }
+                State::Between(mut reader) => match reader.fill_buf()?.first() {
+                    None => {
+                        self.state = State::Between(reader);
+                        return Ok(0);
+                    }
+                    Some(0) => self.state = State::Padding(reader),
+                    Some(_) => self.state = State::Member(GzDecoder::new(reader)),
+                },
+                State::Padding(mut reader) => {
+                    let padding = reader.fill_buf()?;
+                    if padding.is_empty() {
+                        self.state = State::Padding(reader);
+                        return Ok(0);
+                    }
+                    let zeros = padding.iter().take_while(|b| **b == 0).count();
+                    if zeros < padding.len() {
+                        return Err(to_io(ErrorKind::Decompression(
+                            "unexpected data after the padding at the end of the gzip stream"
+                                .to_string(),
+                        )));
+                    }
+                    reader.consume(zeros);
+                    self.state = State::Padding(reader);
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Decompresses a raw DEFLATE stream, without any gzip framing, to at most `max_size` bytes
+///
+/// # Errors
+/// Returns a `Decompression` error if the stream is corrupt, or a `DecompressedTooLarge` error
+/// if it holds more than `max_size` bytes
+pub(crate) fn decompress_raw(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    DeflateDecoder::new(data)
+        .take(max_size as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| errors::new(ErrorKind::Decompression(e.to_string())))?;
+    if output.len() > max_size {
+        return Err(errors::new(ErrorKind::DecompressedTooLarge(max_size)));
+    }
+    Ok(output)
+}
+
+/// Compresses data into a raw DEFLATE stream, without any gzip framing
+pub(crate) fn compress_raw(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .expect("writing to a Vec can't fail")
+}
+
+// flate2 reports a corrupt stream, such as one with a bad header or checksum, as invalid input
+// and a truncated one as an unexpected end of file. Anything else comes from the reader itself
+fn corrupt(err: io::Error) -> io::Error {
+    match err.kind() {
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            to_io(ErrorKind::Decompression(err.to_string()))
+        }
+        _ => err,
+    }
+}
+
+// Carries an error of the crate through `Read`, which the conversion back from `io::Error`
+// unwraps again
+fn to_io(kind: ErrorKind) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, errors::new(kind))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_all(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        GzipReader::new(data, max_size).read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    fn assert_decompression_error(result: Result<Vec<u8>>) {
+        match result {
+            Err(e) => match e.kind() {
+                ErrorKind::Decompression(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::Decompression"),
+            },
+            Ok(_) => panic!("decompressed an invalid stream"),
+        }
+    }
+
+    #[test]
+    fn members_and_padding() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let mut gz = gzip(&data[..60_000]);
+        gz.extend(gzip(&data[60_000..]));
+        assert_eq!(read_all(&gz, data.len()).unwrap(), data);
+
+        gz.extend([0; 512].iter());
+        assert_eq!(read_all(&gz, data.len()).unwrap(), data);
+
+        gz.push(1);
+        assert_decompression_error(read_all(&gz, data.len()));
+    }
+
+    #[test]
+    fn size_limit() {
+        // Highly compressible, so the compressed stream is far smaller than the limit it trips
+        let data = vec![0; 1024 * 1024];
+        let gz = gzip(&data);
+        assert!(gz.len() < 4096);
+        assert_eq!(read_all(&gz, data.len()).unwrap(), data);
+        match read_all(&gz, data.len() - 1) {
+            Err(e) => match e.kind() {
+                ErrorKind::DecompressedTooLarge(max) => assert_eq!(*max, data.len() - 1),
+                _ => panic!("failed to assert errors::ErrorKind::DecompressedTooLarge"),
+            },
+            Ok(_) => panic!("decompressed past the limit"),
+        }
+    }
+
+    #[test]
+    fn corrupt_streams() {
+        let gz = gzip(b"some module bytes");
+        let mut bad_crc = gz.clone();
+        let crc = bad_crc.len() - 8;
+        bad_crc[crc] ^= 0xff;
+        for bad in &[&b"not gzip at all"[..], &gz[..gz.len() - 4], &bad_crc] {
+            assert_decompression_error(read_all(bad, 1024));
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let data = b"header.claims.signature header.claims.signature".to_vec();
+        let compressed = compress_raw(&data);
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress_raw(&compressed, data.len()).unwrap(), data);
+        assert!(matches!(
+            decompress_raw(&compressed, data.len() - 1)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::DecompressedTooLarge(_)
+        ));
+        assert_decompression_error(decompress_raw(&[0xff; 16], data.len()));
+    }
+}
@@ -1,16 +1,27 @@
 //! Claims encoding, decoding, and validation for JSON Web Tokens (JWT)
 
-use crate::{errors, errors::ErrorKind, Result};
+pub use crate::core::{Actor, CapConstraint, CapGrant, Claims, HashAlgorithm, HashScope, Token};
+use crate::{caps, caps::CapabilityId, core::from_jwt_segment, errors, errors::ErrorKind, Result};
+use data_encoding::{BASE32_NOPAD, HEXUPPER};
 use nkeys::KeyPair;
+use ring::digest::{digest, SHA256, SHA512};
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::{from_str, to_string};
+use serde_json::{json, to_string};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{Read, Write},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 const HEADER_TYPE: &str = "jwt";
 const HEADER_ALGORITHM: &str = "Ed25519";
+const SECS_PER_DAY: u64 = 86400;
+#[cfg(feature = "gzip")]
+const COMPACT_SEPARATOR: u8 = 0;
+
+/// Reserved tag prefix for the interface contracts an actor implements, e.g.
+/// `contract:wasmcloud:httpserver`
+pub const CONTRACT_TAG_PREFIX: &str = "contract:";
 
 // Current internal revision number that will go into embedded claims
 pub(crate) const WASCAP_INTERNAL_REVISION: u32 = 2;
@@ -18,11 +29,163 @@ pub(crate) const WASCAP_INTERNAL_REVISION: u32 = 2;
 // Minimum revision number at which we verify module hashes
 pub(crate) const MIN_WASCAP_INTERNAL_REVISION: u32 = 2;
 
-/// A structure containing a JWT and its associated decoded claims
+/// A flattened view of an actor's claims, containing everything needed to display them without
+/// unwrapping the optional metadata
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
-pub struct Token<T> {
-    pub jwt: String,
-    pub claims: Claims<T>,
+pub struct ActorInspection {
+    pub name: String,
+    pub issuer: String,
+    pub subject: String,
+    pub caps: Vec<String>,
+    pub tags: Vec<String>,
+    pub version: Option<String>,
+    pub revision: Option<i32>,
+    pub call_alias: Option<String>,
+    pub expires: Option<u64>,
+    pub not_before: Option<u64>,
+    pub hash: String,
+    pub provider: bool,
+}
+
+impl<T> Token<T> {
+    /// A stable fingerprint of the token, the upper-case hex SHA-256 digest of the raw JWT. This
+    /// can be used to key caches of verification results
+    pub fn fingerprint(&self) -> String {
+        HEXUPPER.encode(digest(&SHA256, self.jwt.as_bytes()).as_ref())
+    }
+
+    /// The key ID (`kid`) recorded in the JWT header when the token was encoded with
+    /// `Claims::encode_with_key_id`, or `None` if the header doesn't have one
+    pub fn key_id(&self) -> Option<String> {
+        let header = self.jwt.split('.').next()?;
+        from_jwt_segment::<_, ClaimsHeader>(header).ok()?.key_id
+    }
+}
+
+impl Token<Actor> {
+    /// Returns the capabilities granted to the actor that are still active at the given time
+    /// (in _seconds since the epoch_), leaving out any whose constraints have lapsed. This does
+    /// not take the expiration of the token itself into account
+    pub fn active_capabilities(&self, now: u64) -> Vec<String> {
+        let meta = match self.claims.metadata {
+            Some(ref meta) => meta,
+            None => return Vec::new(),
+        };
+        meta.caps
+            .iter()
+            .flatten()
+            .filter(|cap| {
+                meta.cap_constraints
+                    .as_ref()
+                    .and_then(|constraints| constraints.get(*cap))
+                    .and_then(|constraint| constraint.expires)
+                    .map_or(true, |exp| exp > now)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Produces a JSON representation of the token intended for inspection tooling, containing
+    /// the decoded claims along with human-friendly validity information. This is not the wire
+    /// format of the JWT, which remains available in the `jwt` field
+    pub fn to_inspection_json(&self) -> serde_json::Value {
+        let validation = validate_token::<Actor>(&self.jwt).ok();
+        let meta = self.claims.metadata.clone().unwrap_or_default();
+        json!({
+            "name": self.claims.name(),
+            "module": self.claims.subject,
+            "issuer": self.claims.issuer,
+            "caps": meta.caps.unwrap_or_default(),
+            "tags": meta.tags.unwrap_or_default(),
+            "version": meta.ver,
+            "revision": meta.rev,
+            "call_alias": meta.call_alias,
+            "expires_human": validation.as_ref().map(|v| v.expires_human.clone()),
+            "not_before_human": validation.as_ref().map(|v| v.not_before_human.clone()),
+            "valid": validation.is_some_and(|v| {
+                v.signature_valid && !v.expired && !v.cannot_use_yet
+            }),
+            "hash": meta.module_hash,
+        })
+    }
+
+    /// Encodes the token as a compact string for channels with little room, such as QR codes
+    /// shown to air-gapped machines. The JSON header and claims and the raw signature of the JWT
+    /// are DEFLATE-compressed together and the result is base64url-encoded. Only the JWT is
+    /// kept, so `hash_verified` doesn't survive the round trip
+    #[cfg(feature = "gzip")]
+    pub fn to_compact(&self) -> String {
+        let mut raw = Vec::with_capacity(self.jwt.len());
+        for (i, segment) in self.jwt.split('.').enumerate() {
+            if i > 0 {
+                raw.push(COMPACT_SEPARATOR);
+            }
+            match base64::decode_config(segment, base64::URL_SAFE_NO_PAD) {
+                Ok(bytes) => raw.extend(bytes),
+                // A segment that isn't base64 can't be part of a valid JWT, so it's kept as is
+                // and `from_compact` rejects the result
+                Err(_) => raw.extend(segment.as_bytes()),
+            }
+        }
+        base64::encode_config(crate::gzip::compress_raw(&raw), base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Decodes a token encoded with `to_compact`, verifying that the JWT is signed by its issuer.
+    /// The token's dates aren't checked, and neither is any module hash, so `hash_verified` is
+    /// `false`. The string is decompressed to no more than `DEFAULT_MAX_JWT_SECTION_SIZE` bytes,
+    /// the largest JWT the extraction functions accept from a module
+    ///
+    /// # Errors
+    /// Returns a `Decode` or `Decompression` error if the string wasn't produced by `to_compact`,
+    /// a `DecompressedTooLarge` error if it decompresses to more than the limit, or a
+    /// `SignatureInvalid` error if the JWT's signature doesn't verify
+    #[cfg(feature = "gzip")]
+    pub fn from_compact(s: &str) -> Result<Token<Actor>> {
+        let raw = crate::gzip::decompress_raw(
+            &base64::decode_config(s.trim(), base64::URL_SAFE_NO_PAD)?,
+            crate::wasm::DEFAULT_MAX_JWT_SECTION_SIZE,
+        )?;
+        // JSON can't contain an unescaped NUL, and the signature is the last segment, so the
+        // separators are unambiguous
+        let mut segments = raw.splitn(3, |b| *b == COMPACT_SEPARATOR);
+        let jwt = [segments.next(), segments.next(), segments.next()]
+            .iter()
+            .map(|segment| {
+                segment
+                    .map(|s| base64::encode_config(s, base64::URL_SAFE_NO_PAD))
+                    .ok_or_else(|| errors::new(ErrorKind::Token("invalid token format".into())))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(".");
+        if !validate_token::<Actor>(&jwt)?.signature_valid {
+            return Err(errors::new(ErrorKind::SignatureInvalid));
+        }
+        Ok(Token {
+            claims: Claims::decode_unverified(&jwt)?,
+            jwt,
+            hash_verified: false,
+        })
+    }
+
+    /// Flattens the claims in this token into an `ActorInspection`. Metadata that isn't present
+    /// in the claims shows up as empty lists and strings
+    pub fn inspect(&self) -> ActorInspection {
+        let meta = self.claims.metadata.clone().unwrap_or_default();
+        ActorInspection {
+            name: self.claims.name(),
+            issuer: self.claims.issuer.clone(),
+            subject: self.claims.subject.clone(),
+            caps: meta.caps.unwrap_or_default(),
+            tags: meta.tags.unwrap_or_default(),
+            version: meta.ver,
+            revision: meta.rev,
+            call_alias: meta.call_alias,
+            expires: self.claims.expires,
+            not_before: self.claims.not_before,
+            hash: meta.module_hash,
+            provider: meta.provider,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,52 +195,37 @@ struct ClaimsHeader {
 
     #[serde(rename = "alg")]
     algorithm: String,
-}
 
-fn default_as_false() -> bool {
-    false
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
 }
 
 pub trait WascapEntity: Clone {
-    fn name(&self) -> String;
-}
-
-/// The metadata that corresponds to an actor module
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
-pub struct Actor {
-    /// A descriptive name for this actor, should not include version information or public key
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub name: Option<String>,
+    /// Whether `ClaimsBuilder` records the current wascap revision in claims of this type. The
+    /// revision decides which rules an embedded module hash is checked by, so it's only recorded
+    /// for the types that can be embedded in a module
+    const RECORDS_REVISION: bool = false;
 
-    /// A hash of the module's bytes as they exist without the embedded signature. This is stored so wascap
-    /// can determine if a WebAssembly module's bytecode has been altered after it was signed
-    #[serde(rename = "hash")]
-    pub module_hash: String,
-
-    /// List of arbitrary string tags associated with the claims
-    #[serde(rename = "tags", skip_serializing_if = "Option::is_none")]
-    pub tags: Option<Vec<String>>,
-
-    /// List of capability attestations. Can be standard wascap capabilities or custom namespace capabilities
-    #[serde(rename = "caps", skip_serializing_if = "Option::is_none")]
-    pub caps: Option<Vec<String>>,
+    fn name(&self) -> String;
 
-    /// Indicates a monotonically increasing revision number.  Optional.
-    #[serde(rename = "rev", skip_serializing_if = "Option::is_none")]
-    pub rev: Option<i32>,
+    /// The hash of the module these claims are embedded in, for entities that can be embedded.
+    /// Entities that don't record a module hash can't have their embedded claims verified
+    fn module_hash(&self) -> Option<&str> {
+        None
+    }
 
-    /// Indicates a human-friendly version string
-    #[serde(rename = "ver", skip_serializing_if = "Option::is_none")]
-    pub ver: Option<String>,
+    /// Records the hash of the module these claims are being embedded in
+    fn set_module_hash(&mut self, _hash: String) {}
 
-    /// An optional, code-friendly alias that can be used instead of a public key or
-    /// OCI reference for invocations
-    #[serde(rename = "call_alias", skip_serializing_if = "Option::is_none")]
-    pub call_alias: Option<String>,
+    /// The algorithm used to compute the module hash
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::default()
+    }
 
-    /// Indicates whether this module is a capability provider
-    #[serde(rename = "prov", default = "default_as_false")]
-    pub provider: bool,
+    /// The parts of the module covered by the module hash
+    fn hash_scope(&self) -> HashScope {
+        HashScope::default()
+    }
 }
 
 /// The claims metadata corresponding to a capability provider
@@ -97,6 +245,17 @@ pub struct CapabilityProvider {
     pub ver: Option<String>,
     /// The file hashes that correspond to the achitecture-OS target triples for this provider.
     pub target_hashes: HashMap<String, String>,
+    /// A hash of the module's bytes as they exist without the embedded signature, for
+    /// providers that are distributed as WebAssembly modules
+    #[serde(rename = "hash", skip_serializing_if = "Option::is_none")]
+    pub module_hash: Option<String>,
+    /// The algorithm used to compute `module_hash`
+    #[serde(
+        rename = "hash_alg",
+        default,
+        skip_serializing_if = "HashAlgorithm::is_default"
+    )]
+    pub hash_algorithm: HashAlgorithm,
 }
 
 /// The claims metadata corresponding to an account
@@ -107,6 +266,14 @@ pub struct Account {
     /// A list of valid public keys that may appear as an `issuer` on
     /// actors signed by one of this account's multiple seed keys
     pub valid_signers: Option<Vec<String>>,
+    /// The capability contracts actors signed by this account may be granted. If `None` any
+    /// capability may be granted
+    #[serde(rename = "caps", default, skip_serializing_if = "Option::is_none")]
+    pub valid_capabilities: Option<Vec<String>>,
+    /// Named resource limits placed on the account by its operator, e.g. a maximum number of
+    /// actors. wascap records these but doesn't interpret them
+    #[serde(rename = "limits", default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<BTreeMap<String, u64>>,
 }
 
 /// The claims metadata corresponding to an operator
@@ -140,46 +307,6 @@ pub struct Invocation {
     pub invocation_hash: String,
 }
 
-/// Represents a set of [RFC 7519](https://tools.ietf.org/html/rfc7519) compliant JSON Web Token
-/// claims.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
-pub struct Claims<T> {
-    /// All timestamps in JWTs are stored in _seconds since the epoch_ format
-    /// as described as `NumericDate` in the RFC. Corresponds to the `exp` field in a JWT.
-    #[serde(rename = "exp", skip_serializing_if = "Option::is_none")]
-    pub expires: Option<u64>,
-
-    /// Corresponds to the `jti` field in a JWT.
-    #[serde(rename = "jti")]
-    pub id: String,
-
-    /// The `iat` field, stored in _seconds since the epoch_
-    #[serde(rename = "iat")]
-    pub issued_at: u64,
-
-    /// Issuer of the token, by convention usually the public key of the _account_ that
-    /// signed the token
-    #[serde(rename = "iss")]
-    pub issuer: String,
-
-    /// Subject of the token, usually the public key of the _module_ corresponding to the WebAssembly file
-    /// being signed
-    #[serde(rename = "sub")]
-    pub subject: String,
-
-    /// The `nbf` JWT field, indicates the time when the token becomes valid. If `None` token is valid immediately
-    #[serde(rename = "nbf", skip_serializing_if = "Option::is_none")]
-    pub not_before: Option<u64>,
-
-    /// Custom jwt claims in the `wascap` namespace
-    #[serde(rename = "wascap", skip_serializing_if = "Option::is_none")]
-    pub metadata: Option<T>,
-
-    /// Internal revision number used to aid in parsing and validating claims
-    #[serde(rename = "wascap_revision", skip_serializing_if = "Option::is_none")]
-    pub(crate) wascap_revision: Option<u32>,
-}
-
 /// The result of the validation process perform on a JWT
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct TokenValidation {
@@ -199,34 +326,160 @@ pub struct TokenValidation {
     pub signature_valid: bool,
 }
 
+/// Additional, opt-in checks made on claims beyond what `extract_claims` and `validate_token`
+/// do by default. The default options don't make any additional checks
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationOptions {
+    /// When set, claims issued more than `issued_at_skew` ahead of the current time are rejected
+    pub reject_future_issued_at: bool,
+    /// How far ahead of the current time the `issued_at` time of the claims may be, to allow for
+    /// clock differences between the signing machine and this one
+    pub issued_at_skew: Duration,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            reject_future_issued_at: false,
+            issued_at_skew: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ValidationOptions {
+    /// Makes the enabled checks on the claims against the OS system clock
+    ///
+    /// # Errors
+    /// Returns an `IssuedInFuture` error if the claims were issued too far in the future
+    pub fn check<T>(&self, claims: &Claims<T>) -> Result<()> {
+        self.check_at(claims, since_the_epoch()?.as_secs())
+    }
+
+    /// Makes the enabled checks on the claims at the given time, in _seconds since the epoch_
+    pub fn check_at<T>(&self, claims: &Claims<T>, now: u64) -> Result<()> {
+        if self.reject_future_issued_at
+            && claims.issued_at > now.saturating_add(self.issued_at_skew.as_secs())
+        {
+            return Err(errors::new(ErrorKind::IssuedInFuture(claims.issued_at)));
+        }
+        Ok(())
+    }
+}
+
+/// Something that can sign claims as an issuer. This is implemented for nkeys key pairs, and can
+/// be implemented for keys that are held elsewhere, such as in an HSM or a cloud KMS, so that
+/// claims can be signed without the seed ever being in process
+pub trait WascapSigner {
+    /// The encoded public key of the issuer, e.g. an account key starting with `A`
+    fn public_key(&self) -> String;
+
+    /// Produces an Ed25519 signature of the given input
+    fn sign(&self, input: &[u8]) -> Result<Vec<u8>>;
+}
+
+impl WascapSigner for KeyPair {
+    fn public_key(&self) -> String {
+        KeyPair::public_key(self)
+    }
+
+    fn sign(&self, input: &[u8]) -> Result<Vec<u8>> {
+        Ok(KeyPair::sign(self, input)?)
+    }
+}
+
+impl<S: WascapSigner + ?Sized> WascapSigner for &S {
+    fn public_key(&self) -> String {
+        (**self).public_key()
+    }
+
+    fn sign(&self, input: &[u8]) -> Result<Vec<u8>> {
+        (**self).sign(input)
+    }
+}
+
 impl<T> Claims<T>
 where
     T: Serialize + DeserializeOwned + WascapEntity,
 {
-    pub fn encode(&self, kp: &KeyPair) -> Result<String> {
+    pub fn encode(&self, kp: &dyn WascapSigner) -> Result<String> {
+        self.encode_jwt(kp, None)
+    }
+
+    /// Encodes and signs the claims in the same way as `encode`, additionally recording a key ID
+    /// (`kid`) in the JWT header. Verifiers that trust several signing keys can read it back with
+    /// `Token::key_id` to pick the matching key without trying each one
+    pub fn encode_with_key_id(&self, kp: &dyn WascapSigner, key_id: &str) -> Result<String> {
+        self.encode_jwt(kp, Some(key_id.to_string()))
+    }
+
+    fn encode_jwt(&self, kp: &dyn WascapSigner, key_id: Option<String>) -> Result<String> {
+        let head_and_claims = self.signing_input(key_id)?;
+        let sig = kp.sign(head_and_claims.as_bytes())?;
+        let sig64 = base64::encode_config(&sig, base64::URL_SAFE_NO_PAD);
+        Ok(format!("{}.{}", head_and_claims, sig64))
+    }
+
+    /// The encoded header and claims of the JWT, separated by a `.`, whose bytes are what the
+    /// signature covers
+    pub(crate) fn signing_input(&self, key_id: Option<String>) -> Result<String> {
         let header = ClaimsHeader {
             header_type: HEADER_TYPE.to_string(),
             algorithm: HEADER_ALGORITHM.to_string(),
+            key_id,
         };
         let jheader = to_jwt_segment(&header)?;
         let jclaims = to_jwt_segment(self)?;
+        Ok(format!("{}.{}", jheader, jclaims))
+    }
 
-        let head_and_claims = format!("{}.{}", jheader, jclaims);
-        let sig = kp.sign(head_and_claims.as_bytes())?;
-        let sig64 = base64::encode_config(&sig, base64::URL_SAFE_NO_PAD);
-        Ok(format!("{}.{}", head_and_claims, sig64))
+    /// Encodes and signs the claims in the same way as `encode`, writing the JWT to the given
+    /// writer
+    pub fn encode_to_writer<W: Write>(&self, kp: &dyn WascapSigner, mut w: W) -> Result<()> {
+        w.write_all(self.encode(kp)?.as_bytes())?;
+        Ok(())
     }
 
+    /// Decodes a JWT read from the given reader in the same way as `decode`. The reader must
+    /// contain a single token, optionally surrounded by whitespace such as a trailing newline
+    pub fn decode_from_reader<R: Read>(mut r: R) -> Result<Claims<T>> {
+        let mut input = String::new();
+        r.read_to_string(&mut input)?;
+        Self::decode(input.trim())
+    }
+
+    /// Decodes the claims in a JWT, only returning them if they are usable: the signature must be
+    /// present and valid for the issuer, and the token must be neither expired nor not yet valid
+    /// according to the OS system clock. Use `decode_unverified` to read claims regardless, e.g.
+    /// to show what an expired token contained
+    ///
+    /// # Errors
+    /// Returns a `SignatureMissing` or `SignatureInvalid` error for unsigned or forged tokens, a
+    /// `TokenExpired` error containing the expiration time for expired tokens, and a
+    /// `TokenTooEarly` error for tokens that can't be used yet
     pub fn decode(input: &str) -> Result<Claims<T>> {
+        let claims = Self::decode_unverified(input)?;
         let segments: Vec<&str> = input.split('.').collect();
-        if segments.len() != 3 {
-            return Err(errors::new(errors::ErrorKind::Token(
-                "invalid token format".into(),
-            )));
+        let header: ClaimsHeader = from_jwt_segment(segments[0])?;
+        validate_header(&header)?;
+        validate_issuer(&claims.issuer)?;
+
+        if segments[2].is_empty() {
+            return Err(errors::new(ErrorKind::SignatureMissing));
+        }
+        let sig = base64::decode_config(segments[2], base64::URL_SAFE_NO_PAD)
+            .map_err(|_| errors::new(ErrorKind::SignatureInvalid))?;
+        let kp = KeyPair::from_public_key(&claims.issuer)?;
+        let header_and_claims = format!("{}.{}", segments[0], segments[1]);
+        if kp.verify(header_and_claims.as_bytes(), &sig).is_err() {
+            return Err(errors::new(ErrorKind::SignatureInvalid));
         }
-        let claims: Claims<T> = from_jwt_segment(segments[1])?;
 
-        Ok(claims)
+        let now = since_the_epoch()?.as_secs();
+        match claims.expires {
+            Some(exp) if claims.expired_at(now) => Err(errors::new(ErrorKind::TokenExpired(exp))),
+            _ if claims.cannot_use_yet_at(now) => Err(errors::new(ErrorKind::TokenTooEarly)),
+            _ => Ok(claims),
+        }
     }
 
     pub fn name(&self) -> String {
@@ -236,22 +489,80 @@ where
     }
 }
 
+impl<T> Claims<T> {
+    /// Indicates whether these claims have expired, as determined by the current OS system clock
+    pub fn expired(&self) -> bool {
+        self.expired_at(epoch_secs())
+    }
+
+    /// Indicates whether these claims are _not yet_ valid, as determined by the current OS
+    /// system clock
+    pub fn cannot_use_yet(&self) -> bool {
+        self.cannot_use_yet_at(epoch_secs())
+    }
+
+    /// The time left before these claims expire, as determined by the current OS system clock.
+    /// Returns `None` for claims that never expire and `Duration::ZERO` for claims whose
+    /// expiration has been reached
+    pub fn time_until_expiry(&self) -> Option<Duration> {
+        self.time_until_expiry_at(epoch_secs())
+    }
+
+    /// Indicates whether these claims expire within the given duration, as determined by the
+    /// current OS system clock
+    pub fn expires_within(&self, d: Duration) -> bool {
+        self.expires_within_at(d, epoch_secs())
+    }
+}
+
 impl WascapEntity for Actor {
+    const RECORDS_REVISION: bool = true;
+
     fn name(&self) -> String {
         self.name
             .as_ref()
             .unwrap_or(&"Anonymous".to_string())
             .to_string()
     }
+
+    fn module_hash(&self) -> Option<&str> {
+        Some(&self.module_hash)
+    }
+
+    fn set_module_hash(&mut self, hash: String) {
+        self.module_hash = hash;
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    fn hash_scope(&self) -> HashScope {
+        self.hash_scope.clone()
+    }
 }
 
 impl WascapEntity for CapabilityProvider {
+    const RECORDS_REVISION: bool = true;
+
     fn name(&self) -> String {
         self.name
             .as_ref()
             .unwrap_or(&"Unnamed Provider".to_string())
             .to_string()
     }
+
+    fn module_hash(&self) -> Option<&str> {
+        self.module_hash.as_deref()
+    }
+
+    fn set_module_hash(&mut self, hash: String) {
+        self.module_hash = Some(hash);
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
 }
 
 impl WascapEntity for Account {
@@ -305,13 +616,10 @@ impl Claims<Account> {
         additional_keys: Vec<String>,
     ) -> Claims<Account> {
         Claims {
-            metadata: Some(Account {
-                name: Some(name),
-                valid_signers: Some(additional_keys),
-            }),
+            metadata: Some(Account::new(name, additional_keys)),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
@@ -351,17 +659,12 @@ impl Claims<CapabilityProvider> {
         expires: Option<u64>,
     ) -> Claims<CapabilityProvider> {
         Claims {
-            metadata: Some(CapabilityProvider {
-                name: Some(name),
-                capid,
-                rev,
-                ver,
-                target_hashes: hashes,
-                vendor,
-            }),
+            metadata: Some(CapabilityProvider::new(
+                name, capid, vendor, rev, ver, hashes,
+            )),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
@@ -395,7 +698,7 @@ impl Claims<Operator> {
             }),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
@@ -429,7 +732,7 @@ impl Claims<Cluster> {
             }),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
@@ -456,6 +759,8 @@ impl Claims<Actor> {
         )
     }
 
+    /// Creates a set of actor claims with the given validity dates. Most arguments here are
+    /// positional options that are easy to mix up, so prefer using `ClaimsBuilder::<Actor>`
     #[allow(clippy::too_many_arguments)]
     pub fn with_dates(
         name: String,
@@ -474,13 +779,78 @@ impl Claims<Actor> {
             metadata: Some(Actor::new(name, caps, tags, provider, rev, ver, call_alias)),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
             wascap_revision: Some(WASCAP_INTERNAL_REVISION),
         }
     }
+
+    /// Re-issues these claims with a new id, issued now, and expiring the given number of days
+    /// from now (or never). Everything else, including the metadata and module hash, is carried
+    /// over unchanged, so the renewed claims can be embedded in the same module. The revision is
+    /// also kept, since the module hash was computed by that revision. The claims must be renewed
+    /// by their original issuer
+    ///
+    /// # Errors
+    /// Returns an error if `kp` isn't the issuer of these claims, or a `ClockError` if the
+    /// system clock is set before the UNIX epoch
+    pub fn renew(&self, kp: &KeyPair, new_expires_in_days: Option<u64>) -> Result<Claims<Actor>> {
+        if kp.public_key() != self.issuer {
+            return Err(errors::new(ErrorKind::Token(
+                "claims can only be renewed by their issuer".to_string(),
+            )));
+        }
+        let now = since_the_epoch()?.as_secs();
+        Ok(Claims {
+            id: nuid::next(),
+            issued_at: now,
+            expires: new_expires_in_days.map(|days| now + days * SECS_PER_DAY),
+            ..self.clone()
+        })
+    }
+
+    /// Checks the invariants that claims produced by wascap always satisfy, to catch tokens
+    /// that have been edited by hand or produced by a broken tool. This doesn't check the
+    /// signature, so it can tell structurally broken claims apart from a signature failure
+    ///
+    /// # Errors
+    /// Returns an `InconsistentClaims` error if the claims expire before they're issued or
+    /// become valid, an `InvalidKeyType` error if the issuer isn't an account key, a
+    /// `SubjectNotModuleKey` error if the subject isn't a module key, a `MissingMetadata` error if
+    /// there is no metadata, and a `MalformedHash` error if the module hash isn't a hex digest of
+    /// the hash algorithm in the metadata
+    pub fn verify_self_consistency(&self) -> Result<()> {
+        if let Some(exp) = self.expires {
+            if self.issued_at > exp {
+                return Err(errors::new(ErrorKind::InconsistentClaims(format!(
+                    "issued at {}, after expiring at {}",
+                    self.issued_at, exp
+                ))));
+            }
+            if let Some(nbf) = self.not_before.filter(|nbf| *nbf > exp) {
+                return Err(errors::new(ErrorKind::InconsistentClaims(format!(
+                    "valid from {}, after expiring at {}",
+                    nbf, exp
+                ))));
+            }
+        }
+        if !is_key_of_type(&self.issuer, 'A') {
+            return Err(errors::new(ErrorKind::InvalidKeyType));
+        }
+        if !is_key_of_type(&self.subject, 'M') {
+            return Err(errors::new(ErrorKind::SubjectNotModuleKey(
+                self.subject.clone(),
+            )));
+        }
+        let meta = self
+            .metadata
+            .as_ref()
+            .ok_or_else(|| errors::new(ErrorKind::MissingMetadata))?;
+        decode_module_hash(&meta.module_hash, meta.hash_algorithm)?;
+        Ok(())
+    }
 }
 
 impl Claims<Invocation> {
@@ -511,7 +881,7 @@ impl Claims<Invocation> {
             }),
             expires,
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: epoch_secs(),
             issuer,
             subject,
             not_before,
@@ -520,9 +890,10 @@ impl Claims<Invocation> {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ClaimsBuilder<T> {
     claims: Claims<T>,
+    issued_at: Option<u64>,
 }
 
 impl<T> ClaimsBuilder<T>
@@ -548,13 +919,33 @@ where
 
     /// Indicates how long this claim set will remain valid
     pub fn expires_in(&mut self, d: Duration) -> &mut Self {
-        self.claims.expires = Some(d.as_secs() + since_the_epoch().as_secs());
+        self.claims.expires = Some(epoch_secs().saturating_add(d.as_secs()));
         self
     }
 
     /// Indicates how long until this claim set becomes valid
     pub fn valid_in(&mut self, d: Duration) -> &mut Self {
-        self.claims.not_before = Some(d.as_secs() + since_the_epoch().as_secs());
+        self.claims.not_before = Some(epoch_secs().saturating_add(d.as_secs()));
+        self
+    }
+
+    /// Sets the exact time at which this claim set expires, in _seconds since the epoch_
+    pub fn expires_at(&mut self, stamp: u64) -> &mut Self {
+        self.claims.expires = Some(stamp);
+        self
+    }
+
+    /// Sets the exact time at which this claim set becomes valid, in _seconds since the epoch_
+    pub fn not_before(&mut self, stamp: u64) -> &mut Self {
+        self.claims.not_before = Some(stamp);
+        self
+    }
+
+    /// Sets the time at which this claim set was issued, in _seconds since the epoch_, instead of
+    /// the time it's built. This allows reproducing a past signing or building identical claims in
+    /// tests
+    pub fn issued_at(&mut self, stamp: u64) -> &mut Self {
+        self.issued_at = Some(stamp);
         self
     }
 
@@ -564,125 +955,614 @@ where
         self
     }
 
+    // Produces a claims set from the builder in the same way as `build`, issued at the current
+    // time of the given clock unless an issue time was set
+    pub(crate) fn build_with_clock(&self, clock: &dyn Clock) -> Result<Claims<T>> {
+        let issued_at = match self.issued_at {
+            Some(stamp) => stamp,
+            None => since_the_epoch_with(clock)?.as_secs(),
+        };
+        Ok(Claims {
+            issued_at,
+            ..self.build()
+        })
+    }
+
     // Produce a claims set from the builder
     pub fn build(&self) -> Claims<T> {
         Claims {
             id: nuid::next(),
-            issued_at: since_the_epoch().as_secs(),
+            issued_at: self.issued_at.unwrap_or_else(epoch_secs),
+            wascap_revision: if T::RECORDS_REVISION {
+                Some(WASCAP_INTERNAL_REVISION)
+            } else {
+                self.claims.wascap_revision
+            },
             ..self.claims.clone()
         }
     }
 }
 
-/// Validates a signed JWT. This will check the signature, expiration time, and not-valid-before time
-pub fn validate_token<T>(input: &str) -> Result<TokenValidation>
-where
-    T: Serialize + DeserializeOwned + WascapEntity,
-{
-    let segments: Vec<&str> = input.split('.').collect();
-    let header_and_claims = format!("{}.{}", segments[0], segments[1]);
-    let sig = base64::decode_config(segments[2], base64::URL_SAFE_NO_PAD)?;
+impl ClaimsBuilder<Actor> {
+    fn actor(&mut self) -> &mut Actor {
+        self.claims.metadata.get_or_insert_with(Actor::default)
+    }
 
-    let header: ClaimsHeader = from_jwt_segment(segments[0])?;
-    validate_header(&header)?;
+    /// Sets the descriptive name of the actor
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        self.actor().name = Some(name.to_string());
+        self
+    }
 
-    let claims = Claims::<T>::decode(input)?;
-    validate_issuer(&claims.issuer)?;
-    validate_subject(&claims.subject)?;
+    /// Adds a capability attestation to the actor's claims
+    pub fn with_cap(&mut self, cap: &str) -> &mut Self {
+        self.actor()
+            .caps
+            .get_or_insert_with(Vec::new)
+            .push(cap.to_string());
+        self
+    }
 
-    let kp = KeyPair::from_public_key(&claims.issuer)?;
-    let sigverify = kp.verify(header_and_claims.as_bytes(), &sig);
+    /// Adds a capability attestation limited to the given target resource, e.g. a key-value
+    /// bucket, to the actor's claims. The capability is also listed in `caps` if it isn't already
+    pub fn with_cap_target(&mut self, cap: &str, target: &str) -> &mut Self {
+        let actor = self.actor();
+        let caps = actor.caps.get_or_insert_with(Vec::new);
+        if !caps.iter().any(|c| c == cap) {
+            caps.push(cap.to_string());
+        }
+        actor
+            .caps_detailed
+            .get_or_insert_with(Vec::new)
+            .push(CapGrant {
+                capability: cap.to_string(),
+                target: Some(target.to_string()),
+            });
+        self
+    }
 
-    let validation = TokenValidation {
-        signature_valid: sigverify.is_ok(),
-        expired: validate_expiration(claims.expires).is_err(),
-        expires_human: stamp_to_human(claims.expires).unwrap_or_else(|| "never".to_string()),
-        not_before_human: stamp_to_human(claims.not_before)
-            .unwrap_or_else(|| "immediately".to_string()),
-        cannot_use_yet: validate_notbefore(claims.not_before).is_err(),
-    };
+    /// Adds several capability attestations to the actor's claims, given as parsed capability
+    /// IDs rather than strings
+    pub fn with_capabilities(&mut self, caps: impl IntoIterator<Item = CapabilityId>) -> &mut Self {
+        self.actor()
+            .caps
+            .get_or_insert_with(Vec::new)
+            .extend(caps.into_iter().map(String::from));
+        self
+    }
 
-    Ok(validation)
-}
+    /// Adds a tag to the actor's claims
+    pub fn with_tag(&mut self, tag: &str) -> &mut Self {
+        self.actor()
+            .tags
+            .get_or_insert_with(Vec::new)
+            .push(tag.to_string());
+        self
+    }
 
-fn validate_notbefore(nb: Option<u64>) -> Result<()> {
-    if let Some(nbf) = nb {
-        let nbf_secs = Duration::from_secs(nbf);
-        if since_the_epoch() < nbf_secs {
-            Err(errors::new(ErrorKind::TokenTooEarly))
-        } else {
-            Ok(())
-        }
-    } else {
-        Ok(())
+    /// Indicates how many days from now this claim set will remain valid
+    pub fn expires_in_days(&mut self, days: u64) -> &mut Self {
+        self.expires_in(Duration::from_secs(days * SECS_PER_DAY))
     }
-}
 
-fn validate_expiration(exp: Option<u64>) -> Result<()> {
-    if let Some(exp) = exp {
-        let exp_secs = Duration::from_secs(exp);
-        if exp_secs < since_the_epoch() {
-            Err(errors::new(ErrorKind::ExpiredToken))
-        } else {
-            Ok(())
-        }
-    } else {
-        Ok(())
+    /// Indicates how many days from now until this claim set becomes valid
+    pub fn not_before_days(&mut self, days: u64) -> &mut Self {
+        self.valid_in(Duration::from_secs(days * SECS_PER_DAY))
     }
-}
 
-fn validate_issuer(iss: &str) -> Result<()> {
-    if iss.is_empty() {
-        Err(errors::new(ErrorKind::MissingIssuer))
-    } else {
-        Ok(())
+    /// Indicates whether the actor is a capability provider
+    pub fn provider(&mut self, provider: bool) -> &mut Self {
+        self.actor().provider = provider;
+        self
     }
-}
 
-fn validate_subject(sub: &str) -> Result<()> {
-    if sub.is_empty() {
-        Err(errors::new(ErrorKind::MissingSubject))
-    } else {
-        Ok(())
+    /// Sets the monotonically increasing revision number of the actor
+    pub fn rev(&mut self, rev: i32) -> &mut Self {
+        self.actor().rev = Some(rev);
+        self
     }
-}
 
-fn since_the_epoch() -> Duration {
-    let start = SystemTime::now();
-    start
-        .duration_since(UNIX_EPOCH)
-        .expect("A timey wimey problem has occurred!")
-}
+    /// Sets the human-friendly version string of the actor
+    pub fn ver(&mut self, ver: &str) -> &mut Self {
+        self.actor().ver = Some(ver.to_string());
+        self
+    }
 
-fn validate_header(h: &ClaimsHeader) -> Result<()> {
-    if h.algorithm != HEADER_ALGORITHM {
-        Err(errors::new(ErrorKind::InvalidAlgorithm))
-    } else if h.header_type != HEADER_TYPE {
-        Err(errors::new(ErrorKind::Token("Invalid header".to_string())))
-    } else {
-        Ok(())
+    /// Sets the call alias of the actor. The alias is normalized in the same way as
+    /// with `Actor::new`
+    pub fn call_alias(&mut self, alias: &str) -> &mut Self {
+        self.actor().call_alias = normalize_call_alias(Some(alias.to_string()));
+        self
+    }
+
+    /// Sets the OCI image reference the actor will be published under
+    pub fn oci_reference(&mut self, reference: &str) -> &mut Self {
+        self.actor().oci_reference = Some(reference.to_string());
+        self
     }
 }
 
-fn to_jwt_segment<T: Serialize>(input: &T) -> Result<String> {
-    let encoded = to_string(input)?;
-    Ok(base64::encode_config(
-        encoded.as_bytes(),
-        base64::URL_SAFE_NO_PAD,
-    ))
+/// A single difference between two sets of actor claims, as reported by `diff_claims`. For list
+/// fields such as `caps`, each added or removed entry is a separate change with only `new` or
+/// only `old` set
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct ClaimChange {
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
 }
 
-fn from_jwt_segment<B: AsRef<str>, T: DeserializeOwned>(encoded: B) -> Result<T> {
-    let decoded = base64::decode_config(encoded.as_ref(), base64::URL_SAFE_NO_PAD)?;
-    let s = String::from_utf8(decoded)?;
+/// Lists the differences between two sets of actor claims: capabilities and tags added or
+/// removed, and changes to the version, revision, expiration time and module hash
+pub fn diff_claims(a: &Claims<Actor>, b: &Claims<Actor>) -> Vec<ClaimChange> {
+    let old = a.metadata.clone().unwrap_or_default();
+    let new = b.metadata.clone().unwrap_or_default();
+    let mut changes = Vec::new();
+
+    let mut diff_list = |field: &str, old: &Option<Vec<String>>, new: &Option<Vec<String>>| {
+        let old = old.as_deref().unwrap_or_default();
+        let new = new.as_deref().unwrap_or_default();
+        for removed in old.iter().filter(|item| !new.contains(item)) {
+            changes.push(ClaimChange {
+                field: field.to_string(),
+                old: Some(removed.clone()),
+                new: None,
+            });
+        }
+        for added in new.iter().filter(|item| !old.contains(item)) {
+            changes.push(ClaimChange {
+                field: field.to_string(),
+                old: None,
+                new: Some(added.clone()),
+            });
+        }
+    };
+    diff_list("caps", &old.caps, &new.caps);
+    diff_list("tags", &old.tags, &new.tags);
+
+    let values = [
+        ("version", old.ver, new.ver),
+        (
+            "revision",
+            old.rev.map(|r| r.to_string()),
+            new.rev.map(|r| r.to_string()),
+        ),
+        (
+            "expires",
+            a.expires.map(|e| e.to_string()),
+            b.expires.map(|e| e.to_string()),
+        ),
+        ("hash", Some(old.module_hash), Some(new.module_hash)),
+    ];
+    for (field, old, new) in values {
+        if old != new {
+            changes.push(ClaimChange {
+                field: field.to_string(),
+                old,
+                new,
+            });
+        }
+    }
+    changes
+}
 
-    Ok(from_str(&s)?)
+/// A list of revoked module (subject) and account (issuer) keys. Claims signed by or for any
+/// key on the list should no longer be trusted. The list serializes to JSON so that it can be
+/// distributed to the hosts that check it
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct RevocationList {
+    #[serde(default)]
+    revoked_subjects: HashSet<String>,
+    #[serde(default)]
+    revoked_issuers: HashSet<String>,
 }
 
-fn stamp_to_human(stamp: Option<u64>) -> Option<String> {
-    stamp.map(|s| {
-        let now = since_the_epoch().as_secs() as i64;
-        let diff_sec = (now - (s as i64)).abs();
+impl RevocationList {
+    pub fn new() -> RevocationList {
+        RevocationList::default()
+    }
+
+    /// Revokes all claims issued for the given module key
+    pub fn revoke_subject(&mut self, subject: &str) -> &mut Self {
+        self.revoked_subjects.insert(subject.to_string());
+        self
+    }
+
+    /// Revokes all claims signed by the given account key
+    pub fn revoke_issuer(&mut self, issuer: &str) -> &mut Self {
+        self.revoked_issuers.insert(issuer.to_string());
+        self
+    }
+
+    /// Indicates whether the subject or the issuer of the claims has been revoked
+    pub fn is_revoked(&self, claims: &Claims<Actor>) -> bool {
+        self.revoked_subjects.contains(&claims.subject)
+            || self.revoked_issuers.contains(&claims.issuer)
+    }
+}
+
+/// A host-side policy on the capabilities actors may use, applied after their claims have been
+/// verified regardless of who signed them. Capabilities on the deny list are always refused.
+/// When an allow list is set, only the capabilities on it are permitted; otherwise anything not
+/// denied is. The policy serializes to JSON so that it can be distributed to the hosts that
+/// enforce it
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Default)]
+pub struct CapabilityPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allow: Option<HashSet<String>>,
+    #[serde(default)]
+    deny: HashSet<String>,
+}
+
+/// The outcome of evaluating a `CapabilityPolicy` against an actor's claims
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PolicyDecision {
+    /// The capabilities declared by the actor that the policy refuses, in the order they're
+    /// declared
+    pub denied: Vec<String>,
+}
+
+impl PolicyDecision {
+    /// Indicates whether the policy permits every capability the actor declares
+    pub fn is_allowed(&self) -> bool {
+        self.denied.is_empty()
+    }
+}
+
+impl CapabilityPolicy {
+    /// Creates a policy that permits every capability
+    pub fn new() -> CapabilityPolicy {
+        CapabilityPolicy::default()
+    }
+
+    /// Adds a capability to the allow list, which is created by the first call. Once there is an
+    /// allow list, capabilities that aren't on it are refused
+    pub fn allow(&mut self, cap: &str) -> &mut Self {
+        self.allow
+            .get_or_insert_with(HashSet::new)
+            .insert(cap.to_string());
+        self
+    }
+
+    /// Adds a capability to the deny list, refusing it even if it's also allowed
+    pub fn deny(&mut self, cap: &str) -> &mut Self {
+        self.deny.insert(cap.to_string());
+        self
+    }
+
+    /// Evaluates the policy against the capabilities declared in an actor's claims. Both the
+    /// declared capabilities and those on the policy's lists are normalized in the same way as
+    /// `unsatisfied_capabilities`, so bundle aliases and display names match their capability IDs
+    pub fn evaluate(&self, token: &Token<Actor>) -> PolicyDecision {
+        let normalized = |list: &HashSet<String>| {
+            normalize_capabilities(&list.iter().cloned().collect::<Vec<_>>())
+        };
+        let allow = self.allow.as_ref().map(normalized);
+        let deny = normalized(&self.deny);
+        let denied = normalize_capabilities(&declared_capabilities(token))
+            .into_iter()
+            .filter(|cap| {
+                deny.contains(cap) || allow.as_ref().is_some_and(|allow| !allow.contains(cap))
+            })
+            .collect();
+        PolicyDecision { denied }
+    }
+}
+
+/// Checks that the OCI reference recorded in an actor's claims matches the reference it was
+/// retrieved from. Claims that don't record a reference were not signed for one, and always
+/// pass this check
+pub fn verify_oci_reference(token: &Token<Actor>, expected: &str) -> bool {
+    token
+        .claims
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.oci_reference.as_deref())
+        .map_or(true, |reference| reference == expected)
+}
+
+/// Returns the capabilities an actor's claims declare that aren't among those offered by a host,
+/// e.g. to check whether the actor can be scheduled there. Both lists are normalized first:
+/// bundle aliases such as `standard-io` are expanded and display names of well-known
+/// capabilities (e.g. `Key-Value Store`) are replaced with their capability IDs
+pub fn unsatisfied_capabilities(token: &Token<Actor>, host_caps: &[String]) -> Vec<String> {
+    let host_caps = normalize_capabilities(host_caps);
+    normalize_capabilities(&declared_capabilities(token))
+        .into_iter()
+        .filter(|cap| !host_caps.contains(cap))
+        .collect()
+}
+
+/// Returns the capabilities offered by a host that an actor's claims never declare, normalized
+/// in the same way as `unsatisfied_capabilities`
+pub fn excess_host_capabilities(token: &Token<Actor>, host_caps: &[String]) -> Vec<String> {
+    let declared = normalize_capabilities(&declared_capabilities(token));
+    normalize_capabilities(host_caps)
+        .into_iter()
+        .filter(|cap| !declared.contains(cap))
+        .collect()
+}
+
+/// Returns the interface contracts an actor advertises through tags with the reserved
+/// `contract:` prefix, with the prefix removed, in the order they're tagged
+pub fn interface_tags(token: &Token<Actor>) -> Vec<String> {
+    actor_tags(token)
+        .iter()
+        .filter_map(|tag| tag.strip_prefix(CONTRACT_TAG_PREFIX))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Indicates whether an actor's claims carry the given tag, compared exactly
+pub fn has_tag(token: &Token<Actor>, tag: &str) -> bool {
+    actor_tags(token).iter().any(|t| t == tag)
+}
+
+/// Finds the call aliases claimed by more than one actor, which would make routing invocations by
+/// alias ambiguous. Each conflicting alias is returned, in alphabetical order, with the subject
+/// keys of the actors claiming it in the order they appear. Several tokens for the same subject,
+/// such as different revisions of one actor, don't conflict with each other
+pub fn check_alias_conflicts(tokens: &[Token<Actor>]) -> Vec<(String, Vec<String>)> {
+    let mut claimants: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for token in tokens {
+        let alias = token
+            .claims
+            .metadata
+            .as_ref()
+            .and_then(|meta| meta.call_alias.as_deref());
+        if let Some(alias) = alias {
+            let subjects = claimants.entry(alias).or_default();
+            if !subjects.contains(&token.claims.subject) {
+                subjects.push(token.claims.subject.clone());
+            }
+        }
+    }
+    claimants
+        .into_iter()
+        .filter(|(_, subjects)| subjects.len() > 1)
+        .map(|(alias, subjects)| (alias.to_string(), subjects))
+        .collect()
+}
+
+/// Converts the account public key that issued an actor's claims into an Ed25519 JSON Web Key
+/// (`{"kty":"OKP","crv":"Ed25519","x":"..."}`), so the token can be verified by standard JWT
+/// libraries that don't understand nkeys
+///
+/// # Errors
+/// Returns an `InvalidKeyType` error if the issuer isn't an account key, or an error if it
+/// isn't a valid public key
+pub fn issuer_jwk(token: &Token<Actor>) -> Result<serde_json::Value> {
+    let issuer = &token.claims.issuer;
+    KeyPair::from_public_key(issuer)?;
+    if !issuer.starts_with('A') {
+        return Err(errors::new(ErrorKind::InvalidKeyType));
+    }
+    // An encoded public key is a prefix byte, the 32 bytes of the key and a CRC-16 checksum
+    let raw = BASE32_NOPAD
+        .decode(issuer.as_bytes())
+        .map_err(|_| errors::new(ErrorKind::InvalidKeyType))?;
+    Ok(json!({
+        "kty": "OKP",
+        "crv": "Ed25519",
+        "x": base64::encode_config(&raw[1..33], base64::URL_SAFE_NO_PAD),
+    }))
+}
+
+// Indicates whether a key is a valid public key of the type identified by the prefix, e.g. `M`
+// for modules or `A` for accounts
+pub(crate) fn is_key_of_type(key: &str, prefix: char) -> bool {
+    key.starts_with(prefix) && KeyPair::from_public_key(key).is_ok()
+}
+
+// Decodes a hex module hash, checking it has the length of a digest of the given algorithm
+pub(crate) fn decode_module_hash(hash: &str, algorithm: HashAlgorithm) -> Result<Vec<u8>> {
+    let expected_len = match algorithm {
+        HashAlgorithm::Sha256 => SHA256.output_len,
+        HashAlgorithm::Sha512 => SHA512.output_len,
+    };
+    match HEXUPPER.decode(hash.as_bytes()) {
+        Ok(bytes) if bytes.len() == expected_len => Ok(bytes),
+        _ => Err(errors::new(ErrorKind::MalformedHash(hash.to_string()))),
+    }
+}
+
+fn actor_tags(token: &Token<Actor>) -> &[String] {
+    token
+        .claims
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.tags.as_deref())
+        .unwrap_or_default()
+}
+
+fn declared_capabilities(token: &Token<Actor>) -> Vec<String> {
+    token
+        .claims
+        .metadata
+        .as_ref()
+        .and_then(|meta| meta.caps.clone())
+        .unwrap_or_default()
+}
+
+fn normalize_capabilities(list: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(list.len());
+    for cap in caps::expand_capability_aliases(list) {
+        let cap = caps::capability_id(&cap).map_or(cap, str::to_string);
+        if !normalized.contains(&cap) {
+            normalized.push(cap);
+        }
+    }
+    normalized
+}
+
+/// Validates a signed JWT. This will check the signature, expiration time, and not-valid-before
+/// time. Only the raw JWT is required, so tokens stored separately from their WebAssembly module
+/// can be validated without the module bytes
+pub fn validate_token<T>(input: &str) -> Result<TokenValidation>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    validate_token_with_clock::<T>(input, &SystemClock)
+}
+
+/// Validates a signed JWT in the same way as `validate_token`, checking the expiration and
+/// not-valid-before times against the given clock
+pub fn validate_token_with_clock<T>(input: &str, clock: &dyn Clock) -> Result<TokenValidation>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    let segments: Vec<&str> = input.split('.').collect();
+    if segments.len() != 3 {
+        return Err(errors::new(ErrorKind::Token("invalid token format".into())));
+    }
+    let header_and_claims = format!("{}.{}", segments[0], segments[1]);
+    let sig = base64::decode_config(segments[2], base64::URL_SAFE_NO_PAD)?;
+
+    let header: ClaimsHeader = from_jwt_segment(segments[0])?;
+    validate_header(&header)?;
+
+    let claims = Claims::<T>::decode_unverified(input)?;
+    validate_issuer(&claims.issuer)?;
+    validate_subject(&claims.subject)?;
+
+    let kp = KeyPair::from_public_key(&claims.issuer)?;
+    let sigverify = kp.verify(header_and_claims.as_bytes(), &sig);
+
+    let now = since_the_epoch_with(clock)?.as_secs();
+    let validation = TokenValidation {
+        signature_valid: sigverify.is_ok(),
+        expired: claims.expired_at(now),
+        expires_human: stamp_to_human(claims.expires, now).unwrap_or_else(|| "never".to_string()),
+        not_before_human: stamp_to_human(claims.not_before, now)
+            .unwrap_or_else(|| "immediately".to_string()),
+        cannot_use_yet: claims.cannot_use_yet_at(now),
+    };
+
+    Ok(validation)
+}
+
+fn validate_issuer(iss: &str) -> Result<()> {
+    if iss.is_empty() {
+        Err(errors::new(ErrorKind::MissingIssuer))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_subject(sub: &str) -> Result<()> {
+    if sub.is_empty() {
+        Err(errors::new(ErrorKind::MissingSubject))
+    } else {
+        Ok(())
+    }
+}
+
+/// Verifies the chain of trust from an actor up to an operator: the actor must be issued by the
+/// account, and the account by the operator, either by their primary keys or one of their
+/// `valid_signers`. The JWT of every token is verified with `Claims::decode`, so each
+/// level must be properly signed and currently valid
+///
+/// # Errors
+/// Returns an `UntrustedIssuer` error if a token wasn't issued by the level above it, or any
+/// error `Claims::decode` returns for the tokens themselves
+pub fn verify_chain(
+    actor_token: &Token<Actor>,
+    account_token: &Token<Account>,
+    operator_token: &Token<Operator>,
+) -> Result<()> {
+    let actor = Claims::<Actor>::decode(&actor_token.jwt)?;
+    let account = Claims::<Account>::decode(&account_token.jwt)?;
+    let operator = Claims::<Operator>::decode(&operator_token.jwt)?;
+
+    let account_signers = account
+        .metadata
+        .as_ref()
+        .and_then(|a| a.valid_signers.as_ref());
+    if !is_signer(&actor.issuer, &account.subject, account_signers) {
+        return Err(errors::new(ErrorKind::UntrustedIssuer(actor.issuer)));
+    }
+    let operator_signers = operator
+        .metadata
+        .as_ref()
+        .and_then(|o| o.valid_signers.as_ref());
+    if !is_signer(&account.issuer, &operator.subject, operator_signers) {
+        return Err(errors::new(ErrorKind::UntrustedIssuer(account.issuer)));
+    }
+    Ok(())
+}
+
+fn is_signer(key: &str, subject: &str, valid_signers: Option<&Vec<String>>) -> bool {
+    key == subject || valid_signers.is_some_and(|signers| signers.iter().any(|s| s == key))
+}
+
+/// A source of the current time. Everything that needs the time uses the OS system clock,
+/// except where a `Clock` can be supplied so that a fixed or skewed time can be injected
+pub trait Clock {
+    /// The current time in _seconds since the epoch_
+    fn now_secs(&self) -> u64;
+
+    /// The current time. Only clocks that can be set before the UNIX epoch need to
+    /// override this
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.now_secs())
+    }
+}
+
+/// A `Clock` backed by the OS system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        epoch_secs()
+    }
+
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+pub(crate) fn since_the_epoch() -> Result<Duration> {
+    since_the_epoch_with(&SystemClock)
+}
+
+pub(crate) fn since_the_epoch_with(clock: &dyn Clock) -> Result<Duration> {
+    clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| errors::new(ErrorKind::ClockError))
+}
+
+// The current time in seconds since the epoch, for the places that can't return an error.
+// A clock set before the epoch is treated as the epoch itself
+fn epoch_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn validate_header(h: &ClaimsHeader) -> Result<()> {
+    if h.algorithm != HEADER_ALGORITHM {
+        Err(errors::new(ErrorKind::InvalidAlgorithm))
+    } else if h.header_type != HEADER_TYPE {
+        Err(errors::new(ErrorKind::Token("Invalid header".to_string())))
+    } else {
+        Ok(())
+    }
+}
+
+fn to_jwt_segment<T: Serialize>(input: &T) -> Result<String> {
+    let encoded = to_string(input)?;
+    Ok(base64::encode_config(
+        encoded.as_bytes(),
+        base64::URL_SAFE_NO_PAD,
+    ))
+}
+
+fn stamp_to_human(stamp: Option<u64>, now: u64) -> Option<String> {
+    stamp.map(|s| {
+        let now = now as i64;
+        let diff_sec = (now - (s as i64)).abs();
 
         // calculate roundoff
         let diff_sec = if diff_sec >= 86400 {
@@ -732,14 +1612,32 @@ impl Actor {
         Actor {
             name: Some(name),
             module_hash: "".to_string(),
+            hash_algorithm: HashAlgorithm::default(),
+            hash_scope: HashScope::default(),
             tags,
             caps,
+            cap_constraints: None,
+            caps_detailed: None,
             provider,
             rev,
             ver,
             call_alias: normalize_call_alias(call_alias),
+            oci_reference: None,
+            imports: None,
         }
     }
+
+    /// Returns the targets a capability is granted for, in the order they're granted. An empty
+    /// list means there are no scoped grants for the capability: if it's listed in `caps`, it
+    /// isn't limited to any particular resource
+    pub fn cap_targets(&self, capability: &str) -> Vec<&str> {
+        self.caps_detailed
+            .iter()
+            .flatten()
+            .filter(|grant| grant.capability == capability)
+            .filter_map(|grant| grant.target.as_deref())
+            .collect()
+    }
 }
 
 impl CapabilityProvider {
@@ -758,6 +1656,8 @@ impl CapabilityProvider {
             vendor,
             rev,
             ver,
+            module_hash: None,
+            hash_algorithm: HashAlgorithm::default(),
         }
     }
 }
@@ -767,8 +1667,17 @@ impl Account {
         Account {
             name: Some(name),
             valid_signers: Some(additional_keys),
+            valid_capabilities: None,
+            limits: None,
         }
     }
+
+    /// Indicates whether actors signed by this account may be granted the given capability
+    pub fn allows_capability(&self, cap: &str) -> bool {
+        self.valid_capabilities
+            .as_ref()
+            .map_or(true, |caps| caps.iter().any(|c| c == cap))
+    }
 }
 
 impl Operator {
@@ -800,16 +1709,27 @@ impl Invocation {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
-    use super::{Account, Actor, Claims, ErrorKind, Invocation, KeyPair, Operator};
+    use super::{
+        Account, Actor, Claims, DeserializeOwned, ErrorKind, Invocation, KeyPair, Operator,
+        Serialize, WascapEntity,
+    };
     use crate::{
-        caps::{KEY_VALUE, LOGGING, MESSAGING},
+        caps::{CapabilityId, KEY_VALUE, LOGGING, MESSAGING},
         jwt::{
-            since_the_epoch, validate_token, CapabilityProvider, ClaimsBuilder, Cluster,
-            WASCAP_INTERNAL_REVISION,
+            check_alias_conflicts, diff_claims, excess_host_capabilities, from_jwt_segment,
+            has_tag, interface_tags, issuer_jwk, since_the_epoch, unsatisfied_capabilities,
+            validate_token, validate_token_with_clock, verify_chain, verify_oci_reference,
+            CapConstraint, CapabilityPolicy, CapabilityProvider, ClaimChange, ClaimsBuilder, Clock,
+            Cluster, Token, ValidationOptions, WASCAP_INTERNAL_REVISION,
         },
     };
-    use std::collections::HashMap;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        convert::TryFrom,
+        time::Duration,
+    };
 
     #[test]
     fn full_validation_nbf() {
@@ -829,7 +1749,7 @@ mod test {
             issued_at: 0,
             issuer: kp.public_key(),
             subject: "test.wasm".to_string(),
-            not_before: Some(since_the_epoch().as_secs() + 1000),
+            not_before: Some(since_the_epoch().unwrap().as_secs() + 1000),
             wascap_revision: Some(WASCAP_INTERNAL_REVISION),
         };
 
@@ -856,7 +1776,7 @@ mod test {
                 Some("".to_string()),
                 None,
             )),
-            expires: Some(since_the_epoch().as_secs() - 30000),
+            expires: Some(since_the_epoch().unwrap().as_secs() - 30000),
             id: nuid::next(),
             issued_at: 0,
             issuer: kp.public_key(),
@@ -875,12 +1795,49 @@ mod test {
         }
     }
 
+    struct FixedClock(u64);
+
+    impl Clock for FixedClock {
+        fn now_secs(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn validation_with_fixed_clock() {
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Account::new("test account".to_string(), vec![])),
+            expires: Some(10_000),
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: "foo".to_string(),
+            not_before: Some(5_000),
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let encoded = claims.encode(&kp).unwrap();
+
+        let early = validate_token_with_clock::<Account>(&encoded, &FixedClock(1_400)).unwrap();
+        assert!(early.cannot_use_yet);
+        assert!(!early.expired);
+        assert_eq!(early.not_before_human, "in 1h");
+
+        let valid = validate_token_with_clock::<Account>(&encoded, &FixedClock(10_000)).unwrap();
+        assert!(!valid.cannot_use_yet);
+        assert!(!valid.expired);
+
+        let late = validate_token_with_clock::<Account>(&encoded, &FixedClock(10_060)).unwrap();
+        assert!(late.expired);
+        assert_eq!(late.expires_human, "1m ago");
+    }
+
     #[test]
     fn validate_account() {
         let issuer = KeyPair::new_operator();
         let claims = Claims {
             metadata: Some(Account::new("test account".to_string(), vec![])),
-            expires: Some(since_the_epoch().as_secs() - 30000),
+            expires: Some(since_the_epoch().unwrap().as_secs() - 30000),
             id: nuid::next(),
             issued_at: 0,
             issuer: issuer.public_key(),
@@ -1062,6 +2019,984 @@ mod test {
         );
     }
 
+    #[test]
+    fn actor_builder() {
+        let account = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let before = since_the_epoch().unwrap().as_secs();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&account.public_key())
+            .subject(&module.public_key())
+            .with_cap(MESSAGING)
+            .with_cap(KEY_VALUE)
+            .with_tag("testing")
+            .expires_in_days(2)
+            .not_before_days(1)
+            .rev(3)
+            .ver("v0.0.3")
+            .call_alias("Test Actor")
+            .build();
+
+        let after = since_the_epoch().unwrap().as_secs();
+        let nbf = claims.not_before.unwrap();
+        let exp = claims.expires.unwrap();
+        assert!(nbf >= before + 86400 && nbf <= after + 86400);
+        assert!(exp >= before + 2 * 86400 && exp <= after + 2 * 86400);
+        let meta = claims.metadata.as_ref().unwrap();
+        assert_eq!(meta.name, Some("test".to_string()));
+        assert_eq!(
+            meta.caps,
+            Some(vec![MESSAGING.to_string(), KEY_VALUE.to_string()])
+        );
+        assert_eq!(meta.tags, Some(vec!["testing".to_string()]));
+        assert_eq!(meta.rev, Some(3));
+        assert_eq!(meta.ver, Some("v0.0.3".to_string()));
+        assert_eq!(meta.call_alias, Some("test_actor".to_string()));
+        assert!(!meta.provider);
+        assert_eq!(claims.wascap_revision, Some(WASCAP_INTERNAL_REVISION));
+
+        let encoded = claims.encode(&account).unwrap();
+        // Not valid for another day, so it can only be decoded unverified
+        assert_eq!(
+            claims,
+            Claims::<Actor>::decode_unverified(&encoded).unwrap()
+        );
+
+        // Only the claims that can be embedded in modules record the revision
+        let account_claims = ClaimsBuilder::<Account>::new()
+            .issuer(&KeyPair::new_operator().public_key())
+            .subject(&account.public_key())
+            .build();
+        assert_eq!(account_claims.wascap_revision, None);
+    }
+
+    #[test]
+    fn capability_constraints() {
+        let kp = KeyPair::new_account();
+        let mut actor = Actor::new(
+            "test".to_string(),
+            Some(vec![MESSAGING.to_string(), KEY_VALUE.to_string()]),
+            None,
+            false,
+            Some(1),
+            None,
+            None,
+        );
+        let mut constraints = BTreeMap::new();
+        constraints.insert(
+            KEY_VALUE.to_string(),
+            CapConstraint {
+                expires: Some(1000),
+            },
+        );
+        actor.cap_constraints = Some(constraints);
+        let claims = ClaimsBuilder::new()
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_metadata(actor)
+            .build();
+
+        let jwt = claims.encode(&kp).unwrap();
+        let token = Token {
+            claims: Claims::<Actor>::decode(&jwt).unwrap(),
+            jwt,
+            hash_verified: false,
+        };
+        assert_eq!(token.claims, claims);
+        assert_eq!(
+            token.active_capabilities(999),
+            vec![MESSAGING.to_string(), KEY_VALUE.to_string()]
+        );
+        assert_eq!(token.active_capabilities(1000), vec![MESSAGING.to_string()]);
+    }
+
+    #[test]
+    fn inspection_json() {
+        let kp = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&module.public_key())
+            .with_cap(MESSAGING)
+            .rev(2)
+            .ver("v0.0.2")
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+
+        let json = token.to_inspection_json();
+        assert_eq!(json["name"], "test");
+        assert_eq!(json["module"], module.public_key());
+        assert_eq!(json["issuer"], kp.public_key());
+        assert_eq!(json["caps"], serde_json::json!([MESSAGING]));
+        assert_eq!(json["tags"], serde_json::json!([]));
+        assert_eq!(json["version"], "v0.0.2");
+        assert_eq!(json["revision"], 2);
+        assert_eq!(json["expires_human"], "never");
+        assert_eq!(json["valid"], true);
+
+        let inspection = token.inspect();
+        assert_eq!(inspection.name, "test");
+        assert_eq!(inspection.subject, module.public_key());
+        assert_eq!(inspection.caps, vec![MESSAGING.to_string()]);
+        assert!(inspection.tags.is_empty());
+        assert_eq!(inspection.version, Some("v0.0.2".to_string()));
+        assert_eq!(inspection.revision, Some(2));
+        assert_eq!(inspection.expires, None);
+        assert!(!inspection.provider);
+    }
+
+    #[test]
+    fn oci_reference() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .oci_reference("registry.example.com/echo:0.1.0")
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+        let token = Token {
+            claims: Claims::<Actor>::decode(&jwt).unwrap(),
+            jwt,
+            hash_verified: false,
+        };
+        assert!(verify_oci_reference(
+            &token,
+            "registry.example.com/echo:0.1.0"
+        ));
+        assert!(!verify_oci_reference(
+            &token,
+            "registry.example.com/other:0.1.0"
+        ));
+
+        // Claims signed without a reference aren't tied to one
+        let unset = Token {
+            claims: Claims::<Actor>::new(
+                "test".to_string(),
+                kp.public_key(),
+                KeyPair::new_module().public_key(),
+                None,
+                None,
+                false,
+                None,
+                None,
+                None,
+            ),
+            jwt: String::new(),
+            hash_verified: false,
+        };
+        assert!(verify_oci_reference(&unset, "anything"));
+    }
+
+    #[test]
+    fn capability_ids() {
+        let parsed: CapabilityId = "acme:payments:v2".parse().unwrap();
+        assert_eq!(parsed.to_string(), "acme:payments:v2");
+        assert_eq!(
+            CapabilityId::try_from(KEY_VALUE).unwrap(),
+            CapabilityId::KEY_VALUE
+        );
+        for bad in &[
+            "keyvalue",
+            "wasmcloud:",
+            ":keyvalue",
+            "wasmcloud::kv",
+            "wasm cloud:kv",
+        ] {
+            match bad.parse::<CapabilityId>() {
+                Err(e) => match e.kind() {
+                    ErrorKind::InvalidCapability => (),
+                    _ => panic!("failed to assert errors::ErrorKind::InvalidCapability"),
+                },
+                Ok(_) => panic!("parsed malformed capability ID {}", bad),
+            }
+        }
+
+        let claims = ClaimsBuilder::<Actor>::new()
+            .with_cap(LOGGING)
+            .with_capabilities(vec![CapabilityId::MESSAGING, parsed])
+            .build();
+        assert_eq!(
+            claims.metadata.unwrap().caps.unwrap(),
+            vec![LOGGING, MESSAGING, "acme:payments:v2"]
+        );
+    }
+
+    #[test]
+    fn capability_namespaces() {
+        use crate::caps::{self, is_builtin, namespace};
+
+        assert_eq!(namespace(caps::LOGGING), Some("wasmcloud:builtin"));
+        assert_eq!(namespace(caps::NUMBERGEN), Some(caps::BUILTIN_NAMESPACE));
+        assert_eq!(namespace(caps::KEY_VALUE), Some("wasmcloud"));
+        for lenient in &[caps::STANDARD_IO, "", ":logging", "wasmcloud:"] {
+            assert_eq!(namespace(lenient), None);
+        }
+
+        let builtin: Vec<_> = caps::all_known_capabilities()
+            .iter()
+            .copied()
+            .filter(|cap| is_builtin(cap))
+            .collect();
+        assert_eq!(builtin, vec![caps::NUMBERGEN, caps::LOGGING]);
+        assert!(!is_builtin("wasmcloud:builtinlogging"));
+        assert!(!is_builtin("acme:wasmcloud:builtin:logging"));
+    }
+
+    #[test]
+    fn key_id_header() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        let jwt = claims.encode_with_key_id(&kp, "signing-key-2").unwrap();
+        assert!(Claims::<Actor>::decode(&jwt).is_ok());
+        assert!(validate_token::<Actor>(&jwt).unwrap().signature_valid);
+        let token = Token {
+            claims: claims.clone(),
+            jwt,
+            hash_verified: false,
+        };
+        assert_eq!(token.key_id(), Some("signing-key-2".to_string()));
+
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+        assert_eq!(token.key_id(), None);
+    }
+
+    #[test]
+    fn capability_audit() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap(crate::caps::STANDARD_IO)
+            .with_cap("acme:custom")
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+        let host = vec![
+            "Messaging".to_string(),
+            LOGGING.to_string(),
+            KEY_VALUE.to_string(),
+        ];
+
+        assert_eq!(
+            unsatisfied_capabilities(&token, &host),
+            vec![crate::caps::NUMBERGEN, "acme:custom"]
+        );
+        assert_eq!(excess_host_capabilities(&token, &host), vec![KEY_VALUE]);
+    }
+
+    #[test]
+    fn self_consistency() {
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .issued_at(1000)
+            .expires_at(2000)
+            .not_before(1500)
+            .build();
+        claims.metadata.as_mut().unwrap().module_hash = "AB".repeat(32);
+        claims.verify_self_consistency().unwrap();
+
+        let broken = |edit: &dyn Fn(&mut Claims<Actor>)| {
+            let mut claims = claims.clone();
+            edit(&mut claims);
+            claims.verify_self_consistency().unwrap_err().into_kind()
+        };
+        assert!(matches!(
+            broken(&|c| c.issued_at = 3000),
+            ErrorKind::InconsistentClaims(_)
+        ));
+        assert!(matches!(
+            broken(&|c| c.not_before = Some(2500)),
+            ErrorKind::InconsistentClaims(_)
+        ));
+        assert!(matches!(
+            broken(&|c| c.issuer = KeyPair::new_module().public_key()),
+            ErrorKind::InvalidKeyType
+        ));
+        assert!(matches!(
+            broken(&|c| c.subject = "Mnotakey".to_string()),
+            ErrorKind::SubjectNotModuleKey(_)
+        ));
+        assert!(matches!(
+            broken(&|c| c.metadata.as_mut().unwrap().module_hash = "not hex".to_string()),
+            ErrorKind::MalformedHash(_)
+        ));
+        assert!(matches!(
+            broken(&|c| c.metadata = None),
+            ErrorKind::MissingMetadata
+        ));
+
+        // Claims signed into a module are consistent
+        claims.expires = None;
+        claims.not_before = None;
+        let module =
+            crate::wasm::embed_claims(include_bytes!("../examples/loop.wasm"), &claims, &kp)
+                .unwrap();
+        let token = crate::wasm::extract_claims(&module).unwrap().unwrap();
+        token.claims.verify_self_consistency().unwrap();
+    }
+
+    #[test]
+    fn capability_targets() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap_target(KEY_VALUE, "orders")
+            .with_cap_target(KEY_VALUE, "sessions")
+            .build();
+        let meta = claims.metadata.as_ref().unwrap();
+        assert_eq!(meta.caps.as_ref().unwrap(), &vec![MESSAGING, KEY_VALUE]);
+        assert_eq!(meta.cap_targets(KEY_VALUE), vec!["orders", "sessions"]);
+        assert!(meta.cap_targets(MESSAGING).is_empty());
+
+        let decoded = Claims::<Actor>::decode(&claims.encode(&kp).unwrap()).unwrap();
+        assert_eq!(decoded, claims);
+
+        // Claims from before scoped grants still decode, with no targets
+        let legacy: Actor =
+            serde_json::from_str(r#"{"hash":"","caps":["wasmcloud:keyvalue"]}"#).unwrap();
+        assert_eq!(legacy.caps_detailed, None);
+        assert!(legacy.cap_targets(KEY_VALUE).is_empty());
+    }
+
+    #[test]
+    fn capability_policy() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap(KEY_VALUE)
+            .with_cap(crate::caps::HTTP_SERVER)
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+
+        assert!(CapabilityPolicy::new().evaluate(&token).is_allowed());
+
+        let mut policy = CapabilityPolicy::new();
+        policy.deny(crate::caps::HTTP_SERVER);
+        assert_eq!(
+            policy.evaluate(&token).denied,
+            vec![crate::caps::HTTP_SERVER]
+        );
+
+        // Deny wins over allow, and anything not allowed is refused
+        policy.allow("Messaging").allow(crate::caps::HTTP_SERVER);
+        let decision = policy.evaluate(&token);
+        assert!(!decision.is_allowed());
+        assert_eq!(decision.denied, vec![KEY_VALUE, crate::caps::HTTP_SERVER]);
+
+        let json = serde_json::to_string(&policy).unwrap();
+        let parsed: CapabilityPolicy = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, policy);
+        let deny_only: CapabilityPolicy =
+            serde_json::from_str(r#"{"deny":["wasmcloud:keyvalue"]}"#).unwrap();
+        assert_eq!(deny_only.evaluate(&token).denied, vec![KEY_VALUE]);
+    }
+
+    #[test]
+    fn contract_tags() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_tag("contract:wasmcloud:httpserver")
+            .with_tag("experimental")
+            .with_tag("contract:wasmcloud:keyvalue")
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+
+        assert_eq!(
+            interface_tags(&token),
+            vec!["wasmcloud:httpserver", "wasmcloud:keyvalue"]
+        );
+        assert!(has_tag(&token, "experimental"));
+        assert!(has_tag(&token, "contract:wasmcloud:keyvalue"));
+        assert!(!has_tag(&token, "wasmcloud:keyvalue"));
+
+        let untagged = Token {
+            claims: Claims::<Actor>::default(),
+            ..token
+        };
+        assert!(interface_tags(&untagged).is_empty());
+        assert!(!has_tag(&untagged, "experimental"));
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn compact_tokens() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap(KEY_VALUE)
+            .with_cap(LOGGING)
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: true,
+        };
+
+        let compact = token.to_compact();
+        assert!(compact.len() < token.jwt.len());
+        let decoded = Token::from_compact(&compact).unwrap();
+        assert_eq!(decoded, token);
+        assert_eq!(decoded.claims.subject, token.claims.subject);
+        assert!(!decoded.hash_verified);
+
+        let forged = Token {
+            jwt: token.claims.encode(&KeyPair::new_account()).unwrap(),
+            ..token
+        };
+        match Token::from_compact(&forged.to_compact()) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("decoded a token with a forged signature"),
+        }
+        assert!(Token::from_compact("not compact!").is_err());
+
+        // A small string that inflates far past any real token is cut off
+        let bomb = base64::encode_config(
+            crate::gzip::compress_raw(&vec![b'a'; 10 * 1024 * 1024]),
+            base64::URL_SAFE_NO_PAD,
+        );
+        assert!(bomb.len() < 64 * 1024);
+        match Token::from_compact(&bomb) {
+            Err(e) => match e.kind() {
+                ErrorKind::DecompressedTooLarge(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::DecompressedTooLarge"),
+            },
+            Ok(_) => panic!("decoded a token past the size limit"),
+        }
+    }
+
+    #[test]
+    fn alias_conflicts() {
+        let kp = KeyPair::new_account();
+        let token = |subject: &str, alias: Option<&str>| {
+            let mut claims = ClaimsBuilder::<Actor>::new()
+                .name("test")
+                .issuer(&kp.public_key())
+                .subject(subject)
+                .build();
+            claims.metadata.as_mut().unwrap().call_alias = alias.map(str::to_string);
+            Token {
+                jwt: claims.encode(&kp).unwrap(),
+                claims,
+                hash_verified: false,
+            }
+        };
+        let (first, second, third) = (
+            KeyPair::new_module().public_key(),
+            KeyPair::new_module().public_key(),
+            KeyPair::new_module().public_key(),
+        );
+        let tokens = vec![
+            token(&first, Some("echo")),
+            token(&second, Some("uppercase")),
+            token(&third, Some("echo")),
+            token(&first, Some("echo")),
+            token(&second, None),
+            token(&second, Some("aardvark")),
+            token(&first, Some("aardvark")),
+        ];
+
+        assert_eq!(
+            check_alias_conflicts(&tokens),
+            vec![
+                ("aardvark".to_string(), vec![second, first.clone()]),
+                ("echo".to_string(), vec![first, third]),
+            ]
+        );
+        assert!(check_alias_conflicts(&tokens[..2]).is_empty());
+    }
+
+    #[test]
+    fn explicit_issued_at() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .issued_at(1_000_000)
+            .build();
+        assert_eq!(claims.issued_at, 1_000_000);
+        let decoded = Claims::<Actor>::decode(&claims.encode(&kp).unwrap()).unwrap();
+        assert_eq!(decoded.issued_at, 1_000_000);
+
+        let before = since_the_epoch().unwrap().as_secs();
+        let claims = ClaimsBuilder::<Actor>::new().name("test").build();
+        assert!(claims.issued_at >= before);
+    }
+
+    #[test]
+    fn issuer_public_key_as_jwk() {
+        use ring::signature::{Ed25519KeyPair, KeyPair as _, UnparsedPublicKey, ED25519};
+
+        let seed = [7; 32];
+        let kp = KeyPair::new_from_raw(nkeys::KeyPairType::Account, seed).unwrap();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let token = Token {
+            jwt: claims.encode(&kp).unwrap(),
+            claims,
+            hash_verified: false,
+        };
+
+        let jwk = issuer_jwk(&token).unwrap();
+        assert_eq!(jwk["kty"], "OKP");
+        assert_eq!(jwk["crv"], "Ed25519");
+        let x = base64::decode_config(jwk["x"].as_str().unwrap(), base64::URL_SAFE_NO_PAD).unwrap();
+        let raw = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        assert_eq!(x, raw.public_key().as_ref());
+
+        // The key verifies the token without going through nkeys
+        let (input, signature) = token.jwt.rsplit_once('.').unwrap();
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).unwrap();
+        UnparsedPublicKey::new(&ED25519, &x)
+            .verify(input.as_bytes(), &signature)
+            .unwrap();
+
+        let module_issued = Token {
+            claims: Claims {
+                issuer: KeyPair::new_module().public_key(),
+                ..token.claims.clone()
+            },
+            ..token
+        };
+        match issuer_jwk(&module_issued) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidKeyType => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidKeyType"),
+            },
+            Ok(_) => panic!("converted a module key as the issuer"),
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+        assert_eq!(Claims::<Actor>::decode(&jwt).unwrap(), claims);
+
+        let unsigned = format!("{}.", jwt.rsplit_once('.').unwrap().0);
+        assert!(matches!(
+            Claims::<Actor>::decode(&unsigned).unwrap_err().kind(),
+            ErrorKind::SignatureMissing
+        ));
+
+        let forged = Claims {
+            issuer: KeyPair::new_account().public_key(),
+            ..claims.clone()
+        }
+        .encode(&kp)
+        .unwrap();
+        assert!(matches!(
+            Claims::<Actor>::decode(&forged).unwrap_err().kind(),
+            ErrorKind::SignatureInvalid
+        ));
+
+        let expired = Claims {
+            expires: Some(1000),
+            ..claims.clone()
+        }
+        .encode(&kp)
+        .unwrap();
+        match Claims::<Actor>::decode(&expired).unwrap_err().kind() {
+            ErrorKind::TokenExpired(exp) => assert_eq!(*exp, 1000),
+            _ => panic!("failed to assert errors::ErrorKind::TokenExpired"),
+        }
+        // The claims can still be read when they don't need to be trusted
+        assert_eq!(
+            Claims::<Actor>::decode_unverified(&expired)
+                .unwrap()
+                .expires,
+            Some(1000)
+        );
+
+        let early = Claims {
+            not_before: Some(since_the_epoch().unwrap().as_secs() + 1000),
+            ..claims
+        }
+        .encode(&kp)
+        .unwrap();
+        assert!(matches!(
+            Claims::<Actor>::decode(&early).unwrap_err().kind(),
+            ErrorKind::TokenTooEarly
+        ));
+    }
+
+    #[test]
+    fn absolute_timestamps() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .not_before(1_700_000_000)
+            .expires_at(1_800_000_000)
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+
+        let payload: serde_json::Value = from_jwt_segment(jwt.split('.').nth(1).unwrap()).unwrap();
+        assert_eq!(payload["nbf"], 1_700_000_000);
+        assert_eq!(payload["exp"], 1_800_000_000);
+    }
+
+    #[test]
+    fn issued_in_future() {
+        let claims = Claims::<Account> {
+            issued_at: 1060,
+            ..Default::default()
+        };
+        assert!(ValidationOptions::default().check_at(&claims, 0).is_ok());
+
+        let strict = ValidationOptions {
+            reject_future_issued_at: true,
+            ..Default::default()
+        };
+        assert!(strict.check_at(&claims, 1000).is_ok());
+        match strict.check_at(&claims, 999) {
+            Err(e) => match e.kind() {
+                ErrorKind::IssuedInFuture(1060) => (),
+                _ => panic!("failed to assert errors::ErrorKind::IssuedInFuture"),
+            },
+            Ok(_) => panic!("accepted claims issued in the future"),
+        }
+    }
+
+    #[test]
+    fn renew_claims() {
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_tag("tag")
+            .expires_at(1000)
+            .build();
+        claims.metadata.as_mut().unwrap().module_hash = "HASH".to_string();
+
+        let renewed = claims.renew(&kp, Some(30)).unwrap();
+        let now = since_the_epoch().unwrap().as_secs();
+        assert_ne!(renewed.id, claims.id);
+        assert!(renewed.issued_at >= claims.issued_at);
+        let exp = renewed.expires.unwrap();
+        assert!(exp > now + 29 * 86400 && exp <= now + 30 * 86400);
+        assert_eq!(renewed.issuer, claims.issuer);
+        assert_eq!(renewed.subject, claims.subject);
+        assert_eq!(renewed.metadata, claims.metadata);
+        assert_eq!(renewed.wascap_revision, claims.wascap_revision);
+
+        assert_eq!(claims.renew(&kp, None).unwrap().expires, None);
+        assert!(claims.renew(&KeyPair::new_account(), Some(30)).is_err());
+    }
+
+    #[test]
+    fn claims_diff() {
+        let kp = KeyPair::new_account();
+        let old = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap(LOGGING)
+            .with_tag("tag")
+            .rev(1)
+            .build();
+        assert!(diff_claims(&old, &old).is_empty());
+
+        let mut new = old.renew(&kp, Some(1)).unwrap();
+        {
+            let meta = new.metadata.as_mut().unwrap();
+            meta.caps = Some(vec![MESSAGING.to_string(), KEY_VALUE.to_string()]);
+            meta.rev = Some(2);
+        }
+        let changes = diff_claims(&old, &new);
+        let change = |field: &str, old: Option<&str>, new: Option<&str>| ClaimChange {
+            field: field.to_string(),
+            old: old.map(String::from),
+            new: new.map(String::from),
+        };
+        assert_eq!(
+            changes,
+            vec![
+                change("caps", Some(LOGGING), None),
+                change("caps", None, Some(KEY_VALUE)),
+                change("revision", Some("1"), Some("2")),
+                change("expires", None, Some(&new.expires.unwrap().to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn token_fingerprint() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+        let token = Token {
+            jwt: jwt.clone(),
+            claims: claims.clone(),
+            hash_verified: false,
+        };
+        let same = Token {
+            jwt,
+            claims,
+            hash_verified: true,
+        };
+        assert_eq!(token, same);
+        assert_eq!(token.fingerprint(), same.fingerprint());
+        assert_eq!(token.fingerprint().len(), 64);
+
+        let other = Token {
+            jwt: token.claims.encode(&KeyPair::new_account()).unwrap(),
+            ..token.clone()
+        };
+        assert_ne!(token, other);
+        assert_ne!(token.fingerprint(), other.fingerprint());
+    }
+
+    #[test]
+    fn token_parts_roundtrip() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+        let token = Token::from_parts(jwt.clone(), claims.clone()).unwrap();
+        assert!(!token.hash_verified);
+
+        let cached = serde_json::to_string(&token).unwrap();
+        let restored: Token<Actor> = serde_json::from_str(&cached).unwrap();
+        restored.check_claims().unwrap();
+        let (restored_jwt, restored_claims) = restored.into_parts();
+        assert_eq!(restored_jwt, jwt);
+        assert_eq!(restored_claims.subject, claims.subject);
+
+        let mut tampered = claims;
+        tampered.metadata.as_mut().unwrap().caps = Some(vec![KEY_VALUE.to_string()]);
+        match Token::from_parts(jwt, tampered) {
+            Err(e) => match e.kind() {
+                ErrorKind::Token(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::Token"),
+            },
+            Ok(_) => panic!("accepted claims that don't match the JWT"),
+        }
+    }
+
+    #[test]
+    fn deserialized_token_hash_unverified() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let jwt = claims.encode(&kp).unwrap();
+        let forged = serde_json::json!({
+            "jwt": jwt,
+            "claims": claims,
+            "hash_verified": true,
+        });
+
+        let restored: Token<Actor> = serde_json::from_value(forged).unwrap();
+        restored.check_claims().unwrap();
+        assert!(!restored.hash_verified);
+        let (jwt, claims) = restored.into_parts();
+        assert!(!Token::from_parts(jwt, claims).unwrap().hash_verified);
+    }
+
+    #[test]
+    fn trust_chain() {
+        let operator_kp = KeyPair::new_operator();
+        let account_kp = KeyPair::new_account();
+        let signer_kp = KeyPair::new_account();
+        fn token<T>(jwt: String) -> Token<T>
+        where
+            T: Serialize + DeserializeOwned + WascapEntity,
+        {
+            Token {
+                claims: Claims::decode(&jwt).unwrap(),
+                jwt,
+                hash_verified: false,
+            }
+        }
+
+        let operator = token(
+            Claims::<Operator>::new(
+                "operator".to_string(),
+                operator_kp.public_key(),
+                operator_kp.public_key(),
+                vec![],
+            )
+            .encode(&operator_kp)
+            .unwrap(),
+        );
+        let account = token(
+            Claims::<Account>::new(
+                "account".to_string(),
+                operator_kp.public_key(),
+                account_kp.public_key(),
+                vec![signer_kp.public_key()],
+            )
+            .encode(&operator_kp)
+            .unwrap(),
+        );
+        let actor_by = |kp: &KeyPair| {
+            token(
+                ClaimsBuilder::<Actor>::new()
+                    .name("test")
+                    .issuer(&kp.public_key())
+                    .subject(&KeyPair::new_module().public_key())
+                    .build()
+                    .encode(kp)
+                    .unwrap(),
+            )
+        };
+
+        assert!(verify_chain(&actor_by(&account_kp), &account, &operator).is_ok());
+        assert!(verify_chain(&actor_by(&signer_kp), &account, &operator).is_ok());
+        match verify_chain(&actor_by(&KeyPair::new_account()), &account, &operator) {
+            Err(e) => match e.kind() {
+                ErrorKind::UntrustedIssuer(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::UntrustedIssuer"),
+            },
+            Ok(_) => panic!("verified an actor from another account"),
+        }
+
+        let other_operator_kp = KeyPair::new_operator();
+        let other_operator = token(
+            Claims::<Operator>::new(
+                "other".to_string(),
+                other_operator_kp.public_key(),
+                other_operator_kp.public_key(),
+                vec![],
+            )
+            .encode(&other_operator_kp)
+            .unwrap(),
+        );
+        assert!(verify_chain(&actor_by(&account_kp), &account, &other_operator).is_err());
+    }
+
+    #[test]
+    fn claims_time_boundaries() {
+        let claims = Claims::<Account> {
+            expires: Some(1000),
+            not_before: Some(500),
+            ..Default::default()
+        };
+        assert!(claims.cannot_use_yet_at(499));
+        assert!(!claims.cannot_use_yet_at(500));
+        assert!(!claims.expired_at(1000));
+        assert!(claims.expired_at(1001));
+
+        let forever = Claims::<Account>::default();
+        assert!(!forever.expired());
+        assert!(!forever.cannot_use_yet());
+    }
+
+    #[test]
+    fn claims_time_until_expiry() {
+        let claims = Claims::<Account> {
+            expires: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(
+            claims.time_until_expiry_at(400),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(claims.time_until_expiry_at(5000), Some(Duration::ZERO));
+        assert!(claims.expires_within_at(Duration::from_secs(601), 400));
+        assert!(!claims.expires_within_at(Duration::from_secs(600), 400));
+        assert!(claims.expires_within_at(Duration::ZERO, 5000));
+
+        // The claims are still valid at their expiration time, when no time is left, so they
+        // only expire within a duration that takes them past it
+        assert_eq!(claims.time_until_expiry_at(1000), Some(Duration::ZERO));
+        for now in 998..=1001 {
+            for d in 0..=2 {
+                assert_eq!(
+                    claims.expires_within_at(Duration::from_secs(d), now),
+                    claims.expired_at(now + d)
+                );
+            }
+        }
+        let last = Claims::<Account> {
+            expires: Some(u64::MAX),
+            ..Default::default()
+        };
+        assert_eq!(last.time_until_expiry_at(u64::MAX), Some(Duration::ZERO));
+
+        let forever = Claims::<Account>::default();
+        assert_eq!(forever.time_until_expiry(), None);
+        assert!(!forever.expires_within(Duration::from_secs(u64::MAX)));
+
+        let soon = Claims::<Account> {
+            expires: Some(since_the_epoch().unwrap().as_secs() + 3600),
+            ..Default::default()
+        };
+        assert!(soon.time_until_expiry().unwrap() <= Duration::from_secs(3600));
+        assert!(soon.expires_within(Duration::from_secs(3601)));
+        assert!(!soon.expires_within(Duration::from_secs(60)));
+    }
+
     #[test]
     fn encode_decode_logging_roundtrip() {
         let kp = KeyPair::new_account();
@@ -1103,12 +3038,64 @@ mod test {
             vec![kp2.public_key()],
         );
         let encoded = claims.encode(&kp1).unwrap();
-        let decoded = Claims::<Account>::decode(&encoded).unwrap();
+        let decoded = Claims::<Account>::decode_unverified(&encoded).unwrap();
         assert!(validate_token::<Account>(&encoded).is_ok());
         assert_eq!(claims, decoded);
         assert_eq!(claims.metadata.unwrap().valid_signers.unwrap().len(), 1);
     }
 
+    #[test]
+    fn account_roundtrip() {
+        let op = KeyPair::new_operator();
+        let account = KeyPair::new_account();
+        let mut limits = std::collections::BTreeMap::new();
+        limits.insert("max_actors".to_string(), 10);
+        let claims = Claims {
+            metadata: Some(Account {
+                valid_capabilities: Some(vec![MESSAGING.to_string()]),
+                limits: Some(limits),
+                ..Account::new("test account".to_string(), vec![])
+            }),
+            ..Claims::<Account>::new(
+                "test account".to_string(),
+                op.public_key(),
+                account.public_key(),
+                vec![],
+            )
+        };
+        let encoded = claims.encode(&op).unwrap();
+        let decoded = Claims::<Account>::decode(&encoded).unwrap();
+        assert_eq!(claims, decoded);
+        assert!(validate_token::<Account>(&encoded).unwrap().signature_valid);
+
+        let meta = decoded.metadata.unwrap();
+        assert_eq!(meta.limits.as_ref().unwrap()["max_actors"], 10);
+        assert!(meta.allows_capability(MESSAGING));
+        assert!(!meta.allows_capability(KEY_VALUE));
+        assert!(Account::default().allows_capability(KEY_VALUE));
+    }
+
+    #[test]
+    fn encode_decode_streams() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        let mut buf = Vec::new();
+        claims.encode_to_writer(&kp, &mut buf).unwrap();
+        assert!(
+            validate_token::<Actor>(std::str::from_utf8(&buf).unwrap())
+                .unwrap()
+                .signature_valid
+        );
+        buf.push(b'\n');
+        let decoded = Claims::<Actor>::decode_from_reader(buf.as_slice()).unwrap();
+        assert_eq!(claims, decoded);
+    }
+
     #[test]
     fn cluster_extra_signers() {
         let op = KeyPair::new_operator();
@@ -1121,7 +3108,7 @@ mod test {
             vec![kp2.public_key()],
         );
         let encoded = claims.encode(&kp1).unwrap();
-        let decoded = Claims::<Cluster>::decode(&encoded).unwrap();
+        let decoded = Claims::<Cluster>::decode_unverified(&encoded).unwrap();
         assert!(validate_token::<Cluster>(&encoded).is_ok());
         assert_eq!(claims, decoded);
         assert_eq!(claims.metadata.unwrap().valid_signers.unwrap().len(), 1);
@@ -1163,6 +3150,42 @@ mod test {
         }
     }
 
+    #[test]
+    fn validate_bad_token() {
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "test".to_string(),
+                Some(vec![MESSAGING.to_string(), KEY_VALUE.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: "test.wasm".to_string(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+
+        let encoded_nosep = claims.encode(&kp).unwrap().replace(".", "");
+
+        let vres = validate_token::<Actor>(&encoded_nosep);
+        assert!(vres.is_err());
+        if let Err(e) = vres {
+            match e.kind() {
+                ErrorKind::Token(s) => assert_eq!(s, "invalid token format"),
+                _ => {
+                    panic!("failed to assert errors::ErrorKind::Token");
+                }
+            }
+        }
+    }
+
     #[test]
     fn ensure_issuer_on_token() {
         let kp = KeyPair::new_account();
@@ -10,12 +10,13 @@
 //! The following example illustrates embedding a new set of claims
 //! into a WebAssembly module, then extracting, validating, and examining those claims.
 //! ```rust
+//!# #[cfg(feature = "std")]
+//!# fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 //!use wascap::prelude::*;
 //!
 //!# fn read_unsigned_wasm() -> Vec<u8> {
 //!#   include_bytes!("../examples/loop.wasm").to_vec()
 //!# }
-//!# fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 //! let unsigned = read_unsigned_wasm(); // Read a Wasm file into a byte vector
 //! let issuer = KeyPair::new_account(); // Create an Ed25519 key pair to sign the module
 //! let module = KeyPair::new_module(); // Create a key pair for the module itself
@@ -34,8 +35,8 @@
 //! // Sign the JWT and embed it into the WebAssembly module, returning the signed bytes
 //! let embedded = wasm::embed_claims(&unsigned, &claims, &issuer)?;
 //!
-//! // Extract a signed JWT from a WebAssembly module's bytes (performs a check on
-//! // the signed module hash)
+//! // Extract a signed JWT from a WebAssembly module's bytes (verifies the JWT's
+//! // signature and dates, and checks the signed module hash)
 //! let extracted = wasm::extract_claims(&embedded)?.unwrap();
 //!
 //! // Validate dates, signature, JWT structure, etc.
@@ -49,31 +50,63 @@
 //!
 //!# Ok(())
 //!# }
+//!# #[cfg(not(feature = "std"))]
+//!# fn main() {}
 //! ```
 //!
 //! The `Ed25519` key functionality is provided by the [nkeys](https://docs.rs/nkeys) crate.
+//!
+//! Everything but the claim types in the `core` module requires the `std` feature, which is
+//! enabled by default. Without it the crate is `no_std` and only needs `alloc`, so claims can
+//! still be decoded and inspected, but not signed or verified.
+//!
+//! The `tokio` feature adds `wasm::embed_claims_async`, which signs modules on Tokio's blocking
+//! thread pool, and the `rayon` feature adds `wasm::sign_batch`, which signs many modules in
+//! parallel. The `reqwest` feature adds `wasm::verify_url`, which downloads a module, up to a
+//! size limit, and verifies its claims, and the `rekor` feature adds
+//! `wasm::verify_with_transparency`, which also checks that the signing was recorded in a Rekor
+//! transparency log.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
 #[macro_use]
 extern crate serde_derive;
 
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate lazy_static;
 
 /// Wascap-specific result type
-pub type Result<T> = std::result::Result<T, errors::Error>;
+pub type Result<T> = ::core::result::Result<T, errors::Error>;
 pub use errors::Error;
 
+#[cfg(feature = "std")]
 pub mod caps;
+pub mod core;
 mod errors;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "std")]
 pub mod jwt;
+#[cfg(feature = "mnemonic")]
+pub mod mnemonic;
+#[cfg(feature = "rekor")]
+mod rekor;
+#[cfg(feature = "std")]
 pub mod wasm;
 
+#[cfg(feature = "std")]
 pub mod prelude {
     //! Public re-exports of the most commonly used wascap types
     pub use super::{Error as WascapError, Result as WascapResult};
     pub use crate::{
         caps,
-        jwt::{validate_token, Account, Actor, Claims, ClaimsBuilder, Invocation, Operator},
+        jwt::{
+            validate_token, Account, Actor, Claims, ClaimsBuilder, HashAlgorithm, Invocation,
+            Operator, WascapSigner,
+        },
         wasm,
     };
     pub use nkeys::KeyPair;
@@ -0,0 +1,175 @@
+//! Derivation of key pairs from BIP39 mnemonic phrases, for keys kept in mnemonic form rather
+//! than as nkeys seeds. Phrases are read against the standard English BIP39 word list, which is
+//! bundled with the crate
+
+use crate::{errors, errors::ErrorKind, Result};
+use nkeys::{KeyPair, KeyPairType};
+use ring::{
+    digest::{digest, SHA256},
+    pbkdf2,
+};
+use std::num::NonZeroU32;
+use unicode_normalization::UnicodeNormalization;
+
+// The English list from the BIP39 repository, one word per line in index order
+const ENGLISH: &str = include_str!("mnemonic/english.txt");
+const PBKDF2_ROUNDS: u32 = 2048;
+
+/// Derives a key pair of the given type from a BIP39 mnemonic phrase. The phrase is NFKD
+/// normalized and checked against the English word list and its checksum, then turned into a
+/// BIP39 seed (with an empty passphrase) whose first 32 bytes are used as the Ed25519 seed of
+/// the key pair. The same phrase always produces the same key pair
+///
+/// # Errors
+/// Returns an `InvalidMnemonic` error if the phrase doesn't have 12, 15, 18, 21 or 24 words,
+/// contains a word that isn't on the list, or fails its checksum
+pub fn keypair_from_mnemonic(phrase: &str, prefix: KeyPairType) -> Result<KeyPair> {
+    let seed = mnemonic_to_seed(phrase, "")?;
+    let mut raw = [0; 32];
+    raw.copy_from_slice(&seed[..32]);
+    Ok(KeyPair::new_from_raw(prefix, raw)?)
+}
+
+fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> Result<[u8; 64]> {
+    let phrase: String = phrase.nfkd().collect();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    if ![12, 15, 18, 21, 24].contains(&words.len()) {
+        return Err(invalid(&format!(
+            "expected 12, 15, 18, 21 or 24 words, found {}",
+            words.len()
+        )));
+    }
+
+    // Every word encodes 11 bits: the entropy followed by a checksum of one bit per 32 bits
+    // of entropy, taken from the start of its SHA-256 digest
+    let mut bits = Vec::with_capacity(words.len() * 11);
+    for word in &words {
+        let index = ENGLISH
+            .lines()
+            .position(|w| w == *word)
+            .ok_or_else(|| invalid(&format!("unknown word '{}'", word)))?;
+        bits.extend((0..11).rev().map(|i| (index >> i) & 1 == 1));
+    }
+    let checksum_len = bits.len() / 33;
+    let (entropy_bits, checksum) = bits.split_at(bits.len() - checksum_len);
+    let entropy: Vec<u8> = entropy_bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, bit| (acc << 1) | *bit as u8))
+        .collect();
+    let hash = digest(&SHA256, &entropy);
+    let expected = (0..checksum_len).map(|i| (hash.as_ref()[i / 8] >> (7 - i % 8)) & 1 == 1);
+    if !expected.eq(checksum.iter().copied()) {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let salt: String = "mnemonic".chars().chain(passphrase.nfkd()).collect();
+    let mut seed = [0; 64];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA512,
+        NonZeroU32::new(PBKDF2_ROUNDS).unwrap(),
+        salt.as_bytes(),
+        words.join(" ").as_bytes(),
+        &mut seed,
+    );
+    Ok(seed)
+}
+
+fn invalid(reason: &str) -> errors::Error {
+    errors::new(ErrorKind::InvalidMnemonic(reason.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon abandon abandon about";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn bundled_word_list() {
+        let words: Vec<&str> = ENGLISH.lines().collect();
+        assert_eq!(words.len(), 2048);
+        assert_eq!(words[0], "abandon");
+        assert_eq!(words[2047], "zoo");
+        assert!(words.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    // From the reference vectors, which all use the passphrase "TREZOR":
+    // https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+    #[test]
+    fn reference_vectors() {
+        let vectors = [
+            (
+                PHRASE,
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a69875\
+                 99d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+                "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f\
+                 0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+            ),
+            (
+                "legal winner thank year wave sausage worth useful legal winner thank year \
+                 wave sausage worth useful legal will",
+                "f2b94508732bcbacbcc020faefecfc89feafa6649a5491b8c952cede496c214a0c7b3c392d\
+                 168748f2d4a612bada0753b52a1c7ac53c1e93abd5c6320b9e95dd",
+            ),
+            (
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                 abandon abandon abandon abandon abandon art",
+                "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73\
+                 245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+            ),
+        ];
+        for (phrase, seed) in &vectors {
+            assert_eq!(hex(&mnemonic_to_seed(phrase, "TREZOR").unwrap()), *seed);
+        }
+    }
+
+    #[test]
+    fn derive_from_mnemonic() {
+        let kp = keypair_from_mnemonic(PHRASE, KeyPairType::Account).unwrap();
+        assert!(kp.public_key().starts_with('A'));
+        // The first half of the BIP39 seed of the phrase with an empty passphrase
+        let expected = KeyPair::new_from_raw(
+            KeyPairType::Account,
+            [
+                0x5e, 0xb0, 0x0b, 0xbd, 0xdc, 0xf0, 0x69, 0x08, 0x48, 0x89, 0xa8, 0xab, 0x91, 0x55,
+                0x56, 0x81, 0x65, 0xf5, 0xc4, 0x53, 0xcc, 0xb8, 0x5e, 0x70, 0x81, 0x1a, 0xae, 0xd6,
+                0xf6, 0xda, 0x5f, 0xc1,
+            ],
+        )
+        .unwrap();
+        assert_eq!(kp.public_key(), expected.public_key());
+
+        // Compatibility forms such as full-width letters normalize to the same phrase
+        let full_width: String = PHRASE
+            .chars()
+            .map(|c| match c {
+                'a'..='z' => char::from_u32(c as u32 - 'a' as u32 + 0xff41).unwrap(),
+                c => c,
+            })
+            .collect();
+        let kp = keypair_from_mnemonic(&full_width, KeyPairType::Account).unwrap();
+        assert_eq!(kp.public_key(), expected.public_key());
+
+        let bad_checksum = PHRASE.replace("about", "abandon");
+        let too_short = "abandon abandon abandon";
+        let unknown = PHRASE.replace("about", "zebras");
+        for bad in &[bad_checksum.as_str(), too_short, unknown.as_str()] {
+            match keypair_from_mnemonic(bad, KeyPairType::Account) {
+                Err(e) => match e.kind() {
+                    ErrorKind::InvalidMnemonic(_) => (),
+                    _ => panic!("failed to assert errors::ErrorKind::InvalidMnemonic"),
+                },
+                Ok(_) => panic!("derived a key pair from an invalid mnemonic"),
+            }
+        }
+    }
+}
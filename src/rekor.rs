@@ -0,0 +1,287 @@
+//! Looks up entries in a Sigstore Rekor transparency log over its REST API, and checks that they
+//! are included in the log with their inclusion proofs, as defined by RFC 9162
+
+use crate::{errors, errors::ErrorKind, Result};
+use data_encoding::HEXLOWER_PERMISSIVE;
+use reqwest::{header::CONTENT_TYPE, Client};
+use ring::digest::{Context, SHA256};
+use serde_json::{json, Value};
+use std::{collections::HashMap, convert::TryFrom};
+
+type Hash = [u8; 32];
+
+#[derive(Deserialize)]
+struct LogEntry {
+    body: String,
+    verification: Option<Verification>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Verification {
+    inclusion_proof: Option<InclusionProof>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionProof {
+    log_index: u64,
+    tree_size: u64,
+    root_hash: String,
+    hashes: Vec<String>,
+}
+
+/// Looks up the entries the log at `rekor_url` holds for a SHA-256 digest, given in the
+/// `sha256:<hex>` form the log indexes entries by. Returns `false` if there are none, and `true`
+/// if one of them records the digest and its inclusion proof leads to the log's root hash. The
+/// root hash is the one the log reports alongside the proof; its signed checkpoint isn't
+/// verified against the log's public key
+///
+/// # Errors
+/// Returns an `InclusionProofInvalid` error if the log holds entries for the digest but none of
+/// them is proven to be included, or an `Http` error if the log can't be queried
+pub(crate) async fn is_included(rekor_url: &str, digest: &str) -> Result<bool> {
+    let client = Client::new();
+    let base = rekor_url.trim_end_matches('/');
+    let uuids: Vec<String> = serde_json::from_slice(
+        &client
+            .post(format!("{}/api/v1/index/retrieve", base))
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&json!({ "hash": digest }))?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?,
+    )?;
+
+    let mut failure = None;
+    for uuid in uuids {
+        let entries: HashMap<String, LogEntry> = serde_json::from_slice(
+            &client
+                .get(format!("{}/api/v1/log/entries/{}", base, uuid))
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?,
+        )?;
+        for entry in entries.values() {
+            match check_entry(entry, digest) {
+                Ok(()) => return Ok(true),
+                Err(e) => failure = Some(e),
+            }
+        }
+    }
+    failure.map_or(Ok(false), Err)
+}
+
+// Checks that an entry records the given digest, and that its inclusion proof holds
+fn check_entry(entry: &LogEntry, digest: &str) -> Result<()> {
+    let body = base64::decode(&entry.body).map_err(|_| invalid("the entry body isn't base64"))?;
+    let recorded: Value = serde_json::from_slice(&body)?;
+    let hex = digest.trim_start_matches("sha256:");
+    let matches = recorded
+        .pointer("/spec/data/hash/value")
+        .and_then(Value::as_str)
+        .is_some_and(|value| value.eq_ignore_ascii_case(hex));
+    if !matches {
+        return Err(invalid(
+            "the entry doesn't record the digest it was looked up by",
+        ));
+    }
+
+    let proof = entry
+        .verification
+        .as_ref()
+        .and_then(|verification| verification.inclusion_proof.as_ref())
+        .ok_or_else(|| invalid("the entry has no inclusion proof"))?;
+    let path = proof
+        .hashes
+        .iter()
+        .map(|hash| decode_hash(hash))
+        .collect::<Result<Vec<_>>>()?;
+    let root = root_from_inclusion_proof(proof.log_index, proof.tree_size, hash_leaf(&body), &path);
+    if root != Some(decode_hash(&proof.root_hash)?) {
+        return Err(invalid("the inclusion proof doesn't lead to the root hash"));
+    }
+    Ok(())
+}
+
+// Computes the root hash of a tree of `size` leaves from the hash of the leaf at `index` and its
+// audit path, following section 2.1.3.2 of RFC 9162. Returns `None` if the path doesn't fit the
+// position of the leaf in the tree
+fn root_from_inclusion_proof(index: u64, size: u64, leaf: Hash, path: &[Hash]) -> Option<Hash> {
+    if index >= size {
+        return None;
+    }
+    let (mut fnode, mut snode) = (index, size - 1);
+    let mut root = leaf;
+    for sibling in path {
+        if snode == 0 {
+            return None;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            root = hash_children(sibling, &root);
+            while fnode & 1 == 0 && fnode != 0 {
+                fnode >>= 1;
+                snode >>= 1;
+            }
+        } else {
+            root = hash_children(&root, sibling);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    if snode == 0 {
+        Some(root)
+    } else {
+        None
+    }
+}
+
+fn hash_leaf(data: &[u8]) -> Hash {
+    hash(&[&[0], data])
+}
+
+fn hash_children(left: &Hash, right: &Hash) -> Hash {
+    hash(&[&[1], left, right])
+}
+
+fn hash(parts: &[&[u8]]) -> Hash {
+    let mut context = Context::new(&SHA256);
+    for part in parts {
+        context.update(part);
+    }
+    let mut hash = [0; 32];
+    hash.copy_from_slice(context.finish().as_ref());
+    hash
+}
+
+fn decode_hash(hex: &str) -> Result<Hash> {
+    HEXLOWER_PERMISSIVE
+        .decode(hex.as_bytes())
+        .ok()
+        .and_then(|bytes| Hash::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| invalid("a hash in the inclusion proof isn't a hex SHA-256 digest"))
+}
+
+fn invalid(reason: &str) -> errors::Error {
+    errors::new(ErrorKind::InclusionProofInvalid(reason.to_string()))
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    // The root hash of a tree with the given leaves, as defined by section 2.1.1 of RFC 9162
+    pub(crate) fn tree_root(leaves: &[Vec<u8>]) -> Hash {
+        match leaves.len() {
+            0 => hash(&[]),
+            1 => hash_leaf(&leaves[0]),
+            n => {
+                let k = split(n);
+                hash_children(&tree_root(&leaves[..k]), &tree_root(&leaves[k..]))
+            }
+        }
+    }
+
+    // The audit path of the leaf at `index`, as defined by section 2.1.3.1 of RFC 9162
+    pub(crate) fn audit_path(index: usize, leaves: &[Vec<u8>]) -> Vec<Hash> {
+        if leaves.len() <= 1 {
+            return Vec::new();
+        }
+        let k = split(leaves.len());
+        let (mut path, sibling) = if index < k {
+            (audit_path(index, &leaves[..k]), tree_root(&leaves[k..]))
+        } else {
+            (audit_path(index - k, &leaves[k..]), tree_root(&leaves[..k]))
+        };
+        path.push(sibling);
+        path
+    }
+
+    // The largest power of two smaller than `n`
+    fn split(n: usize) -> usize {
+        let mut k = 1;
+        while k * 2 < n {
+            k *= 2;
+        }
+        k
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        let leaves: Vec<Vec<u8>> = (0..9u8).map(|i| vec![i; i as usize + 1]).collect();
+        for size in 1..=leaves.len() {
+            let tree = &leaves[..size];
+            let root = tree_root(tree);
+            for (index, leaf) in tree.iter().enumerate() {
+                let path = audit_path(index, tree);
+                let (index, size) = (index as u64, size as u64);
+                let leaf = hash_leaf(leaf);
+                assert_eq!(
+                    root_from_inclusion_proof(index, size, leaf, &path),
+                    Some(root)
+                );
+
+                // The same path can't prove the leaf at another position
+                let moved = root_from_inclusion_proof(index ^ 1, size.max(2), leaf, &path);
+                assert_ne!(moved, Some(root));
+                assert_eq!(root_from_inclusion_proof(size, size, leaf, &path), None);
+                if let Some((last, rest)) = path.split_last() {
+                    let mut tampered = rest.to_vec();
+                    tampered.push(hash_leaf(last));
+                    assert_ne!(
+                        root_from_inclusion_proof(index, size, leaf, &tampered),
+                        Some(root)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn entry_must_record_digest() {
+        let digest = format!("sha256:{}", "ab".repeat(32));
+        let body = serde_json::to_vec(&json!({
+            "kind": "hashedrekord",
+            "spec": { "data": { "hash": { "algorithm": "sha256", "value": "ab".repeat(32) } } },
+        }))
+        .unwrap();
+        let leaves = vec![b"first".to_vec(), body.clone(), b"third".to_vec()];
+        let entry = |body: &[u8], root: Hash| LogEntry {
+            body: base64::encode(body),
+            verification: Some(Verification {
+                inclusion_proof: Some(InclusionProof {
+                    log_index: 1,
+                    tree_size: 3,
+                    root_hash: HEXLOWER_PERMISSIVE.encode(&root),
+                    hashes: audit_path(1, &leaves)
+                        .iter()
+                        .map(|hash| HEXLOWER_PERMISSIVE.encode(hash))
+                        .collect(),
+                }),
+            }),
+        };
+        let root = tree_root(&leaves);
+        assert!(check_entry(&entry(&body, root), &digest).is_ok());
+
+        let other = format!("sha256:{}", "cd".repeat(32));
+        let mut wrong_root = root;
+        wrong_root[0] ^= 1;
+        for (entry, digest) in &[
+            (entry(&body, root), &other),
+            (entry(&body, wrong_root), &digest),
+            (entry(b"{}", root), &digest),
+        ] {
+            match check_entry(entry, digest) {
+                Err(e) => match e.kind() {
+                    ErrorKind::InclusionProofInvalid(_) => (),
+                    _ => panic!("failed to assert errors::ErrorKind::InclusionProofInvalid"),
+                },
+                Ok(_) => panic!("accepted an entry that isn't proven to record the digest"),
+            }
+        }
+    }
+}
@@ -1,267 +1,5317 @@
 //! Functions for extracting and embedding claims within a WebAssembly module
 
 use crate::{
+    caps,
     errors::{self, ErrorKind},
-    jwt::{Actor, Claims, Token, MIN_WASCAP_INTERNAL_REVISION},
+    jwt::{
+        decode_module_hash, is_key_of_type, since_the_epoch, since_the_epoch_with,
+        validate_token_with_clock, Actor, Claims, ClaimsBuilder, Clock, HashAlgorithm, HashScope,
+        RevocationList, SystemClock, Token, ValidationOptions, WascapEntity, WascapSigner,
+        MIN_WASCAP_INTERNAL_REVISION,
+    },
     Result,
 };
+#[cfg(feature = "rekor")]
+use data_encoding::HEXLOWER;
 use data_encoding::HEXUPPER;
 use nkeys::KeyPair;
-use ring::digest::{Context, Digest, SHA256};
+#[cfg(feature = "rekor")]
+use ring::digest::digest;
+use ring::{
+    constant_time,
+    digest::{Context, SHA256, SHA512},
+};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
-    io::Read,
-    time::{SystemTime, UNIX_EPOCH},
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+    time::Duration,
 };
-use wasmparser::BinaryReaderError;
-use wasmparser::Payload::*;
+use wasm_encoder::{Encode, RawSection, Section};
+use wasmparser::{Chunk, CustomSectionReader, Encoding, Parser, Payload, Payload::*};
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+const PREAMBLE_LEN: usize = 8;
+// Core modules, the experimental module version and components
+const SUPPORTED_VERSIONS: [u32; 3] = [0x1, 0xd, 0x0001_000a];
 const SECS_PER_DAY: u64 = 86400;
+const SECS_PER_HOUR: u64 = 3600;
+const SECS_PER_MINUTE: u64 = 60;
 const SECTION_JWT: &str = "jwt";
 const SECTION_WC_JWT: &str = "wasmcloud_jwt";
+const SECTION_COSIGNATURE: &str = "wasmcloud_jwt_cosignature";
+
+/// The largest JWT section, in bytes, that the extraction functions accept unless a different
+/// limit is given to `extract_claims_with_max_size`. Real tokens are a few kilobytes at most
+pub const DEFAULT_MAX_JWT_SECTION_SIZE: usize = 64 * 1024;
+
+/// The largest module, in bytes, that `extract_claims_gz` decompresses unless a different limit
+/// is given to `extract_claims_gz_with_max_size`
+#[cfg(feature = "gzip")]
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 256 * 1024 * 1024;
 
 /// Extracts a set of claims from the raw bytes of a WebAssembly module. In the case where no
 /// JWT is discovered in the module, this function returns `None`.
-/// If there is a token in the file with a valid hash, then you will get a `Token` back
-/// containing both the raw JWT and the decoded claims.
+/// If there is a token in the file that is signed by its issuer, currently valid and has a valid
+/// hash, then you will get a `Token` back containing both the raw JWT and the decoded claims.
 ///
 /// # Errors
 /// Will return an error if hash computation fails or it can't read the JWT from inside
-/// a section's data, etc
+/// a section's data, etc. The JWT is decoded with `Claims::decode`, so unsigned and forged tokens
+/// are rejected with a `SignatureMissing` or `SignatureInvalid` error, expired tokens with a
+/// `TokenExpired` error and tokens that can't be used yet with a `TokenTooEarly` error. Use
+/// `extract_claims_unverified` to read them regardless. If the module contains more than one
+/// distinct JWT, this function returns a `MultipleTokens` error rather than picking one of them,
+/// and if the JWT has no actor metadata it returns a `MissingMetadata` error. A JWT section
+/// larger than `DEFAULT_MAX_JWT_SECTION_SIZE` is rejected with a `JwtSectionTooLarge` error. A
+/// module hash that doesn't match is reported as an `InvalidModuleHash` error, or a
+/// `MalformedHash` error if the signed hash isn't a hex digest of the right length for its
+/// algorithm. Empty input is rejected with an `EmptyModule` error, and input that doesn't start
+/// with the magic number and a supported version with a `NotAWasmModule` error
+///
+/// Every JWT section is read wherever it appears among the other sections, so reordering the
+/// sections, e.g. moving the `name` section after the claims, doesn't change the result.
+///
+/// For components, only JWT sections at the top level of the component are considered. Tokens
+/// embedded in any nested modules or components belong to those and are ignored
 pub fn extract_claims(contents: impl AsRef<[u8]>) -> Result<Option<Token<Actor>>> {
-    let parser = wasmparser::Parser::new(0);
-    for payload in parser.parse_all(contents.as_ref()) {
-        match payload? {
-            wasmparser::Payload::CustomSection(reader) => {
-                if reader.name() == SECTION_JWT || reader.name() == SECTION_WC_JWT {
-                    let jwt = String::from_utf8(reader.data().to_vec())?;
-                    let claims: Claims<Actor> = Claims::decode(&jwt)?;
-                    let hash = compute_hash_without_jwt(contents.as_ref())?;
-                    if let Some(ref meta) = claims.metadata {
-                        if meta.module_hash != hash
-                            && claims.wascap_revision.unwrap_or_default()
-                                >= MIN_WASCAP_INTERNAL_REVISION
-                        {
-                            return Err(errors::new(ErrorKind::InvalidModuleHash));
-                        } else {
-                            return Ok(Some(Token { jwt, claims }));
-                        }
-                    } else {
-                        return Err(errors::new(ErrorKind::InvalidAlgorithm));
-                    }
-                }
+    Ok(extract_claims_located(contents)?.map(|(token, _)| token))
+}
+
+/// Extracts a set of claims from the raw bytes of a WebAssembly module in the same way as
+/// `extract_claims`, but without verifying the JWT's signature or dates, e.g. to show what an
+/// expired or unsigned module claims. **Nothing about the claims can be trusted**; only the module
+/// hash is checked
+pub fn extract_claims_unverified(contents: impl AsRef<[u8]>) -> Result<Option<Token<Actor>>> {
+    Ok(extract_located(
+        contents.as_ref(),
+        DEFAULT_MAX_JWT_SECTION_SIZE,
+        Claims::decode_unverified,
+    )?
+    .map(|(token, _)| token))
+}
+
+/// The checks `full_report` makes on a module's claims
+#[derive(Clone)]
+pub struct VerifyOptions<'a> {
+    /// The account keys trusted to issue claims. `None` trusts any issuer
+    pub trusted_issuers: Option<Vec<String>>,
+    /// The clock the expiration and not-valid-before times are checked against. It's shared
+    /// with the future returned by `verify_url`, so it must be `Sync`
+    pub clock: &'a (dyn Clock + Sync),
+    /// When set, module hashes that can't be verified because the claims were signed by an
+    /// older version of wascap fail the hash check, as they do in `extract_claims_strict`
+    pub strict: bool,
+    /// When set, modules that declare a start function, which runs as soon as the module is
+    /// instantiated, are refused outright. This includes the modules nested in a component, as
+    /// well as components with a start function of their own
+    pub forbid_start: bool,
+    /// When set, claims signed with a wascap revision below this one are refused outright, e.g.
+    /// to require a signing toolchain with a security fix. Claims that don't record a revision
+    /// are treated as revision 0
+    pub min_revision: Option<u32>,
+}
+
+impl Default for VerifyOptions<'_> {
+    fn default() -> Self {
+        VerifyOptions {
+            trusted_issuers: None,
+            clock: &SystemClock,
+            strict: false,
+            forbid_start: false,
+            min_revision: None,
+        }
+    }
+}
+
+/// The outcome of each of the checks made by `full_report`. Every check is `None` when it
+/// passed, or the reason it failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerificationReport {
+    /// The JWT and claims embedded in the module
+    pub token: Token<Actor>,
+    /// Whether the JWT is signed by its issuer
+    pub signature: Option<String>,
+    /// Whether the module hash in the claims matches the module
+    pub module_hash: Option<String>,
+    /// Whether the claims have expired
+    pub expiry: Option<String>,
+    /// Whether the claims can be used yet
+    pub not_before: Option<String>,
+    /// Whether the issuer is one of the trusted issuers
+    pub issuer: Option<String>,
+}
+
+impl VerificationReport {
+    /// Indicates whether every check passed
+    pub fn is_valid(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// The reasons for each failed check, in the order the checks appear in the report
+    pub fn failures(&self) -> Vec<&str> {
+        [
+            &self.signature,
+            &self.module_hash,
+            &self.expiry,
+            &self.not_before,
+            &self.issuer,
+        ]
+        .iter()
+        .filter_map(|check| check.as_deref())
+        .collect()
+    }
+}
+
+/// Checks the claims embedded in a module in the same ways that the `extract_claims` family of
+/// functions do, but makes every check rather than stopping at the first failure, reporting
+/// the outcome of each
+///
+/// # Errors
+/// Returns a `NoEmbeddedClaims` error if the module isn't signed, or an error if the module
+/// can't be parsed or its JWT decoded. Failed checks are reported rather than returned as errors,
+/// except for a start function forbidden by `forbid_start`, which is a `StartFunctionForbidden`
+/// error, and a revision below `min_revision`, which is a `RevisionTooOld` error
+pub fn full_report(contents: &[u8], opts: &VerifyOptions) -> Result<VerificationReport> {
+    let (jwt, _) = locate_jwt(contents, DEFAULT_MAX_JWT_SECTION_SIZE)?
+        .ok_or_else(|| errors::new(ErrorKind::NoEmbeddedClaims))?;
+    if opts.forbid_start && has_start_function(contents)? {
+        return Err(errors::new(ErrorKind::StartFunctionForbidden));
+    }
+    let claims: Claims<Actor> = Claims::decode_unverified(&jwt)?;
+    if let Some(min) = opts.min_revision {
+        let revision = claims.wascap_revision.unwrap_or_default();
+        if revision < min {
+            return Err(errors::new(ErrorKind::RevisionTooOld { revision, min }));
+        }
+    }
+    let now = since_the_epoch_with(opts.clock)?.as_secs();
+    let reason = |kind| Some(errors::new(kind).to_string());
+
+    let signature = match validate_token_with_clock::<Actor>(&jwt, opts.clock) {
+        Ok(validation) if validation.signature_valid => None,
+        Ok(_) => reason(ErrorKind::SignatureInvalid),
+        Err(e) => Some(e.to_string()),
+    };
+    let (hash_verified, module_hash) = match claims.metadata {
+        Some(ref meta) => {
+            match compute_module_hash_with_scope(
+                contents,
+                meta.hash_algorithm(),
+                &meta.hash_scope(),
+            )
+            .and_then(|hash| verify_module_hash(&claims, &hash))
+            {
+                Ok(true) => (true, None),
+                Ok(false) if opts.strict => (false, reason(ErrorKind::InvalidModuleHash)),
+                Ok(false) => (false, None),
+                Err(e) => (false, Some(e.to_string())),
             }
-            _ => {}
         }
+        None => (false, reason(ErrorKind::MissingMetadata)),
+    };
+    let expiry = match claims.expires {
+        Some(exp) if claims.expired_at(now) => reason(ErrorKind::TokenExpired(exp)),
+        _ => None,
+    };
+    let not_before = if claims.cannot_use_yet_at(now) {
+        reason(ErrorKind::TokenTooEarly)
+    } else {
+        None
+    };
+    let issuer = match opts.trusted_issuers {
+        Some(ref trusted) if !trusted.contains(&claims.issuer) => {
+            reason(ErrorKind::UntrustedIssuer(claims.issuer.clone()))
+        }
+        _ => None,
+    };
+
+    Ok(VerificationReport {
+        token: Token {
+            jwt,
+            claims,
+            hash_verified,
+        },
+        signature,
+        module_hash,
+        expiry,
+        not_before,
+        issuer,
+    })
+}
+
+/// Downloads a module and verifies its claims, making the checks of `full_report` and
+/// returning the first one that fails as an error. The body is streamed, and the download is
+/// abandoned as soon as it's known to be larger than `max_bytes`, whether from its
+/// `Content-Length` or from the bytes received, so an oversized artifact can't exhaust memory
+/// before it's verified
+///
+/// # Errors
+/// Returns an `ArtifactTooLarge` error if the body is larger than `max_bytes`, an `Http` error if
+/// the request fails or the response status isn't a success, a `NoEmbeddedClaims` error if the
+/// module isn't signed, or, for the first failing check, a `StartFunctionForbidden`,
+/// `RevisionTooOld`, `SignatureInvalid`, `InvalidModuleHash`, `TokenExpired`, `TokenTooEarly` or
+/// `UntrustedIssuer` error
+#[cfg(feature = "reqwest")]
+pub async fn verify_url(
+    url: &str,
+    max_bytes: usize,
+    opts: &VerifyOptions<'_>,
+) -> Result<Token<Actor>> {
+    let too_large = || errors::new(ErrorKind::ArtifactTooLarge(max_bytes));
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let mut contents = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if chunk.len() > max_bytes - contents.len() {
+            return Err(too_large());
+        }
+        contents.extend_from_slice(&chunk);
     }
-    Ok(None)
+    verify(&contents, opts)
 }
 
-/// This function will embed a set of claims inside the bytecode of a WebAssembly module. The claims
-/// are converted into a JWT and signed using the provided `KeyPair`.
-/// According to the WebAssembly [custom section](https://webassembly.github.io/spec/core/appendix/custom.html)
-/// specification, arbitary sets of bytes can be stored in a WebAssembly module without impacting
-/// parsers or interpreters. Returns a vector of bytes representing the new WebAssembly module which can
-/// be saved to a `.wasm` file
-pub fn embed_claims(orig_bytecode: &[u8], claims: &Claims<Actor>, kp: &KeyPair) -> Result<Vec<u8>> {
-    let mut bytes = orig_bytecode.to_vec();
+// Makes the checks of `full_report`, failing with the first one that doesn't pass
+#[cfg(feature = "reqwest")]
+fn verify(contents: &[u8], opts: &VerifyOptions) -> Result<Token<Actor>> {
+    let report = full_report(contents, opts)?;
+    let claims = &report.token.claims;
+    let failure = if report.signature.is_some() {
+        ErrorKind::SignatureInvalid
+    } else if report.module_hash.is_some() {
+        match claims.metadata {
+            Some(_) => ErrorKind::InvalidModuleHash,
+            None => ErrorKind::MissingMetadata,
+        }
+    } else if report.expiry.is_some() {
+        ErrorKind::TokenExpired(claims.expires.unwrap_or_default())
+    } else if report.not_before.is_some() {
+        ErrorKind::TokenTooEarly
+    } else if report.issuer.is_some() {
+        ErrorKind::UntrustedIssuer(claims.issuer.clone())
+    } else {
+        return Ok(report.token);
+    };
+    Err(errors::new(failure))
+}
+
+/// Returns the issuer of the claims embedded in a module, without verifying the JWT's signature
+/// or computing the module hash, for cheaply indexing large numbers of modules. The issuer is
+/// untrusted: anyone can embed claims naming any issuer, so it mustn't be relied on until the
+/// claims have been verified, e.g. with `extract_claims`
+///
+/// # Errors
+/// Will return an error if the module can't be parsed or its JWT decoded
+pub fn peek_issuer(contents: &[u8]) -> Result<Option<String>> {
+    match locate_jwt(contents, DEFAULT_MAX_JWT_SECTION_SIZE)? {
+        // The metadata is never looked at, so it's decoded as whatever JSON it holds
+        Some((jwt, _)) => Ok(Some(
+            Claims::<serde_json::Value>::decode_unverified(&jwt)?.issuer,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn has_start_function(contents: &[u8]) -> Result<bool> {
+    for payload in Parser::new(0).parse_all(contents) {
+        if let StartSection { .. } | ComponentStartSection(_) = payload? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
 
-    let hash = compute_hash_without_jwt(orig_bytecode)?;
-    let mut claims = (*claims).clone();
-    let meta = claims.metadata.map(|md| Actor {
-        module_hash: hash,
-        ..md
-    });
-    claims.metadata = meta;
+/// Decodes the claims embedded in a module without verifying them, for inspecting modules that
+/// fail verification, e.g. after tampering. Alongside the claims, returns whether the module
+/// hash in them matches the module, rather than failing when it doesn't. Neither the signature
+/// nor the dates are checked; `validate_token` can be used on the JWT for that
+///
+/// # Errors
+/// Will return an error if the module can't be parsed or its JWT decoded
+pub fn peek_claims(contents: &[u8]) -> Result<Option<(Claims<Actor>, bool)>> {
+    let (jwt, _) = match locate_jwt(contents, DEFAULT_MAX_JWT_SECTION_SIZE)? {
+        Some(located) => located,
+        None => return Ok(None),
+    };
+    let claims: Claims<Actor> = Claims::decode_unverified(&jwt)?;
+    let hash_matches = match claims.metadata {
+        Some(ref meta) => match meta.module_hash() {
+            Some(signed) => {
+                let hash = compute_module_hash_with_scope(
+                    contents,
+                    meta.hash_algorithm(),
+                    &meta.hash_scope(),
+                )?;
+                // A malformed signed hash can't match any module
+                hashes_match(signed, &hash, meta.hash_algorithm()).unwrap_or(false)
+            }
+            None => false,
+        },
+        None => false,
+    };
+    Ok(Some((claims, hash_matches)))
+}
 
-    let encoded = claims.encode(kp)?;
-    let encvec = encoded.as_bytes().to_vec();
-    wasm_gen::write_custom_section(&mut bytes, SECTION_WC_JWT, &encvec);
+/// Extracts a set of claims from the raw bytes of a WebAssembly module in the same way as
+/// `extract_claims`, but always enforces the module hash. `extract_claims` tolerates a hash
+/// mismatch in tokens signed by wascap revisions prior to `MIN_WASCAP_INTERNAL_REVISION`, whose
+/// hashes can't be verified; this rejects them instead, for deployments that refuse anything
+/// unverifiable. Note that some genuinely old modules will fail this check even though they
+/// haven't been tampered with, because their hashes were computed by different rules
+///
+/// # Errors
+/// Returns a `SignatureMissing` or `SignatureInvalid` error if the JWT isn't signed by its
+/// issuer, an `InvalidModuleHash` error if the module hash in the claims doesn't match the
+/// module, whatever the revision of the token, or an error under the same conditions as
+/// `extract_claims`
+pub fn extract_claims_strict(contents: impl AsRef<[u8]>) -> Result<Option<Token<Actor>>> {
+    match extract_claims(contents)? {
+        Some(token) if !token.hash_verified => Err(errors::new(ErrorKind::InvalidModuleHash)),
+        token => Ok(token),
+    }
+}
 
-    Ok(bytes)
+/// Extracts a set of claims in the same way as `extract_claims`, additionally requiring
+/// the claims to be issued by one of the given account keys. An empty list trusts no issuer at
+/// all, so any embedded claims are rejected. A module without claims is `None`, as it is for
+/// `extract_claims`
+///
+/// # Errors
+/// Returns an `UntrustedIssuer` error if the issuer of the claims isn't in `trusted_issuers`, or
+/// an error under the same conditions as `extract_claims`
+pub fn extract_claims_trusted(
+    contents: &[u8],
+    trusted_issuers: &[String],
+) -> Result<Option<Token<Actor>>> {
+    match extract_claims(contents)? {
+        Some(token) if !trusted_issuers.contains(&token.claims.issuer) => {
+            Err(errors::new(ErrorKind::UntrustedIssuer(token.claims.issuer)))
+        }
+        token => Ok(token),
+    }
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn sign_buffer_with_claims(
-    name: String,
-    buf: impl AsRef<[u8]>,
-    mod_kp: KeyPair,
-    acct_kp: KeyPair,
-    expires_in_days: Option<u64>,
-    not_before_days: Option<u64>,
-    caps: Vec<String>,
-    tags: Vec<String>,
-    provider: bool,
-    rev: Option<i32>,
-    ver: Option<String>,
-    call_alias: Option<String>,
-) -> Result<Vec<u8>> {
-    let claims = Claims::<Actor>::with_dates(
-        name,
-        acct_kp.public_key(),
-        mod_kp.public_key(),
-        Some(caps),
-        Some(tags),
-        days_from_now_to_jwt_time(not_before_days),
-        days_from_now_to_jwt_time(expires_in_days),
-        provider,
-        rev,
-        ver,
-        call_alias,
+/// Extracts a set of claims in the same way as `extract_claims`, additionally requiring the
+/// issuer to be a trusted account whose keys may have been rotated. `key_history` maps each
+/// trusted account's canonical id to every public key it has signed with, including the current
+/// one, so modules signed before a rotation are still accepted without being re-signed. As with
+/// `extract_claims_trusted`, a module without claims is `None`
+///
+/// # Errors
+/// Returns an `UntrustedIssuer` error if the issuer of the claims isn't one of the keys in
+/// `key_history`, or an error under the same conditions as `extract_claims`
+pub fn extract_claims_with_key_history(
+    contents: &[u8],
+    key_history: &HashMap<String, Vec<String>>,
+) -> Result<Option<Token<Actor>>> {
+    match extract_claims(contents)? {
+        Some(token)
+            if !key_history
+                .values()
+                .any(|keys| keys.contains(&token.claims.issuer)) =>
+        {
+            Err(errors::new(ErrorKind::UntrustedIssuer(token.claims.issuer)))
+        }
+        token => Ok(token),
+    }
+}
+
+/// Extracts a set of claims in the same way as `extract_claims`, additionally making the checks
+/// enabled in the given `ValidationOptions`. The checks are made on claims whose signature has
+/// already been verified, so editing the times in the payload doesn't get claims past them
+///
+/// # Errors
+/// Returns an error if one of the enabled checks fails, or under the same conditions as
+/// `extract_claims`
+pub fn extract_claims_with_validation(
+    contents: impl AsRef<[u8]>,
+    opts: &ValidationOptions,
+) -> Result<Option<Token<Actor>>> {
+    let token = extract_claims(contents)?;
+    if let Some(ref token) = token {
+        opts.check(&token.claims)?;
+    }
+    Ok(token)
+}
+
+/// Extracts a set of claims in the same way as `extract_claims`, rejecting them if their subject
+/// or issuer is on the given revocation list. The list is only consulted once the signature has
+/// been verified, so edited claims can't dodge it with a fresh subject, issuer or `jti`
+///
+/// # Errors
+/// Returns a `Revoked` error if the claims have been revoked, or an error under the same
+/// conditions as `extract_claims`
+pub fn extract_claims_with_revocation(
+    contents: impl AsRef<[u8]>,
+    list: &RevocationList,
+) -> Result<Option<Token<Actor>>> {
+    match extract_claims(contents)? {
+        Some(token) if list.is_revoked(&token.claims) => Err(errors::new(ErrorKind::Revoked)),
+        token => Ok(token),
+    }
+}
+
+/// Extracts a set of claims in the same way as `extract_claims`, then checks that the signing
+/// was recorded in the Sigstore Rekor transparency log at `rekor_url`, e.g.
+/// `https://rekor.sigstore.dev`. The log is searched for entries by the SHA-256 digest of the
+/// JWT, and an entry only counts once its inclusion proof has been checked against the log's
+/// root hash. The log is only queried for claims that pass local verification
+///
+/// # Errors
+/// Returns a `NoEmbeddedClaims` error if the module isn't signed, a `NotInTransparencyLog` error
+/// if the log holds no entry for the JWT, an `InclusionProofInvalid` error if it holds entries
+/// but none of them is proven to be included, an `Http` error if the log can't be queried, or an
+/// error under the same conditions as `extract_claims`
+#[cfg(feature = "rekor")]
+pub async fn verify_with_transparency(contents: &[u8], rekor_url: &str) -> Result<Token<Actor>> {
+    let token =
+        extract_claims(contents)?.ok_or_else(|| errors::new(ErrorKind::NoEmbeddedClaims))?;
+    let entry = format!(
+        "sha256:{}",
+        HEXLOWER.encode(digest(&SHA256, token.jwt.as_bytes()).as_ref())
     );
-    embed_claims(buf.as_ref(), &claims, &acct_kp)
+    if crate::rekor::is_included(rekor_url, &entry).await? {
+        Ok(token)
+    } else {
+        Err(errors::new(ErrorKind::NotInTransparencyLog(entry)))
+    }
 }
 
-fn since_the_epoch() -> std::time::Duration {
-    let start = SystemTime::now();
-    start
-        .duration_since(UNIX_EPOCH)
-        .expect("A timey wimey problem has occurred!")
+/// Extracts a set of claims from the raw bytes of a WebAssembly module in the same way as
+/// `extract_claims`, with a different limit on the size of the JWT section than
+/// `DEFAULT_MAX_JWT_SECTION_SIZE`
+///
+/// # Errors
+/// Returns a `JwtSectionTooLarge` error if a JWT section holds more than `max_jwt_size` bytes,
+/// before its contents are copied, or an error under the same conditions as `extract_claims`
+pub fn extract_claims_with_max_size(
+    contents: impl AsRef<[u8]>,
+    max_jwt_size: usize,
+) -> Result<Option<Token<Actor>>> {
+    Ok(extract_located(contents.as_ref(), max_jwt_size, Claims::decode)?.map(|(token, _)| token))
 }
 
-pub fn days_from_now_to_jwt_time(stamp: Option<u64>) -> Option<u64> {
-    stamp.map(|e| since_the_epoch().as_secs() + e * SECS_PER_DAY)
+/// Extracts a set of claims from the raw bytes of a WebAssembly module in the same way as
+/// `extract_claims`, also returning the `[start, end)` byte offsets within the module of the
+/// custom section the JWT was read from. The range covers the section's contents (its name
+/// followed by the JWT) as reported by the parser, not the section id and size that precede it
+pub fn extract_claims_located(
+    contents: impl AsRef<[u8]>,
+) -> Result<Option<(Token<Actor>, Range<usize>)>> {
+    extract_located(
+        contents.as_ref(),
+        DEFAULT_MAX_JWT_SECTION_SIZE,
+        Claims::decode,
+    )
 }
 
-fn sha256_digest<R: Read>(mut reader: R) -> Result<Digest> {
-    let mut context = Context::new(&SHA256);
-    let mut buffer = [0; 1024];
+/// Checks that the module hash recorded in a module's embedded claims matches the module's
+/// contents, returning `false` if it doesn't or if the module has no embedded claims.
+///
+/// This does **not** authenticate the issuer: the JWT signature is neither verified nor required
+/// to be present, so anyone can produce claims that pass this check. It's only meant for detecting
+/// accidental corruption of a module whose claims have already been validated, e.g. when reloading
+/// it repeatedly during development. Use `validate_token` to verify the claims themselves
+pub fn verify_module_integrity(contents: &[u8]) -> Result<bool> {
+    match extract_located::<Actor>(
+        contents,
+        DEFAULT_MAX_JWT_SECTION_SIZE,
+        Claims::decode_unverified,
+    ) {
+        Ok(Some((token, _))) => Ok(token.hash_verified),
+        Ok(None) => Ok(false),
+        Err(e) => match e.kind() {
+            ErrorKind::InvalidModuleHash => Ok(false),
+            _ => Err(e),
+        },
+    }
+}
 
-    loop {
-        let count = reader.read(&mut buffer)?;
-        if count == 0 {
-            break;
+/// Extracts a set of claims of any embeddable type (e.g. `Actor` or `CapabilityProvider`) from
+/// the raw bytes of a WebAssembly module, verifying the embedded module hash in the same way
+/// as `extract_claims`
+pub fn extract_claims_generic<T>(contents: impl AsRef<[u8]>) -> Result<Option<Token<T>>>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    Ok(extract_located(
+        contents.as_ref(),
+        DEFAULT_MAX_JWT_SECTION_SIZE,
+        Claims::decode,
+    )?
+    .map(|(token, _)| token))
+}
+
+// Reads the embedded claims with the given decoding, which decides whether the JWT itself is
+// verified, and checks their module hash
+fn extract_located<T>(
+    contents: &[u8],
+    max_jwt_size: usize,
+    decode: fn(&str) -> Result<Claims<T>>,
+) -> Result<Option<(Token<T>, Range<usize>)>>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    let (jwt, range) = match locate_jwt(contents, max_jwt_size)? {
+        Some(located) => located,
+        None => return Ok(None),
+    };
+    let claims: Claims<T> = decode(&jwt)?;
+    let meta = claims
+        .metadata
+        .as_ref()
+        .ok_or_else(|| errors::new(ErrorKind::MissingMetadata))?;
+    let hash = compute_module_hash_with_scope(contents, meta.hash_algorithm(), &meta.hash_scope())?;
+    let hash_verified = verify_module_hash(&claims, &hash)?;
+
+    Ok(Some((
+        Token {
+            jwt,
+            claims,
+            hash_verified,
+        },
+        range,
+    )))
+}
+
+// Finds the JWT embedded at the top level of a module, without decoding or verifying it
+fn locate_jwt(contents: &[u8], max_jwt_size: usize) -> Result<Option<(String, Range<usize>)>> {
+    check_preamble(contents)?;
+    let mut jwt: Option<(String, Range<usize>)> = None;
+    for section in top_level_custom_sections(contents) {
+        let section = section?;
+        if is_jwt_section(section.name()) {
+            record_jwt(&mut jwt, &section, max_jwt_size)?;
         }
-        context.update(&buffer[..count]);
     }
-
-    Ok(context.finish())
+    Ok(jwt)
 }
 
-// NOTE: we don't need to compute a hash of the entire file, we just need
-// to compute the hash if the things that indicate tampering, like code and
-// custom sections
-fn compute_hash_without_jwt(modbytes: &[u8]) -> Result<String> {
-    let mut binary: Vec<u8> = Vec::new();
-    let parser = wasmparser::Parser::new(0);
+/// Extracts a set of claims from a WebAssembly module read from the given reader, in the same
+/// way as `extract_claims`. Rather than requiring the entire module in memory, the module is
+/// parsed incrementally and hashed as it is read, so only a single section (or, within the code
+/// section, a single function body) is buffered at a time.
+///
+/// If the claims were hashed with an algorithm other than SHA-256, the reader is rewound to
+/// the position it started at and read a second time to verify the hash
+///
+/// # Errors
+/// Will return an error if reading from the reader fails, or under the same conditions
+/// as `extract_claims`
+pub fn extract_claims_from_reader<R: Read + Seek>(mut reader: R) -> Result<Option<Token<Actor>>> {
+    let start = reader.stream_position()?;
+    extract_streamed(&mut reader, |reader| {
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(())
+    })
+}
 
-    for payload in parser.parse_all(modbytes) {
-        match payload? {
-            CodeSectionEntry(fb) => {
-                let mut rdr = fb.get_binary_reader();
-                let remaining = rdr.bytes_remaining();
-                binary.extend_from_slice(
-                    rdr.read_bytes(remaining)
-                        .map_err(|e| BinaryReaderError::from(e))?,
-                );
-            }
-            DataSection(mut reader) => {
-                binary
-                    .extend_from_slice(reader.read().map_err(|e| BinaryReaderError::from(e))?.data);
-            }
-            CustomSection(reader) => {
-                if reader.name() != SECTION_JWT && reader.name() != SECTION_WC_JWT {
-                    binary.extend_from_slice(reader.data());
-                }
+// Extracts claims from a module read once from `reader` and hashed as it's read. Claims hashed
+// with an algorithm or scope other than the default need a second pass over the module, which
+// `rewind` prepares the reader for
+fn extract_streamed<R: Read>(
+    reader: &mut R,
+    rewind: impl FnOnce(&mut R) -> Result<()>,
+) -> Result<Option<Token<Actor>>> {
+    let mut jwt: Option<(String, Range<usize>)> = None;
+    let mut hasher = ModuleHasher::new(HashAlgorithm::Sha256, HashScope::default());
+    stream_payloads(reader, |payload, bytes, offset| {
+        if let CustomSection(section) = payload {
+            if hasher.at_top_level() && is_jwt_section(section.name()) {
+                record_jwt(&mut jwt, section, DEFAULT_MAX_JWT_SECTION_SIZE)?;
             }
-            _ => {}
+        }
+        hasher.update(payload, bytes, offset)
+    })?;
+
+    let jwt = match jwt {
+        Some((jwt, _)) => jwt,
+        None => return Ok(None),
+    };
+    let claims: Claims<Actor> = Claims::decode(&jwt)?;
+    let meta = claims
+        .metadata
+        .as_ref()
+        .ok_or_else(|| errors::new(ErrorKind::MissingMetadata))?;
+    let hash = if meta.hash_algorithm == HashAlgorithm::Sha256
+        && meta.hash_scope == HashScope::default()
+    {
+        hasher.finish()
+    } else {
+        rewind(reader)?;
+        let mut hasher = ModuleHasher::new(meta.hash_algorithm(), meta.hash_scope());
+        stream_payloads(reader, |payload, bytes, offset| {
+            hasher.update(payload, bytes, offset)
+        })?;
+        hasher.finish()
+    };
+    let hash_verified = verify_module_hash(&claims, &hash)?;
+
+    Ok(Some(Token {
+        jwt,
+        claims,
+        hash_verified,
+    }))
+}
+
+/// Extracts a set of claims from a gzip-compressed WebAssembly module (e.g. a `.wasm.gz` file)
+/// read from the given reader, in the same way as `extract_claims_gz_with_max_size` with a limit
+/// of `DEFAULT_MAX_DECOMPRESSED_SIZE`
+///
+/// # Errors
+/// Returns an error under the same conditions as `extract_claims_gz_with_max_size`
+#[cfg(feature = "gzip")]
+pub fn extract_claims_gz<R: Read + Seek>(compressed: R) -> Result<Option<Token<Actor>>> {
+    extract_claims_gz_with_max_size(compressed, DEFAULT_MAX_DECOMPRESSED_SIZE)
+}
+
+/// Extracts a set of claims from a gzip-compressed WebAssembly module read from the given
+/// reader, refusing to decompress more than `max_size` bytes of it. The module is decompressed
+/// as it's read and parsed in the same way as `extract_claims_from_reader`, so it's never held
+/// in memory. Streams made of several gzip members, and zero padding after the last one, are
+/// accepted. The module hash in the claims always covers the uncompressed module, so the same
+/// claims verify whether or not the module is compressed.
+///
+/// If the claims were hashed with an algorithm other than SHA-256, the reader is rewound to
+/// the position it started at and the module is decompressed a second time to verify the hash
+///
+/// # Errors
+/// Returns a `Decompression` error if the data isn't a valid gzip stream, a
+/// `DecompressedTooLarge` error as soon as the module is found to be larger than `max_size`, or
+/// an error under the same conditions as `extract_claims_from_reader`
+#[cfg(feature = "gzip")]
+pub fn extract_claims_gz_with_max_size<R: Read + Seek>(
+    mut compressed: R,
+    max_size: usize,
+) -> Result<Option<Token<Actor>>> {
+    let start = compressed.stream_position()?;
+    let mut module = crate::gzip::GzipReader::new(std::io::BufReader::new(compressed), max_size);
+    extract_streamed(&mut module, |module| module.restart(start))
+}
+
+// nkeys encodes the type of a key in the first character of its public key, so this
+// makes sure actors are issued by an account and have a module as their subject
+fn validate_key_types(claims: &Claims<Actor>) -> Result<()> {
+    if is_key_of_type(&claims.issuer, 'A') && is_key_of_type(&claims.subject, 'M') {
+        Ok(())
+    } else {
+        Err(errors::new(ErrorKind::InvalidKeyType))
+    }
+}
+
+fn validate_capabilities(claims: &Claims<Actor>) -> Result<()> {
+    let caps = claims
+        .metadata
+        .iter()
+        .flat_map(|md| md.caps.iter().flatten());
+    match caps.into_iter().find(|cap| !caps::is_known_capability(cap)) {
+        Some(cap) => Err(errors::new(ErrorKind::UnknownCapability(cap.to_string()))),
+        None => Ok(()),
+    }
+}
+
+fn validate_dates<T: WascapEntity>(claims: &Claims<T>) -> Result<()> {
+    if let Some(exp) = claims.expires {
+        if exp <= claims.issued_at {
+            return Err(errors::new(ErrorKind::AlreadyExpired(exp)));
+        }
+        if let Some(nbf) = claims.not_before.filter(|nbf| *nbf > exp) {
+            log::warn!(
+                "Claims for {} become valid at {} but expire at {}, so they can never be used",
+                claims.subject,
+                nbf,
+                exp
+            );
         }
     }
+    Ok(())
+}
 
-    let digest = sha256_digest(binary.as_slice())?;
-    Ok(HEXUPPER.encode(digest.as_ref()))
+// Rejects input that can't possibly be a module before it reaches the parser, whose errors
+// for it are less helpful
+fn check_preamble(contents: &[u8]) -> Result<()> {
+    if contents.is_empty() {
+        return Err(errors::new(ErrorKind::EmptyModule));
+    }
+    let preamble = &contents[..contents.len().min(PREAMBLE_LEN)];
+    let supported = preamble.len() == PREAMBLE_LEN
+        && preamble[..4] == *WASM_MAGIC
+        && SUPPORTED_VERSIONS
+            .iter()
+            .any(|v| preamble[4..] == v.to_le_bytes());
+    if supported {
+        Ok(())
+    } else {
+        Err(errors::new(ErrorKind::NotAWasmModule(preamble.to_vec())))
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::{
-        caps::{KEY_VALUE, LOGGING, MESSAGING},
-        jwt::{Actor, Claims, WASCAP_INTERNAL_REVISION},
+fn is_jwt_section(name: &str) -> bool {
+    name == SECTION_JWT || name == SECTION_WC_JWT
+}
+
+// Sections holding claims of any kind, none of which are covered by the module hash
+fn is_claims_section(name: &str) -> bool {
+    is_jwt_section(name) || name == SECTION_COSIGNATURE
+}
+
+// Keeps track of the JWT found in a module and the section it was found in, rejecting a
+// second section that doesn't contain the exact same token
+fn record_jwt(
+    jwt: &mut Option<(String, Range<usize>)>,
+    section: &CustomSectionReader,
+    max_size: usize,
+) -> Result<()> {
+    if section.data().len() > max_size {
+        return Err(errors::new(ErrorKind::JwtSectionTooLarge {
+            size: section.data().len(),
+            max: max_size,
+        }));
+    }
+    let section_jwt = String::from_utf8(section.data().to_vec())
+        .map_err(|e| errors::new(ErrorKind::InvalidJwtEncoding(section.name().to_string(), e)))?;
+    match jwt {
+        Some((existing, _)) if *existing != section_jwt => {
+            Err(errors::new(ErrorKind::MultipleTokens))
+        }
+        Some(_) => Ok(()),
+        None => {
+            *jwt = Some((section_jwt, section.range()));
+            Ok(())
+        }
+    }
+}
+
+// Checks the module hash in the claims against the hash of the module, returning whether or
+// not it could actually be verified. Hashes signed by revisions prior to
+// `MIN_WASCAP_INTERNAL_REVISION` aren't compatible, so a mismatch is tolerated for them
+fn verify_module_hash<T: WascapEntity>(claims: &Claims<T>, hash: &str) -> Result<bool> {
+    let meta = match claims.metadata {
+        Some(ref meta) => meta,
+        None => return Ok(false),
     };
-    use base64::decode;
+    let strict = claims.wascap_revision.unwrap_or_default() >= MIN_WASCAP_INTERNAL_REVISION;
+    let matches = match meta.module_hash() {
+        Some(signed) => match hashes_match(signed, hash, meta.hash_algorithm()) {
+            Ok(matches) => matches,
+            // A hash that isn't even the right shape wasn't computed over any module
+            Err(e) if strict => return Err(e),
+            Err(_) => false,
+        },
+        None => false,
+    };
+    if !matches && strict {
+        return Err(errors::new(ErrorKind::InvalidModuleHash));
+    }
+    Ok(matches)
+}
 
-    const WASM_BASE64: &str =
-        "AGFzbQEAAAAADAZkeWxpbmuAgMACAAGKgICAAAJgAn9/AX9gAAACwYCAgAAEA2VudgptZW1vcnlCYXNl\
-         A38AA2VudgZtZW1vcnkCAIACA2VudgV0YWJsZQFwAAADZW52CXRhYmxlQmFzZQN/AAOEgICAAAMAAQEGi\
-         4CAgAACfwFBAAt/AUEACwejgICAAAIKX3RyYW5zZm9ybQAAEl9fcG9zdF9pbnN0YW50aWF0ZQACCYGAgI\
-         AAAArpgICAAAPBgICAAAECfwJ/IABBAEoEQEEAIQIFIAAPCwNAIAEgAmoiAywAAEHpAEYEQCADQfkAOgA\
-         ACyACQQFqIgIgAEcNAAsgAAsLg4CAgAAAAQuVgICAAAACQCMAJAIjAkGAgMACaiQDEAELCw==";
+// Compares a signed module hash with one computed over the module, in constant time. The
+// computed hash is always well-formed, so an error means the signed one is malformed
+fn hashes_match(signed: &str, computed: &str, algorithm: HashAlgorithm) -> Result<bool> {
+    let signed = decode_module_hash(signed, algorithm)?;
+    let computed = decode_module_hash(computed, algorithm)?;
+    Ok(constant_time::verify_slices_are_equal(&signed, &computed).is_ok())
+}
 
-    #[test]
-    fn claims_roundtrip() {
-        // Serialize and de-serialize this because the module loader adds bytes to
-        // the above base64 encoded module.
-        let dec_module = decode(WASM_BASE64).unwrap();
+// Incrementally parses a module from the reader, handing each payload to `f` along with the
+// buffered bytes it was parsed from and the absolute offset of the start of that buffer
+fn stream_payloads<R: Read>(
+    reader: &mut R,
+    mut f: impl FnMut(&Payload, &[u8], usize) -> Result<()>,
+) -> Result<()> {
+    let mut parser = Parser::new(0);
+    let mut stack: Vec<Parser> = Vec::new();
+    let mut buf: Vec<u8> = Vec::new();
+    let mut offset = 0;
+    let mut eof = false;
 
-        let kp = KeyPair::new_account();
-        let claims = Claims {
-            metadata: Some(Actor::new(
-                "testing".to_string(),
-                Some(vec![MESSAGING.to_string(), KEY_VALUE.to_string()]),
-                Some(vec![]),
-                false,
-                Some(1),
-                Some("".to_string()),
-                None,
-            )),
-            expires: None,
-            id: nuid::next(),
-            issued_at: 0,
-            issuer: kp.public_key(),
-            subject: "test.wasm".to_string(),
-            not_before: None,
-            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+    loop {
+        let (payload, consumed) = match parser.parse(&buf, eof)? {
+            Chunk::NeedMoreData(hint) => {
+                eof = Read::take(&mut *reader, hint).read_to_end(&mut buf)? == 0;
+                continue;
+            }
+            Chunk::Parsed { consumed, payload } => (payload, consumed),
         };
-        let modified_bytecode = embed_claims(&dec_module, &claims, &kp).unwrap();
-        println!(
-            "Added {} bytes in custom section.",
-            modified_bytecode.len() - dec_module.len()
-        );
-        if let Some(token) = extract_claims(&modified_bytecode).unwrap() {
-            assert_eq!(claims.issuer, token.claims.issuer);
-            assert_eq!(
-                claims.metadata.as_ref().unwrap().caps,
-                token.claims.metadata.as_ref().unwrap().caps
-            );
-        } else {
-            unreachable!()
+        f(&payload, &buf, offset)?;
+        match payload {
+            ModuleSection { parser: nested, .. } | ComponentSection { parser: nested, .. } => {
+                stack.push(parser);
+                parser = nested;
+            }
+            End(_) => match stack.pop() {
+                Some(p) => parser = p,
+                None => return Ok(()),
+            },
+            _ => {}
         }
+        buf.drain(..consumed);
+        offset += consumed;
     }
+}
 
-    #[test]
-    fn claims_logging_roundtrip() {
-        // Serialize and de-serialize this because the module loader adds bytes to
-        // the above base64 encoded module.
-        let dec_module = decode(WASM_BASE64).unwrap();
+/// Options that control how claims are embedded into a WebAssembly module
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbedOptions {
+    /// The name of the custom section the signed JWT is written to. Note that `extract_claims`
+    /// only reads the `jwt` and `wasmcloud_jwt` sections
+    pub section_name: String,
+    /// Indicates whether any JWT sections already present in the module are removed before
+    /// the new claims are written
+    pub overwrite_existing: bool,
+    /// When set, actor claims may only contain the standard, well-known capabilities defined
+    /// in the `caps` module. Leave unset to allow custom capabilities
+    pub strict_capabilities: bool,
+    /// When set, the imports of the module, as returned by `scan_imports`, are recorded in actor
+    /// claims that don't already list them. This is off by default, since it changes the claims
+    /// that are signed
+    pub record_imports: bool,
+    /// When set, claims whose expiry isn't after their issue time are refused, and a warning is
+    /// logged for claims whose `not_before` is after their expiry. Unset this to deliberately
+    /// embed tokens that can never be used, e.g. as test fixtures
+    pub check_dates: bool,
+    /// When set, the capabilities and tags of actor claims are embedded exactly as given.
+    /// Otherwise they're normalized so that equivalent claims produce the same token: duplicates
+    /// are removed, the lists are sorted, and capability IDs are lowercased
+    pub preserve_lists: bool,
+}
 
-        let kp = KeyPair::new_account();
-        let claims = Claims {
-            metadata: Some(Actor::new(
-                "testing".to_string(),
-                Some(vec![MESSAGING.to_string(), LOGGING.to_string()]),
-                Some(vec![]),
-                false,
-                Some(1),
-                Some("".to_string()),
-                None,
-            )),
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        EmbedOptions {
+            section_name: SECTION_WC_JWT.to_string(),
+            overwrite_existing: true,
+            strict_capabilities: false,
+            record_imports: false,
+            check_dates: true,
+            preserve_lists: false,
+        }
+    }
+}
+
+/// This function will embed a set of claims inside the bytecode of a WebAssembly module. The claims
+/// are converted into a JWT and signed using the provided signer, e.g. a `KeyPair`.
+/// According to the WebAssembly [custom section](https://webassembly.github.io/spec/core/appendix/custom.html)
+/// specification, arbitary sets of bytes can be stored in a WebAssembly module without impacting
+/// parsers or interpreters. Returns a vector of bytes representing the new WebAssembly module which can
+/// be saved to a `.wasm` file. Any JWT sections already present in the module are removed first,
+/// so the result always contains exactly one set of embedded claims. When given a component, the
+/// claims are written as a custom section of the top-level component
+///
+/// # Errors
+/// Returns an `EmptyModule` error for empty input and a `NotAWasmModule` error for input that
+/// doesn't start with the magic number and a supported version, or an error under the same
+/// conditions as `embed_claims_with_options`
+pub fn embed_claims(
+    orig_bytecode: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<Vec<u8>> {
+    embed_claims_with_options(orig_bytecode, claims, kp, &EmbedOptions::default())
+}
+
+/// Indicates whether the given bytes are a WebAssembly component rather than a core module
+///
+/// # Errors
+/// Will return an error if the bytes don't start with a valid WebAssembly header
+pub fn is_component(contents: &[u8]) -> Result<bool> {
+    match Parser::new(0).parse_all(contents).next() {
+        Some(Ok(Version { encoding, .. })) => Ok(encoding == Encoding::Component),
+        Some(Err(e)) => Err(e.into()),
+        _ => Err(errors::new(ErrorKind::WasmElement(
+            "missing WebAssembly header".to_string(),
+        ))),
+    }
+}
+
+/// Embeds a set of claims in a WebAssembly component in the same way as `embed_claims`. The JWT
+/// is written as a custom section of the top-level component, where component inspection tools
+/// can see it, rather than inside any of the core modules the component contains
+///
+/// # Errors
+/// Returns a `WasmElement` error if the bytes are a core module rather than a component, or an
+/// error under the same conditions as `embed_claims`
+pub fn embed_claims_component(
+    component_bytes: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<Vec<u8>> {
+    require_component(component_bytes)?;
+    embed_claims(component_bytes, claims, kp)
+}
+
+/// Extracts the claims embedded in the top-level custom sections of a WebAssembly component,
+/// in the same way as `extract_claims`. Claims embedded in the core modules or components nested
+/// inside it are ignored
+///
+/// # Errors
+/// Returns a `WasmElement` error if the bytes are a core module rather than a component, or an
+/// error under the same conditions as `extract_claims`
+pub fn extract_claims_component(component_bytes: &[u8]) -> Result<Option<Token<Actor>>> {
+    require_component(component_bytes)?;
+    extract_claims(component_bytes)
+}
+
+/// Extracts claims from a module or component in the same way as `extract_claims`, falling back
+/// to the claims of the core modules nested inside a component when the component itself isn't
+/// signed. This is the case when a signed module is composed into a component, which
+/// `extract_claims` reports as unsigned. Alongside the claims, returns whether they were found
+/// in a nested module rather than at the top level.
+///
+/// Nested claims are those of the first signed core module in the component, at any depth, and
+/// their module hash is checked against the bytes of that module alone. They describe the
+/// module, not the component as a whole
+///
+/// # Errors
+/// Will return an error if the component can't be parsed, or under the same conditions as
+/// `extract_claims` for the top-level claims or those of a nested module
+pub fn extract_claims_nested(contents: impl AsRef<[u8]>) -> Result<Option<(Token<Actor>, bool)>> {
+    let contents = contents.as_ref();
+    if let Some(token) = extract_claims(contents)? {
+        return Ok(Some((token, false)));
+    }
+    for payload in Parser::new(0).parse_all(contents) {
+        if let ModuleSection { range, .. } = payload? {
+            if let Some(token) = extract_claims(&contents[range])? {
+                return Ok(Some((token, true)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn require_component(contents: &[u8]) -> Result<()> {
+    if is_component(contents)? {
+        Ok(())
+    } else {
+        Err(errors::new(ErrorKind::WasmElement(
+            "expected a WebAssembly component, found a core module".to_string(),
+        )))
+    }
+}
+
+/// Embeds a set of claims in the same way as `embed_claims`, for builds that need byte-for-byte
+/// reproducible output. Embedding is deterministic given the same module, claims and key, but
+/// constructors and `ClaimsBuilder` stamp a new `id` and `issued_at` each time, so this refuses
+/// claims that don't have both set explicitly rather than generating them. The caller is
+/// responsible for supplying an `id` that is stable across builds of the same module yet still
+/// unique to it
+///
+/// # Errors
+/// Returns an error if the claims have an empty `id` or an `issued_at` of zero
+pub fn embed_claims_reproducible(
+    orig_bytecode: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<Vec<u8>> {
+    if claims.id.is_empty() || claims.issued_at == 0 {
+        return Err(errors::new(ErrorKind::Token(
+            "reproducible claims require an explicit id and issued_at".to_string(),
+        )));
+    }
+    embed_claims(orig_bytecode, claims, kp)
+}
+
+/// Embeds a set of claims inside the bytecode of a WebAssembly module in the same way as
+/// `embed_claims`, using the supplied `EmbedOptions` to pick the custom section name and
+/// whether existing JWT sections are stripped first
+///
+/// # Errors
+/// Returns an `InvalidKeyType` error if the issuer of the claims isn't an account key or the
+/// subject isn't a module key, and an `UnknownCapability` error if strict capabilities are
+/// requested and the claims contain a capability that isn't well-known. Unless date checks are
+/// turned off, an `AlreadyExpired` error is returned if the claims expire at or before the time
+/// they're issued. A `ClockError` is returned if the system clock is set before the UNIX epoch,
+/// rather than signing claims dated 1970. In strict mode, capability bundle aliases such as
+/// `standard-io` are expanded before the claims are checked and embedded
+pub fn embed_claims_with_options(
+    orig_bytecode: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+    opts: &EmbedOptions,
+) -> Result<Vec<u8>> {
+    let claims = prepare_claims(orig_bytecode, claims, opts)?;
+    embed(orig_bytecode, &claims, kp, opts)
+}
+
+/// Embeds a set of claims in the same way as `embed_claims`, recording the supplied module hash
+/// rather than computing it. This is meant for signing several sets of claims (for example with
+/// different expiries) for the same module, where the hash only has to be computed once with
+/// `compute_module_hash_with_scope`, using the hash algorithm and scope of the claims.
+///
+/// The hash is trusted as-is: if it wasn't computed over this module, the claims are still
+/// embedded but will fail hash verification when extracted. Debug builds check the hash against
+/// the module and panic on a mismatch
+///
+/// # Errors
+/// Returns a `MalformedHash` error if the hash isn't a hex digest of the algorithm set in the
+/// claims, or any of the errors returned by `embed_claims`
+pub fn embed_claims_with_hash(
+    orig_bytecode: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+    precomputed_hash: &str,
+) -> Result<Vec<u8>> {
+    let opts = EmbedOptions::default();
+    let mut claims = prepare_claims(orig_bytecode, claims, &opts)?;
+    if let Some(ref mut meta) = claims.metadata {
+        decode_module_hash(precomputed_hash, meta.hash_algorithm)?;
+        debug_assert!(
+            compute_module_hash_with_scope(orig_bytecode, meta.hash_algorithm, &meta.hash_scope)
+                .is_ok_and(|hash| hash == precomputed_hash),
+            "precomputed module hash doesn't match the module"
+        );
+        meta.module_hash = precomputed_hash.to_string();
+    }
+    let jwt = claims.encode(kp)?;
+    rebuild_module(
+        orig_bytecode,
+        is_jwt_section,
+        Some(wasm_encoder::CustomSection {
+            name: &opts.section_name,
+            data: jwt.as_bytes(),
+        }),
+    )
+}
+
+// Makes the checks and additions `embed_claims_with_options` applies to claims before they're
+// signed
+fn prepare_claims(
+    orig_bytecode: &[u8],
+    claims: &Claims<Actor>,
+    opts: &EmbedOptions,
+) -> Result<Claims<Actor>> {
+    check_preamble(orig_bytecode)?;
+    // Claims are stamped with the current time, which is meaningless on a pre-epoch clock
+    since_the_epoch()?;
+    validate_key_types(claims)?;
+    if opts.check_dates {
+        validate_dates(claims)?;
+    }
+    let mut claims = claims.clone();
+    if let Some(ref mut meta) = claims.metadata {
+        if opts.strict_capabilities {
+            if let Some(caps) = meta.caps.as_mut() {
+                *caps = caps::expand_capability_aliases(caps);
+            }
+        }
+        if !opts.preserve_lists {
+            if let Some(caps) = meta.caps.as_mut() {
+                normalize_list(caps, |cap| {
+                    // Bundle aliases and display names aren't IDs, and are left as they are
+                    if caps::namespace(cap).is_some() {
+                        *cap = cap.to_lowercase();
+                    }
+                });
+            }
+            if let Some(tags) = meta.tags.as_mut() {
+                normalize_list(tags, |_| ());
+            }
+        }
+    }
+    if opts.strict_capabilities {
+        validate_capabilities(&claims)?;
+    }
+
+    if let Some(ref mut meta) = claims.metadata {
+        if opts.record_imports && meta.imports.is_none() {
+            meta.imports = Some(scan_imports(orig_bytecode)?);
+        }
+    }
+    Ok(claims)
+}
+
+fn normalize_list(list: &mut Vec<String>, normalize: impl FnMut(&mut String)) {
+    list.iter_mut().for_each(normalize);
+    list.sort();
+    list.dedup();
+}
+
+/// Prepares a set of claims for a module in the same way as `embed_claims`, but rather than
+/// signing them, returns the encoded JWT header and claims along with the exact bytes the
+/// issuer's Ed25519 signature must cover. This is for signing with keys that can't be loaded
+/// into the process, such as ones held in an HSM: sign the bytes externally, then pass the
+/// header and claims and the signature to `assemble_signed_module`
+///
+/// # Errors
+/// Returns an error under the same conditions as `embed_claims`
+pub fn signing_payload(contents: &[u8], claims: &Claims<Actor>) -> Result<(String, Vec<u8>)> {
+    let claims = prepare_claims(contents, claims, &EmbedOptions::default())?;
+    let header_payload = with_module_hash(contents, &claims)?.signing_input(None)?;
+    let bytes = header_payload.as_bytes().to_vec();
+    Ok((header_payload, bytes))
+}
+
+/// Completes the JWT prepared by `signing_payload` with a signature made outside the process and
+/// embeds it into the module in the same way as `embed_claims`. `signature` is the raw Ed25519
+/// signature over the bytes returned by `signing_payload`
+///
+/// # Errors
+/// Returns a `SignatureInvalid` error if the signature doesn't verify against the issuer of the
+/// claims, or an `InvalidModuleHash` error if the claims were prepared for a different module
+pub fn assemble_signed_module(
+    contents: &[u8],
+    header_payload: &str,
+    signature: &[u8],
+) -> Result<Vec<u8>> {
+    let jwt = format!(
+        "{}.{}",
+        header_payload,
+        base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+    );
+    let claims = Claims::<Actor>::decode_unverified(&jwt)?;
+    KeyPair::from_public_key(&claims.issuer)?
+        .verify(header_payload.as_bytes(), signature)
+        .map_err(|_| errors::new(ErrorKind::SignatureInvalid))?;
+
+    let signed = rebuild_module(
+        contents,
+        is_jwt_section,
+        Some(wasm_encoder::CustomSection {
+            name: SECTION_WC_JWT,
+            data: jwt.as_bytes(),
+        }),
+    )?;
+    match extract_claims(&signed)? {
+        Some(token) if token.hash_verified => Ok(signed),
+        _ => Err(errors::new(ErrorKind::InvalidModuleHash)),
+    }
+}
+
+/// Lists the name and data length in bytes of every custom section in a module, in the order
+/// they appear, including those of nested modules and components. This is meant for diagnosing
+/// unexpected sections, for example when a module fails hash verification
+pub fn list_custom_sections(contents: &[u8]) -> Result<Vec<(String, usize)>> {
+    let mut sections = Vec::new();
+    for payload in Parser::new(0).parse_all(contents) {
+        if let CustomSection(reader) = payload? {
+            sections.push((reader.name().to_string(), reader.data().len()));
+        }
+    }
+    Ok(sections)
+}
+
+/// Iterates over the custom sections of a module or component, yielding the name and data of
+/// each one in the order they appear. The module is parsed lazily as the iterator advances, and
+/// the data borrows from `contents`, so nothing but the name is copied. Only top-level sections
+/// are yielded: unlike `list_custom_sections`, those of nested modules and components are skipped
+///
+/// # Errors
+/// An error is yielded, after which the iterator ends, if the module can't be parsed
+pub fn custom_sections(contents: &[u8]) -> impl Iterator<Item = Result<(String, &[u8])>> {
+    top_level_custom_sections(contents)
+        .map(|section| section.map(|section| (section.name().to_string(), section.data())))
+}
+
+fn top_level_custom_sections(
+    contents: &[u8],
+) -> impl Iterator<Item = Result<CustomSectionReader<'_>>> {
+    let mut depth = 0;
+    Parser::new(0)
+        .parse_all(contents)
+        .filter_map(move |payload| match payload {
+            Ok(Version { .. }) => {
+                depth += 1;
+                None
+            }
+            Ok(End(_)) => {
+                depth -= 1;
+                None
+            }
+            Ok(CustomSection(reader)) if depth == 1 => Some(Ok(reader)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.into())),
+        })
+}
+
+/// Structural statistics about a WebAssembly module, for display alongside its claims
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModuleStats {
+    /// The size of the module in bytes
+    pub total_size: usize,
+    /// The number of functions the module defines, not counting imported functions
+    pub num_functions: usize,
+    /// The number of exports the module declares
+    pub num_exports: usize,
+    /// The number of functions, memories, tables and globals the module imports
+    pub num_imports: usize,
+    /// The number of custom sections, including any embedded claims
+    pub num_custom_sections: usize,
+    /// Indicates whether the module declares a start function
+    pub has_start: bool,
+}
+
+/// Computes structural statistics about a module in a single pass, whether or not it has been
+/// signed. Only the top-level module is counted: the contents of nested modules and components
+/// are not included
+pub fn module_stats(contents: &[u8]) -> Result<ModuleStats> {
+    let mut stats = ModuleStats {
+        total_size: contents.len(),
+        ..Default::default()
+    };
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(contents) {
+        match payload? {
+            Version { .. } => depth += 1,
+            End(_) => depth -= 1,
+            _ if depth != 1 => {}
+            FunctionSection(reader) => stats.num_functions += reader.get_count() as usize,
+            ExportSection(reader) => stats.num_exports += reader.get_count() as usize,
+            ImportSection(reader) => stats.num_imports += reader.get_count() as usize,
+            CustomSection(_) => stats.num_custom_sections += 1,
+            StartSection { .. } => stats.has_start = true,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+/// Returns the fully-qualified names (`module::name`) of the functions, memories, tables and
+/// globals a WebAssembly module imports from its host, in the order they're declared. For a
+/// component, these are the names of its imports instead, e.g. `wasi:http/outgoing-handler`.
+/// Only the imports of the top-level module or component are included
+pub fn scan_imports(contents: &[u8]) -> Result<Vec<String>> {
+    let mut imports = Vec::new();
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(contents) {
+        match payload? {
+            Version { .. } => depth += 1,
+            End(_) => depth -= 1,
+            ImportSection(reader) if depth == 1 => {
+                for import in reader {
+                    let import = import?;
+                    imports.push(format!("{}::{}", import.module, import.name));
+                }
+            }
+            ComponentImportSection(reader) if depth == 1 => {
+                for import in reader {
+                    imports.push(import?.name.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(imports)
+}
+
+/// Returns the imports of a module that weren't recorded in its claims when it was signed.
+/// Claims that don't record imports at all aren't checked, and yield an empty list
+pub fn undeclared_imports(contents: &[u8], claims: &Claims<Actor>) -> Result<Vec<String>> {
+    let declared = match claims.metadata.as_ref().and_then(|m| m.imports.as_ref()) {
+        Some(declared) => declared,
+        None => return Ok(Vec::new()),
+    };
+    Ok(scan_imports(contents)?
+        .into_iter()
+        .filter(|import| !declared.contains(import))
+        .collect())
+}
+
+/// Performs the same work as `embed_claims` on Tokio's blocking thread pool, with
+/// `spawn_blocking`, so that hashing and signing a large module doesn't block the async executor
+/// awaiting it. This only offloads the work; the module is still hashed sequentially. It must be
+/// called from within a Tokio runtime
+///
+/// # Errors
+/// Returns an `IO` error if the runtime shuts down before the work runs, or an error under the
+/// same conditions as `embed_claims`
+#[cfg(feature = "tokio")]
+pub async fn embed_claims_async(
+    orig: Vec<u8>,
+    claims: Claims<Actor>,
+    kp: KeyPair,
+) -> Result<Vec<u8>> {
+    match tokio::task::spawn_blocking(move || embed_claims(&orig, &claims, &kp)).await {
+        Ok(result) => result,
+        // Surface a panic in the work the same way as if it had run inline
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(std::io::Error::from(e).into()),
+    }
+}
+
+/// Embeds a set of claims of any embeddable type (e.g. `Actor` or `CapabilityProvider`) inside
+/// the bytecode of a WebAssembly module, recording the module's hash in the claims metadata in
+/// the same way as `embed_claims`
+pub fn embed_claims_generic<T>(
+    orig_bytecode: &[u8],
+    claims: &Claims<T>,
+    kp: &dyn WascapSigner,
+) -> Result<Vec<u8>>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    embed(orig_bytecode, claims, kp, &EmbedOptions::default())
+}
+
+fn embed<T>(
+    orig_bytecode: &[u8],
+    claims: &Claims<T>,
+    kp: &dyn WascapSigner,
+    opts: &EmbedOptions,
+) -> Result<Vec<u8>>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    check_preamble(orig_bytecode)?;
+    // Embedded claims are never part of the module hash, so it can be computed in the same pass
+    // that rebuilds the module, and the JWT encoded once the pass reaches the end of the module
+    let hasher = claims
+        .metadata
+        .as_ref()
+        .map(|meta| ModuleHasher::new(meta.hash_algorithm(), meta.hash_scope()));
+    rebuild_module_with(
+        orig_bytecode,
+        if opts.overwrite_existing {
+            is_jwt_section
+        } else {
+            |_| false
+        },
+        hasher,
+        |hash| {
+            let mut claims = claims.clone();
+            if let (Some(meta), Some(hash)) = (claims.metadata.as_mut(), hash) {
+                meta.set_module_hash(hash);
+            }
+            Ok(Some((opts.section_name.clone(), claims.encode(kp)?)))
+        },
+    )
+}
+
+// Records the hash of the module in a copy of the claims. Embedded claims are never part of the
+// module hash, so it can be computed before they're embedded
+fn with_module_hash<T>(orig_bytecode: &[u8], claims: &Claims<T>) -> Result<Claims<T>>
+where
+    T: Serialize + DeserializeOwned + WascapEntity,
+{
+    let mut claims = claims.clone();
+    if let Some(ref mut meta) = claims.metadata {
+        let hash = compute_module_hash_with_scope(
+            orig_bytecode,
+            meta.hash_algorithm(),
+            &meta.hash_scope(),
+        )?;
+        meta.set_module_hash(hash);
+    }
+    Ok(claims)
+}
+
+/// Signs a set of claims for a WebAssembly module without embedding them, for modules that
+/// can't be modified. The claims are checked and normalized, and the hash of the module recorded
+/// in them, in the same way as `embed_claims`, and the signed JWT is returned so it can be stored
+/// alongside the module
+///
+/// # Errors
+/// Returns an error under the same conditions as `embed_claims`
+pub fn sign_detached(buf: &[u8], claims: &Claims<Actor>, kp: &dyn WascapSigner) -> Result<String> {
+    let claims = prepare_claims(buf, claims, &EmbedOptions::default())?;
+    with_module_hash(buf, &claims)?.encode(kp)
+}
+
+/// Verifies a detached JWT produced by `sign_detached` against the WebAssembly module it was
+/// signed for, checking both the signature of the JWT and that the module hash it contains
+/// matches the module. Unlike embedded claims, the module hash is always checked
+///
+/// # Errors
+/// Returns an error if the signature is invalid, the token has expired or can't be used yet, or
+/// the module hash doesn't match
+pub fn verify_detached(buf: &[u8], jwt: &str) -> Result<Token<Actor>> {
+    let claims = Claims::<Actor>::decode(jwt)?;
+    let meta = claims
+        .metadata
+        .as_ref()
+        .ok_or_else(|| errors::new(ErrorKind::MissingMetadata))?;
+    let hash = compute_module_hash_with_scope(buf, meta.hash_algorithm, &meta.hash_scope)?;
+    if !hashes_match(&meta.module_hash, &hash, meta.hash_algorithm)? {
+        return Err(errors::new(ErrorKind::InvalidModuleHash));
+    }
+
+    Ok(Token {
+        jwt: jwt.to_string(),
+        claims,
+        hash_verified: true,
+    })
+}
+
+/// Verifies that a detached JWT is bound to the given WebAssembly module. In addition to the
+/// checks made by `verify_detached`, the subject of the token must be a valid module public key,
+/// so a token issued for some other kind of entity can't be passed off as the module's claims
+///
+/// # Errors
+/// Returns a `SubjectNotModuleKey` error if the subject isn't a module public key (prefix `M`),
+/// a `HashMismatch` error if the module hash in the token doesn't match the module, or any of the
+/// errors returned by `verify_detached` for an invalid or unusable token
+pub fn verify_detached_binding(buf: &[u8], jwt: &str) -> Result<Token<Actor>> {
+    let claims = Claims::<Actor>::decode(jwt)?;
+    if !is_key_of_type(&claims.subject, 'M') {
+        return Err(errors::new(ErrorKind::SubjectNotModuleKey(claims.subject)));
+    }
+    let meta = claims
+        .metadata
+        .as_ref()
+        .ok_or_else(|| errors::new(ErrorKind::MissingMetadata))?;
+    let hash = compute_module_hash_with_scope(buf, meta.hash_algorithm, &meta.hash_scope)?;
+    if !hashes_match(&meta.module_hash, &hash, meta.hash_algorithm)? {
+        return Err(errors::new(ErrorKind::HashMismatch));
+    }
+
+    Ok(Token {
+        jwt: jwt.to_string(),
+        claims,
+        hash_verified: true,
+    })
+}
+
+/// Removes any embedded claims (custom sections named `jwt` or `wasmcloud_jwt`), along with any
+/// co-signatures added by `embed_additional_signature`, from the raw bytes of a WebAssembly module
+/// or component, returning the bytes without them. All other sections, including those of nested
+/// modules and components, are copied as-is
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed
+pub fn strip_claims(orig_bytecode: &[u8]) -> Result<Vec<u8>> {
+    rebuild_module(orig_bytecode, is_claims_section, None)
+}
+
+/// Recovers the module a signed module was built from, for re-verifying it against an external
+/// record of what was signed. This is the module with its claims and co-signatures removed, as
+/// returned by `strip_claims`: those are the only sections the module hash doesn't cover, so
+/// hashing the result with the algorithm and scope of the embedded claims gives back their
+/// `module_hash`
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed
+pub fn unsigned_bytes(contents: &[u8]) -> Result<Vec<u8>> {
+    check_preamble(contents)?;
+    strip_claims(contents)
+}
+
+/// Re-signs a module that already has embedded claims with a different account key, e.g. when
+/// rotating keys. Every claim is kept as it is apart from the issuer and the module hash, and
+/// the `id` is either kept, so the new token can be traced back to the original, or replaced
+/// with a new one. The capabilities and tags are re-signed exactly as they are, without being
+/// normalized again. The old JWT section is replaced, and the re-signed module is read back to
+/// make sure it carries the new claims. The existing claims are only re-signed once their
+/// signature and module hash have been verified, so claims edited after signing can't be
+/// laundered into a validly signed token
+///
+/// # Errors
+/// Returns a `NoEmbeddedClaims` error if the module isn't signed, an `InvalidKeyType` error if
+/// the new key isn't an account key, or an error under the same conditions as `extract_claims`
+/// if the existing claims can't be read or verified, e.g. `SignatureInvalid` for edited claims
+pub fn re_sign(contents: &[u8], new_acct_kp: &KeyPair, keep_id: bool) -> Result<Vec<u8>> {
+    let token =
+        extract_claims(contents)?.ok_or_else(|| errors::new(ErrorKind::NoEmbeddedClaims))?;
+    let claims = Claims {
+        issuer: new_acct_kp.public_key(),
+        id: if keep_id {
+            token.claims.id
+        } else {
+            nuid::next()
+        },
+        ..token.claims
+    };
+    // The lists were normalized, or deliberately not, when the claims were first signed
+    let opts = EmbedOptions {
+        preserve_lists: true,
+        ..Default::default()
+    };
+    let signed = embed_claims_with_options(contents, &claims, new_acct_kp, &opts)?;
+
+    let resigned =
+        extract_claims(&signed)?.ok_or_else(|| errors::new(ErrorKind::NoEmbeddedClaims))?;
+    if !resigned.hash_verified
+        || resigned.claims.issuer != claims.issuer
+        || resigned.claims.id != claims.id
+    {
+        return Err(errors::new(ErrorKind::Token(
+            "re-signed module does not carry the new claims".to_string(),
+        )));
+    }
+    Ok(signed)
+}
+
+/// Adds a co-signature to a module that has already been signed, e.g. so a security team can
+/// attest to a module signed by its author. The claims are signed and embedded as an additional
+/// token alongside the existing ones, which are left untouched. Co-signatures aren't returned by
+/// `extract_claims`, which only reads the primary claims; use `extract_all_claims` to read every
+/// token. Re-embedding the primary claims keeps any co-signatures, while `strip_claims` removes
+/// them all. The primary claims are verified before anything is co-signed, so a co-signature
+/// never vouches for claims that were edited after signing, and the co-signed claims are checked
+/// and normalized in the same way as they are by `embed_claims`
+///
+/// # Errors
+/// Returns a `NoEmbeddedClaims` error if the module isn't signed, a `Token` error if the subject
+/// isn't the same as that of the existing claims, an error under the same conditions as
+/// `extract_claims` if the primary claims don't verify, or an error under the same conditions as
+/// `embed_claims` for the co-signed claims, such as `InvalidKeyType` or `AlreadyExpired`
+pub fn embed_additional_signature(
+    contents: &[u8],
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<Vec<u8>> {
+    let primary =
+        extract_claims(contents)?.ok_or_else(|| errors::new(ErrorKind::NoEmbeddedClaims))?;
+    if claims.subject != primary.claims.subject {
+        return Err(errors::new(ErrorKind::Token(
+            "co-signature must have the same subject as the module's claims".to_string(),
+        )));
+    }
+    let opts = EmbedOptions {
+        section_name: SECTION_COSIGNATURE.to_string(),
+        overwrite_existing: false,
+        ..Default::default()
+    };
+    embed_claims_with_options(contents, claims, kp, &opts)
+}
+
+/// Extracts every valid token embedded in a module: the primary claims followed by any
+/// co-signatures, in the order they appear. A token is only included if it is signed by its
+/// issuer, currently valid, and carries the correct module hash. Tokens that fail any of these
+/// checks are left out rather than causing an error, so policies such as "signed by at least one
+/// trusted key" or "signed by all required keys" can be applied to the issuers of the result
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed or hashed
+pub fn extract_all_claims(contents: impl AsRef<[u8]>) -> Result<Vec<Token<Actor>>> {
+    let contents = contents.as_ref();
+    let mut jwts: Vec<String> = Vec::new();
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(contents) {
+        match payload? {
+            Version { .. } => depth += 1,
+            End(_) => depth -= 1,
+            CustomSection(reader)
+                if depth == 1
+                    && is_claims_section(reader.name())
+                    && reader.data().len() <= DEFAULT_MAX_JWT_SECTION_SIZE =>
+            {
+                // The `jwt` and `wasmcloud_jwt` sections may both hold the same primary token
+                if let Ok(jwt) = std::str::from_utf8(reader.data()) {
+                    if !jwts.iter().any(|j| j == jwt) {
+                        jwts.push(jwt.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut tokens = Vec::new();
+    for jwt in jwts {
+        let claims = match Claims::<Actor>::decode(&jwt) {
+            Ok(claims) => claims,
+            Err(_) => continue,
+        };
+        let hash_verified = match claims.metadata {
+            Some(ref meta) => {
+                let hash = compute_module_hash_with_scope(
+                    contents,
+                    meta.hash_algorithm,
+                    &meta.hash_scope,
+                )?;
+                hashes_match(&meta.module_hash, &hash, meta.hash_algorithm).unwrap_or(false)
+            }
+            None => false,
+        };
+        if hash_verified {
+            tokens.push(Token {
+                jwt,
+                claims,
+                hash_verified,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Moves claims embedded under the legacy `jwt` section name to the current `wasmcloud_jwt`
+/// section, for retiring the legacy name. The JWT itself is carried over unchanged, so the module
+/// hash and signature stay valid, and the migrated module is checked to yield the same claims.
+/// Returns `None` if there's nothing to migrate: the module has no `jwt` section, or already has
+/// a `wasmcloud_jwt` one. The claims are verified before they're moved, so a forged token is
+/// never carried over into the current section
+///
+/// # Errors
+/// Returns a `Token` error if the migrated module doesn't carry the original claims, or an error
+/// under the same conditions as `extract_claims`
+pub fn migrate_section_name(contents: &[u8]) -> Result<Option<Vec<u8>>> {
+    let original = extract_claims(contents)?;
+    let mut legacy: Option<(String, Range<usize>)> = None;
+    let mut has_current = false;
+    let mut depth = 0;
+    for payload in Parser::new(0).parse_all(contents) {
+        match payload? {
+            Version { .. } => depth += 1,
+            End(_) => depth -= 1,
+            CustomSection(reader) if depth == 1 && reader.name() == SECTION_WC_JWT => {
+                has_current = true
+            }
+            CustomSection(reader) if depth == 1 && reader.name() == SECTION_JWT => {
+                record_jwt(&mut legacy, &reader, DEFAULT_MAX_JWT_SECTION_SIZE)?
+            }
+            _ => {}
+        }
+    }
+    let jwt = match legacy {
+        Some((jwt, _)) if !has_current => jwt,
+        _ => return Ok(None),
+    };
+
+    let migrated = rebuild_module(
+        contents,
+        |name| name == SECTION_JWT,
+        Some(wasm_encoder::CustomSection {
+            name: SECTION_WC_JWT,
+            data: jwt.as_bytes(),
+        }),
+    )?;
+    match (original, extract_claims(&migrated)?) {
+        (Some(before), Some(after))
+            if before == after && before.hash_verified == after.hash_verified =>
+        {
+            Ok(Some(migrated))
+        }
+        _ => Err(errors::new(ErrorKind::Token(
+            "migrated module does not carry the original claims".to_string(),
+        ))),
+    }
+}
+
+// Custom sections that toolchains expect to find at the very end of a module
+fn is_trailing_section(name: &str) -> bool {
+    name == "name" || name.starts_with(".debug")
+}
+
+// Re-encodes a module or component section by section, dropping the top-level custom sections
+// whose names `strip` matches and optionally inserting a new top-level custom section. The new
+// section is placed after the last non-custom section (e.g. `data`) but before any trailing
+// `name` or debug sections, so that its position doesn't depend on which sections the module
+// happens to contain
+fn rebuild_module(
+    orig_bytecode: &[u8],
+    strip: fn(&str) -> bool,
+    insert: Option<wasm_encoder::CustomSection>,
+) -> Result<Vec<u8>> {
+    rebuild_module_with(orig_bytecode, strip, None, |_| {
+        Ok(insert.map(|section| (section.name.to_string(), section.data.to_vec())))
+    })
+}
+
+// Rebuilds a module in the same way as `rebuild_module`, feeding every payload of the original
+// module to the hasher along the way. The section to insert is produced once the whole module
+// has been read, from the finished hash
+fn rebuild_module_with<S: AsRef<[u8]>>(
+    orig_bytecode: &[u8],
+    strip: fn(&str) -> bool,
+    mut hasher: Option<ModuleHasher>,
+    insert: impl FnOnce(Option<String>) -> Result<Option<(String, S)>>,
+) -> Result<Vec<u8>> {
+    let mut insert = Some(insert);
+    let mut output: Vec<u8> = Vec::new();
+    // Top-level name/debug sections are held back until it's clear nothing but custom
+    // sections follow them
+    let mut tail: Option<Vec<u8>> = None;
+    // Nested modules and components are re-encoded into their parent once their `End` is reached
+    let mut stack: Vec<(u8, Vec<u8>)> = Vec::new();
+
+    for payload in wasmparser::Parser::new(0).parse_all(orig_bytecode) {
+        let payload = payload?;
+        if let Some(ref mut hasher) = hasher {
+            hasher.update(&payload, orig_bytecode, 0)?;
+        }
+        match payload {
+            Version { range, .. } => output.extend_from_slice(&orig_bytecode[range]),
+            ModuleSection { .. } | ComponentSection { .. } => {
+                if let Some((id, _)) = payload.as_section() {
+                    if stack.is_empty() {
+                        if let Some(held) = tail.take() {
+                            output.extend(held);
+                        }
+                    }
+                    stack.push((id, std::mem::take(&mut output)));
+                }
+            }
+            End(_) => match stack.pop() {
+                Some((id, mut parent)) => {
+                    parent.push(id);
+                    RawSection { id, data: &output }.encode(&mut parent);
+                    output = parent;
+                }
+                None => {
+                    let insert = insert.take().expect("module has a single top-level end");
+                    if let Some((name, data)) = insert(hasher.take().map(ModuleHasher::finish))? {
+                        let section = wasm_encoder::CustomSection {
+                            name: &name,
+                            data: data.as_ref(),
+                        };
+                        output.push(section.id());
+                        section.encode(&mut output);
+                    }
+                    if let Some(held) = tail.take() {
+                        output.extend(held);
+                    }
+                }
+            },
+            CustomSection(ref reader) if stack.is_empty() && strip(reader.name()) => {}
+            _ => {
+                if let Some((id, range)) = payload.as_section() {
+                    let buf = if stack.is_empty() {
+                        match payload {
+                            CustomSection(ref reader) if is_trailing_section(reader.name()) => {
+                                tail.get_or_insert_with(Vec::new)
+                            }
+                            CustomSection(_) => tail.as_mut().unwrap_or(&mut output),
+                            _ => {
+                                if let Some(held) = tail.take() {
+                                    output.extend(held);
+                                }
+                                &mut output
+                            }
+                        }
+                    } else {
+                        &mut output
+                    };
+                    buf.push(id);
+                    RawSection {
+                        id,
+                        data: &orig_bytecode[range],
+                    }
+                    .encode(buf);
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn sign_buffer_with_claims(
+    name: String,
+    buf: impl AsRef<[u8]>,
+    mod_kp: KeyPair,
+    acct_kp: KeyPair,
+    expires_in_days: Option<u64>,
+    not_before_days: Option<u64>,
+    caps: Vec<String>,
+    tags: Vec<String>,
+    provider: bool,
+    rev: Option<i32>,
+    ver: Option<String>,
+    call_alias: Option<String>,
+    oci_reference: Option<String>,
+) -> Result<Vec<u8>> {
+    // Make sure the clock is usable before any timestamps are put in the claims
+    since_the_epoch()?;
+    sign_buffer(
+        name,
+        buf.as_ref(),
+        mod_kp,
+        acct_kp,
+        days_from_now_to_jwt_time(expires_in_days)?,
+        days_from_now_to_jwt_time(not_before_days)?,
+        caps,
+        tags,
+        provider,
+        rev,
+        ver,
+        call_alias,
+        oci_reference,
+    )
+}
+
+/// Signs a module in the same way as `sign_buffer_with_claims`, with the expiration and
+/// not-valid-before times given as durations from now rather than whole days, e.g. for
+/// short-lived tokens
+///
+/// # Errors
+/// Returns a `TimestampOverflow` error if a duration reaches past the largest timestamp, or an
+/// error under the same conditions as `sign_buffer_with_claims`
+#[allow(clippy::too_many_arguments)]
+pub fn sign_buffer_with_expiry(
+    name: String,
+    buf: impl AsRef<[u8]>,
+    mod_kp: KeyPair,
+    acct_kp: KeyPair,
+    expires_in: Option<Duration>,
+    not_before: Option<Duration>,
+    caps: Vec<String>,
+    tags: Vec<String>,
+    provider: bool,
+    rev: Option<i32>,
+    ver: Option<String>,
+    call_alias: Option<String>,
+    oci_reference: Option<String>,
+) -> Result<Vec<u8>> {
+    let now = since_the_epoch()?.as_secs();
+    sign_buffer(
+        name,
+        buf.as_ref(),
+        mod_kp,
+        acct_kp,
+        duration_from_now(now, expires_in)?,
+        duration_from_now(now, not_before)?,
+        caps,
+        tags,
+        provider,
+        rev,
+        ver,
+        call_alias,
+        oci_reference,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sign_buffer(
+    name: String,
+    buf: &[u8],
+    mod_kp: KeyPair,
+    acct_kp: KeyPair,
+    expires: Option<u64>,
+    not_before: Option<u64>,
+    caps: Vec<String>,
+    tags: Vec<String>,
+    provider: bool,
+    rev: Option<i32>,
+    ver: Option<String>,
+    call_alias: Option<String>,
+    oci_reference: Option<String>,
+) -> Result<Vec<u8>> {
+    let mut claims = Claims::<Actor>::with_dates(
+        name,
+        acct_kp.public_key(),
+        mod_kp.public_key(),
+        Some(caps),
+        Some(tags),
+        not_before,
+        expires,
+        provider,
+        rev,
+        ver,
+        call_alias,
+    );
+    if let Some(ref mut meta) = claims.metadata {
+        meta.oci_reference = oci_reference;
+    }
+    embed_claims(buf, &claims, &acct_kp)
+}
+
+/// Signs a module in the same way as `sign_buffer_with_claims`, with the claims set up in the
+/// given builder rather than passed as separate arguments. The issuer and subject of the builder
+/// are replaced by the public keys of `acct_kp` and `mod_kp`, so only the actor's metadata and
+/// dates need to be set, e.g. with `expires_in_days` or, for short-lived tokens, `expires_in`
+///
+/// # Errors
+/// Returns an error under the same conditions as `embed_claims`
+pub fn sign_buffer_with_builder(
+    buf: impl AsRef<[u8]>,
+    mod_kp: &KeyPair,
+    acct_kp: &KeyPair,
+    claims: &ClaimsBuilder<Actor>,
+) -> Result<Vec<u8>> {
+    sign_buffer_with_builder_at(&SystemClock, buf.as_ref(), mod_kp, acct_kp, claims)
+}
+
+// Signs a module with the claims set up in the builder, issued at the current time of the given
+// clock
+fn sign_buffer_with_builder_at(
+    clock: &dyn Clock,
+    buf: &[u8],
+    mod_kp: &KeyPair,
+    acct_kp: &KeyPair,
+    claims: &ClaimsBuilder<Actor>,
+) -> Result<Vec<u8>> {
+    let claims = claims
+        .clone()
+        .issuer(&acct_kp.public_key())
+        .subject(&mod_kp.public_key())
+        .build_with_clock(clock)?;
+    embed_claims(buf, &claims, acct_kp)
+}
+
+/// Reads an nkeys seed (e.g. `SAAG...`) from a file and creates the key pair it represents.
+/// Whitespace around the seed, such as a trailing newline, is ignored
+///
+/// # Errors
+/// Returns an `InvalidSeed` error if the file doesn't contain a valid seed
+pub fn load_keypair_from_file(path: impl AsRef<Path>) -> Result<KeyPair> {
+    let seed = std::fs::read_to_string(path)?;
+    KeyPair::from_seed(seed.trim()).map_err(|_| errors::new(ErrorKind::InvalidSeed))
+}
+
+/// Reads an nkeys seed from the named environment variable, as CI systems commonly provide
+/// signing keys, and creates the key pair it represents. Whitespace around the seed is ignored
+///
+/// # Errors
+/// Returns a `MissingEnvVar` error naming the variable if it isn't set or isn't valid unicode,
+/// or an `InvalidSeed` error if it doesn't contain a valid seed
+pub fn keypair_from_env(var_name: &str) -> Result<KeyPair> {
+    let seed = std::env::var(var_name)
+        .map_err(|_| errors::new(ErrorKind::MissingEnvVar(var_name.to_string())))?;
+    KeyPair::from_seed(seed.trim()).map_err(|_| errors::new(ErrorKind::InvalidSeed))
+}
+
+/// Signs the module at `in_path` with claims built from the template and writes the signed
+/// module to `out_path` in the same way as `sign_file_with_claims`, reading the account and
+/// module keys from the given seed files
+pub fn sign_file(
+    in_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    acct_seed_path: impl AsRef<Path>,
+    mod_seed_path: impl AsRef<Path>,
+    name: &str,
+    template: &ClaimsTemplate,
+) -> Result<()> {
+    let acct_kp = load_keypair_from_file(acct_seed_path)?;
+    let mod_kp = load_keypair_from_file(mod_seed_path)?;
+    let buf = std::fs::read(in_path)?;
+
+    let claims = template.claims_for(name, acct_kp.public_key(), mod_kp.public_key())?;
+    let signed = embed_claims(&buf, &claims, &acct_kp)?;
+    write_atomically(out_path.as_ref(), &signed)
+}
+
+/// Embeds the claims in the module at `in_path` in the same way as `embed_claims` and writes the
+/// signed module to `out_path`. The module is written to a temporary file next to `out_path`
+/// that is then renamed over it, so a crash or error while writing never leaves a partially
+/// written module behind, and any existing file at `out_path` is untouched unless signing
+/// succeeds
+///
+/// # Errors
+/// Will return an error if reading, writing or renaming a file fails, or under the same
+/// conditions as `embed_claims`
+pub fn sign_file_with_claims(
+    in_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<()> {
+    let buf = std::fs::read(in_path)?;
+    let signed = embed_claims(&buf, claims, kp)?;
+    write_atomically(out_path.as_ref(), &signed)
+}
+
+/// Embeds the claims in the module at `path` and replaces it with the signed module, in the same
+/// way as `sign_file_with_claims`
+pub fn sign_file_in_place(
+    path: impl AsRef<Path>,
+    claims: &Claims<Actor>,
+    kp: &dyn WascapSigner,
+) -> Result<()> {
+    sign_file_with_claims(path.as_ref(), path.as_ref(), claims, kp)
+}
+
+// The temporary file is created in the destination's directory, since a rename is only atomic
+// within a single file system
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a file path"))?;
+    let tmp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        nuid::next()
+    ));
+    let written = std::fs::File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    Ok(written?)
+}
+
+/// The claims shared by every module signed with `sign_batch` or `sign_file`. Each module gets its
+/// own name and module key, everything else is taken from the template
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClaimsTemplate {
+    pub caps: Vec<String>,
+    pub tags: Vec<String>,
+    pub expires_in_days: Option<u64>,
+    pub not_before_days: Option<u64>,
+    pub provider: bool,
+    pub rev: Option<i32>,
+    pub ver: Option<String>,
+}
+
+impl ClaimsTemplate {
+    fn claims_for(&self, name: &str, issuer: String, subject: String) -> Result<Claims<Actor>> {
+        Ok(Claims::<Actor>::with_dates(
+            name.to_string(),
+            issuer,
+            subject,
+            Some(self.caps.clone()),
+            Some(self.tags.clone()),
+            days_from_now_to_jwt_time(self.not_before_days)?,
+            days_from_now_to_jwt_time(self.expires_in_days)?,
+            self.provider,
+            self.rev,
+            self.ver.clone(),
+            None,
+        ))
+    }
+}
+
+/// Signs a batch of named modules with the same account and template, spreading the hashing and
+/// signing across rayon's thread pool, which has one thread per core. Each module is signed for
+/// the module key it's paired with, so the caller decides how module keys are generated and can
+/// keep their seeds. The results are returned in the same order as the modules, and a failure to
+/// sign one module doesn't affect the others
+#[cfg(feature = "rayon")]
+pub fn sign_batch(
+    modules: Vec<(String, Vec<u8>, KeyPair)>,
+    acct_kp: &KeyPair,
+    template: &ClaimsTemplate,
+) -> Vec<Result<Vec<u8>>> {
+    use rayon::prelude::*;
+
+    modules
+        .par_iter()
+        .map(|(name, buf, mod_kp)| {
+            let claims = template.claims_for(name, acct_kp.public_key(), mod_kp.public_key())?;
+            embed_claims(buf, &claims, acct_kp)
+        })
+        .collect()
+}
+
+/// Converts a number of days from now into a JWT timestamp, in _seconds since the epoch_
+///
+/// # Errors
+/// Returns a `ClockError` if the system clock is set before the UNIX epoch, or a
+/// `TimestampOverflow` error if the time is too far in the future to be represented
+pub fn days_from_now_to_jwt_time(stamp: Option<u64>) -> Result<Option<u64>> {
+    days_from_now_to_jwt_time_with_clock(&SystemClock, stamp)
+}
+
+/// Converts a number of days from now, according to the given clock, into a JWT timestamp
+///
+/// # Errors
+/// Returns a `ClockError` if the clock is set before the UNIX epoch, or a `TimestampOverflow`
+/// error if the time is too far in the future to be represented
+pub fn days_from_now_to_jwt_time_with_clock(
+    clock: &dyn Clock,
+    stamp: Option<u64>,
+) -> Result<Option<u64>> {
+    from_now_to_jwt_time(clock, stamp, SECS_PER_DAY)
+}
+
+/// Converts a number of hours from now into a JWT timestamp, in _seconds since the epoch_
+///
+/// # Errors
+/// Returns a `ClockError` if the system clock is set before the UNIX epoch, or a
+/// `TimestampOverflow` error if the time is too far in the future to be represented
+pub fn hours_from_now_to_jwt_time(stamp: Option<u64>) -> Result<Option<u64>> {
+    from_now_to_jwt_time(&SystemClock, stamp, SECS_PER_HOUR)
+}
+
+/// Converts a number of minutes from now into a JWT timestamp, in _seconds since the epoch_
+///
+/// # Errors
+/// Returns a `ClockError` if the system clock is set before the UNIX epoch, or a
+/// `TimestampOverflow` error if the time is too far in the future to be represented
+pub fn minutes_from_now_to_jwt_time(stamp: Option<u64>) -> Result<Option<u64>> {
+    from_now_to_jwt_time(&SystemClock, stamp, SECS_PER_MINUTE)
+}
+
+fn from_now_to_jwt_time(clock: &dyn Clock, stamp: Option<u64>, unit: u64) -> Result<Option<u64>> {
+    stamp
+        .map(|e| {
+            let now = since_the_epoch_with(clock)?.as_secs();
+            e.checked_mul(unit)
+                .and_then(|secs| now.checked_add(secs))
+                .ok_or_else(|| errors::new(ErrorKind::TimestampOverflow))
+        })
+        .transpose()
+}
+
+// The time the given duration from now, in _seconds since the epoch_
+fn duration_from_now(now: u64, d: Option<Duration>) -> Result<Option<u64>> {
+    d.map(|d| {
+        now.checked_add(d.as_secs())
+            .ok_or_else(|| errors::new(ErrorKind::TimestampOverflow))
+    })
+    .transpose()
+}
+
+/// Computes the hash of a WebAssembly module or component that is stored in its embedded claims
+/// (the `module_hash` field of `Actor`). This is the value `extract_claims` compares against to
+/// detect tampering, exposed so the same check can be made against separately stored tokens.
+///
+/// Rather than the entire file, the hash covers only the things that indicate tampering. The
+/// following bytes are concatenated, in the order they appear in the binary, and the result is
+/// hashed with SHA-256 and encoded as upper-case hex (see `compute_module_hash_with_algorithm`
+/// for other algorithms):
+/// * The body of every code section entry (function locals and instructions)
+/// * The data of the _first_ segment of every data section
+/// * The data (excluding the name) of every custom section, except top-level custom sections
+///   named `jwt` or `wasmcloud_jwt`
+/// * For components, the raw contents of every component-level section other than nested
+///   module and component sections, which are instead visited and hashed by the rules above
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed
+pub fn compute_module_hash(modbytes: &[u8]) -> Result<String> {
+    compute_module_hash_with_algorithm(modbytes, HashAlgorithm::Sha256)
+}
+
+/// Computes the hash of a WebAssembly module or component over the same bytes as
+/// `compute_module_hash`, using the given hash algorithm
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed
+pub fn compute_module_hash_with_algorithm(
+    modbytes: &[u8],
+    algorithm: HashAlgorithm,
+) -> Result<String> {
+    compute_module_hash_with_scope(modbytes, algorithm, &HashScope::default())
+}
+
+/// Computes the hash of a WebAssembly module or component in the same way as
+/// `compute_module_hash_with_algorithm`, leaving out the custom sections excluded by `scope`
+///
+/// # Errors
+/// Will return an error if the module cannot be parsed
+pub fn compute_module_hash_with_scope(
+    modbytes: &[u8],
+    algorithm: HashAlgorithm,
+    scope: &HashScope,
+) -> Result<String> {
+    let mut hasher = ModuleHasher::new(algorithm, scope.clone());
+    for payload in Parser::new(0).parse_all(modbytes) {
+        hasher.update(&payload?, modbytes, 0)?;
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Accumulates the hash of a module from its parsed payloads, so the same hash can be computed
+/// over a module held in memory or one being streamed from a reader
+struct ModuleHasher {
+    context: Context,
+    encodings: Vec<Encoding>,
+    scope: HashScope,
+}
+
+impl ModuleHasher {
+    fn new(algorithm: HashAlgorithm, scope: HashScope) -> ModuleHasher {
+        let algorithm = match algorithm {
+            HashAlgorithm::Sha256 => &SHA256,
+            HashAlgorithm::Sha512 => &SHA512,
+        };
+        ModuleHasher {
+            context: Context::new(algorithm),
+            encodings: Vec::new(),
+            scope,
+        }
+    }
+
+    /// Indicates whether the payloads currently being seen belong to the top-level module
+    /// or component, rather than one nested inside it
+    fn at_top_level(&self) -> bool {
+        self.encodings.len() == 1
+    }
+
+    /// Adds a payload to the hash. `bytes` must contain the payload's section, and start at
+    /// the absolute offset `offset` within the module
+    fn update(&mut self, payload: &Payload, bytes: &[u8], offset: usize) -> Result<()> {
+        match payload {
+            Version { encoding, .. } => self.encodings.push(*encoding),
+            End(_) => {
+                self.encodings.pop();
+            }
+            CodeSectionEntry(fb) => {
+                let mut rdr = fb.get_binary_reader();
+                let remaining = rdr.bytes_remaining();
+                self.context.update(rdr.read_bytes(remaining)?);
+            }
+            DataSection(reader) => {
+                if !self.scope.exclude_data {
+                    self.context.update(reader.clone().read()?.data);
+                }
+            }
+            CustomSection(reader) => {
+                let is_claims = self.at_top_level() && is_claims_section(reader.name());
+                if !is_claims && !self.scope.excludes(reader.name()) {
+                    self.context.update(reader.data());
+                }
+            }
+            // Nested modules and components are visited by the parser in turn
+            ModuleSection { .. } | ComponentSection { .. } => {}
+            _ => {
+                if self.encodings.last() == Some(&Encoding::Component) {
+                    if let Some((_, range)) = payload.as_section() {
+                        self.context
+                            .update(&bytes[range.start - offset..range.end - offset]);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> String {
+        HEXUPPER.encode(self.context.finish().as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        caps::{KEY_VALUE, LOGGING, MESSAGING},
+        jwt::{
+            verify_oci_reference, Actor, CapConstraint, CapabilityProvider, Claims, ClaimsBuilder,
+            WASCAP_INTERNAL_REVISION,
+        },
+    };
+    use base64::decode;
+    use std::collections::HashMap;
+
+    // Appends a custom section to the end of a module, as a tool unaware of claims would
+    fn write_custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+        let section = wasm_encoder::CustomSection { name, data };
+        module.push(section.id());
+        section.encode(module);
+    }
+
+    // Replaces the claims of a signed module with edited ones that keep the original signature,
+    // as someone editing the JWT payload by hand would. The module hash of the edited claims
+    // matches the module, so only the signature gives them away
+    fn forge_claims(signed: &[u8], edit: impl FnOnce(&mut Claims<Actor>)) -> Vec<u8> {
+        forge_claims_in(SECTION_WC_JWT, signed, edit)
+    }
+
+    fn forge_claims_in(
+        section: &str,
+        signed: &[u8],
+        edit: impl FnOnce(&mut Claims<Actor>),
+    ) -> Vec<u8> {
+        let jwt = extract_claims(signed).unwrap().unwrap().jwt;
+        let mut claims = Claims::<Actor>::decode(&jwt).unwrap();
+        edit(&mut claims);
+        let segments: Vec<&str> = jwt.split('.').collect();
+        let payload = base64::encode_config(
+            serde_json::to_vec(&claims).unwrap(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let forged = format!("{}.{}.{}", segments[0], payload, segments[2]);
+        let mut module = strip_claims(signed).unwrap();
+        write_custom_section(&mut module, section, forged.as_bytes());
+        module
+    }
+
+    const WASM_BASE64: &str =
+        "AGFzbQEAAAAADAZkeWxpbmuAgMACAAGKgICAAAJgAn9/AX9gAAACwYCAgAAEA2VudgptZW1vcnlCYXNl\
+         A38AA2VudgZtZW1vcnkCAIACA2VudgV0YWJsZQFwAAADZW52CXRhYmxlQmFzZQN/AAOEgICAAAMAAQEGi\
+         4CAgAACfwFBAAt/AUEACwejgICAAAIKX3RyYW5zZm9ybQAAEl9fcG9zdF9pbnN0YW50aWF0ZQACCYGAgI\
+         AAAArpgICAAAPBgICAAAECfwJ/IABBAEoEQEEAIQIFIAAPCwNAIAEgAmoiAywAAEHpAEYEQCADQfkAOgA\
+         ACyACQQFqIgIgAEcNAAsgAAsLg4CAgAAAAQuVgICAAAACQCMAJAIjAkGAgMACaiQDEAELCw==";
+
+    #[test]
+    fn claims_roundtrip() {
+        // Serialize and de-serialize this because the module loader adds bytes to
+        // the above base64 encoded module.
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![KEY_VALUE.to_string(), MESSAGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: KeyPair::new_module().public_key(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let modified_bytecode = embed_claims(&dec_module, &claims, &kp).unwrap();
+        println!(
+            "Added {} bytes in custom section.",
+            modified_bytecode.len() - dec_module.len()
+        );
+        if let Some(token) = extract_claims(&modified_bytecode).unwrap() {
+            assert_eq!(claims.issuer, token.claims.issuer);
+            assert_eq!(
+                claims.metadata.as_ref().unwrap().caps,
+                token.claims.metadata.as_ref().unwrap().caps
+            );
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn claims_logging_roundtrip() {
+        // Serialize and de-serialize this because the module loader adds bytes to
+        // the above base64 encoded module.
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![MESSAGING.to_string(), LOGGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
             expires: None,
             id: nuid::next(),
             issued_at: 0,
             issuer: kp.public_key(),
-            subject: "test.wasm".to_string(),
+            subject: KeyPair::new_module().public_key(),
             not_before: None,
             wascap_revision: Some(WASCAP_INTERNAL_REVISION),
         };
-        let modified_bytecode = embed_claims(&dec_module, &claims, &kp).unwrap();
-        println!(
-            "Added {} bytes in custom section.",
-            modified_bytecode.len() - dec_module.len()
+        let modified_bytecode = embed_claims(&dec_module, &claims, &kp).unwrap();
+        println!(
+            "Added {} bytes in custom section.",
+            modified_bytecode.len() - dec_module.len()
+        );
+        if let Some(token) = extract_claims(&modified_bytecode).unwrap() {
+            assert_eq!(claims.issuer, token.claims.issuer);
+            assert_eq!(claims.subject, token.claims.subject);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn reembed_leaves_single_section() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![MESSAGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: KeyPair::new_module().public_key(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let once = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let twice = embed_claims(&once, &claims, &kp).unwrap();
+
+        let jwt_sections = wasmparser::Parser::new(0)
+            .parse_all(&twice)
+            .filter(|p| match p {
+                Ok(CustomSection(reader)) => {
+                    reader.name() == SECTION_JWT || reader.name() == SECTION_WC_JWT
+                }
+                _ => false,
+            })
+            .count();
+        assert_eq!(jwt_sections, 1);
+        assert_eq!(
+            strip_claims(&twice).unwrap(),
+            strip_claims(&dec_module).unwrap()
+        );
+        assert!(extract_claims(&twice).unwrap().is_some());
+    }
+
+    #[test]
+    fn multiple_tokens_rejected() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let other_kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![MESSAGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: KeyPair::new_module().public_key(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+
+        // An identical duplicate section is tolerated
+        let mut duplicated = embedded.clone();
+        write_custom_section(&mut duplicated, SECTION_JWT, token.jwt.as_bytes());
+        assert!(extract_claims(&duplicated).unwrap().is_some());
+
+        // A second, different token is not
+        let other = Claims {
+            issuer: other_kp.public_key(),
+            ..token.claims
+        }
+        .encode(&other_kp)
+        .unwrap();
+        let mut conflicting = embedded;
+        write_custom_section(&mut conflicting, SECTION_JWT, other.as_bytes());
+        match extract_claims(&conflicting) {
+            Err(e) => match e.kind() {
+                ErrorKind::MultipleTokens => (),
+                _ => panic!("failed to assert errors::ErrorKind::MultipleTokens"),
+            },
+            Ok(_) => panic!("conflicting tokens were accepted"),
+        }
+    }
+
+    #[cfg(feature = "rekor")]
+    #[tokio::test]
+    async fn transparency_log_lookup() {
+        use crate::rekor::test::{audit_path, tree_root};
+        use serde_json::{json, Value};
+
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let jwt = extract_claims(&embedded).unwrap().unwrap().jwt;
+        let hex = HEXLOWER.encode(digest(&SHA256, jwt.as_bytes()).as_ref());
+        let expected = format!("sha256:{}", hex);
+
+        // A log of five entries, the fourth of which records the JWT. The `tampered` log serves
+        // the same entry with an inclusion proof against a different root hash
+        let body = serde_json::to_vec(&json!({
+            "apiVersion": "0.0.1",
+            "kind": "hashedrekord",
+            "spec": { "data": { "hash": { "algorithm": "sha256", "value": hex } } },
+        }))
+        .unwrap();
+        let leaves: Vec<Vec<u8>> = vec![
+            b"first".to_vec(),
+            b"second".to_vec(),
+            b"third".to_vec(),
+            body.clone(),
+            b"fifth".to_vec(),
+        ];
+        let entry = |root: [u8; 32]| {
+            let hashes: Vec<String> = audit_path(3, &leaves)
+                .iter()
+                .map(|hash| HEXLOWER.encode(hash))
+                .collect();
+            serde_json::to_vec(&json!({ "24296fb24b8ad77a": {
+                "body": base64::encode(&body),
+                "logIndex": 3,
+                "verification": { "inclusionProof": {
+                    "logIndex": 3,
+                    "treeSize": 5,
+                    "rootHash": HEXLOWER.encode(&root),
+                    "hashes": hashes,
+                } },
+            } }))
+            .unwrap()
+        };
+        let root = tree_root(&leaves);
+        let mut forged = root;
+        forged[0] ^= 0xff;
+        let (good, tampered) = (entry(root), entry(forged));
+        let indexed = expected.clone();
+        let base = serve(move |method, path, request| {
+            let (log, route) = path[1..].split_once('/').unwrap_or_default();
+            let entry = match log {
+                "good" => &good,
+                "tampered" => &tampered,
+                _ => return ("404 Not Found".to_string(), Vec::new()),
+            };
+            match (method, route) {
+                ("POST", "api/v1/index/retrieve") => {
+                    let query: Value = serde_json::from_slice(request).unwrap();
+                    let uuids: &[&str] = if query["hash"] == indexed.as_str() {
+                        &["24296fb24b8ad77a"]
+                    } else {
+                        &[]
+                    };
+                    ("200 OK".to_string(), serde_json::to_vec(uuids).unwrap())
+                }
+                ("GET", "api/v1/log/entries/24296fb24b8ad77a") => {
+                    ("200 OK".to_string(), entry.clone())
+                }
+                _ => ("404 Not Found".to_string(), Vec::new()),
+            }
+        });
+
+        let token = verify_with_transparency(&embedded, &format!("{}/good/", base))
+            .await
+            .unwrap();
+        assert_eq!(token.jwt, jwt);
+
+        let other = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let unlogged = embed_claims(&dec_module, &other, &kp).unwrap();
+        let unlogged_jwt = extract_claims(&unlogged).unwrap().unwrap().jwt;
+        match verify_with_transparency(&unlogged, &format!("{}/good", base)).await {
+            Err(e) => match e.kind() {
+                ErrorKind::NotInTransparencyLog(entry) => assert_eq!(
+                    *entry,
+                    format!(
+                        "sha256:{}",
+                        HEXLOWER.encode(digest(&SHA256, unlogged_jwt.as_bytes()).as_ref())
+                    )
+                ),
+                _ => panic!("failed to assert errors::ErrorKind::NotInTransparencyLog"),
+            },
+            Ok(_) => panic!("verified a module missing from the log"),
+        }
+        match verify_with_transparency(&embedded, &format!("{}/tampered", base)).await {
+            Err(e) => match e.kind() {
+                ErrorKind::InclusionProofInvalid(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::InclusionProofInvalid"),
+            },
+            Ok(_) => panic!("verified a module with a tampered inclusion proof"),
+        }
+        match verify_with_transparency(&embedded, &format!("{}/missing", base)).await {
+            Err(e) => match e.kind() {
+                ErrorKind::Http(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::Http"),
+            },
+            Ok(_) => panic!("verified a module against a missing log"),
+        }
+
+        // The log is never consulted for modules that fail local verification, so an
+        // unreachable log makes no difference
+        match verify_with_transparency(&dec_module, "http://127.0.0.1:1").await {
+            Err(e) => match e.kind() {
+                ErrorKind::NoEmbeddedClaims => (),
+                _ => panic!("failed to assert errors::ErrorKind::NoEmbeddedClaims"),
+            },
+            Ok(_) => panic!("verified an unsigned module"),
+        }
+    }
+
+    #[test]
+    fn claims_section_order_independent() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let name: &[u8] = b"\x00\x01a";
+        let mut named = dec_module.clone();
+        write_custom_section(&mut named, "name", name);
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let token = extract_claims(embed_claims(&named, &claims, &kp).unwrap())
+            .unwrap()
+            .unwrap();
+        let stale = ClaimsBuilder::<Actor>::new()
+            .name("stale")
+            .issuer(&kp.public_key())
+            .subject(&claims.subject)
+            .build()
+            .encode(&kp)
+            .unwrap();
+        let reorder = |sections: &[(&str, &[u8])]| {
+            let mut module = dec_module.clone();
+            for (name, data) in sections {
+                write_custom_section(&mut module, name, data);
+            }
+            module
+        };
+
+        let jwt = (SECTION_WC_JWT, token.jwt.as_bytes());
+        let legacy = (SECTION_JWT, token.jwt.as_bytes());
+        let name = ("name", name);
+        for sections in &[
+            vec![name, jwt],
+            vec![jwt, name],
+            vec![jwt, name, legacy],
+            vec![legacy, name, jwt],
+        ] {
+            let extracted = extract_claims(reorder(sections)).unwrap().unwrap();
+            assert!(extracted.hash_verified);
+            assert_eq!(extracted, token);
+        }
+
+        let stale = (SECTION_JWT, stale.as_bytes());
+        for sections in &[vec![stale, name, jwt], vec![name, jwt, stale]] {
+            match extract_claims(reorder(sections)) {
+                Err(e) => match e.kind() {
+                    ErrorKind::MultipleTokens => (),
+                    _ => panic!("failed to assert errors::ErrorKind::MultipleTokens"),
+                },
+                Ok(_) => panic!("a stale token was accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn component_claims_roundtrip() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let mut component = wasm_encoder::Component::new();
+        component.section(&RawSection {
+            id: wasm_encoder::ComponentSectionId::CoreModule.into(),
+            data: &dec_module,
+        });
+        let component = component.finish();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![MESSAGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: KeyPair::new_module().public_key(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let embedded = embed_claims(&component, &claims, &kp).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(claims.issuer, token.claims.issuer);
+        assert_eq!(
+            Some(token),
+            extract_claims_from_reader(std::io::Cursor::new(&embedded)).unwrap()
+        );
+
+        // The nested module's code must be part of the component's hash
+        let empty_component = wasm_encoder::Component::new().finish();
+        assert_ne!(
+            compute_module_hash(&component).unwrap(),
+            compute_module_hash(&empty_component).unwrap()
+        );
+
+        // Claims embedded in the nested module don't belong to the component
+        let signed_module = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let mut outer = wasm_encoder::Component::new();
+        outer.section(&RawSection {
+            id: wasm_encoder::ComponentSectionId::CoreModule.into(),
+            data: &signed_module,
+        });
+        assert!(extract_claims(outer.finish()).unwrap().is_none());
+    }
+
+    #[test]
+    fn nested_module_claims() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let signed_module = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let compose = |modules: &[&[u8]]| {
+            let mut component = wasm_encoder::Component::new();
+            for module in modules {
+                component.section(&RawSection {
+                    id: wasm_encoder::ComponentSectionId::CoreModule.into(),
+                    data: module,
+                });
+            }
+            component.finish()
+        };
+
+        let composed = compose(&[&dec_module, &signed_module]);
+        assert!(extract_claims(&composed).unwrap().is_none());
+        let (token, nested) = extract_claims_nested(&composed).unwrap().unwrap();
+        assert!(nested);
+        assert!(token.hash_verified);
+        assert_eq!(token.claims.subject, claims.subject);
+
+        // Claims on the component itself take precedence
+        let component_claims = ClaimsBuilder::<Actor>::new()
+            .name("component")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let signed_component = embed_claims(&composed, &component_claims, &kp).unwrap();
+        let (token, nested) = extract_claims_nested(&signed_component).unwrap().unwrap();
+        assert!(!nested);
+        assert_eq!(token.claims.subject, component_claims.subject);
+
+        assert!(extract_claims_nested(compose(&[&dec_module]))
+            .unwrap()
+            .is_none());
+        let (_, nested) = extract_claims_nested(&signed_module).unwrap().unwrap();
+        assert!(!nested);
+    }
+
+    #[test]
+    fn empty_and_truncated_modules() {
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        for result in &[
+            extract_claims(b"").map(|_| ()),
+            embed_claims(b"", &claims, &kp).map(|_| ()),
+        ] {
+            match result {
+                Err(e) => match e.kind() {
+                    ErrorKind::EmptyModule => (),
+                    _ => panic!("failed to assert errors::ErrorKind::EmptyModule"),
+                },
+                Ok(_) => panic!("accepted an empty module"),
+            }
+        }
+
+        let truncated = b"\0asm\x01";
+        for result in &[
+            extract_claims(truncated).map(|_| ()),
+            embed_claims(truncated, &claims, &kp).map(|_| ()),
+        ] {
+            match result {
+                Err(e) => match e.kind() {
+                    ErrorKind::NotAWasmModule(preamble) => assert_eq!(preamble, truncated),
+                    _ => panic!("failed to assert errors::ErrorKind::NotAWasmModule"),
+                },
+                Ok(_) => panic!("accepted a truncated module"),
+            }
+        }
+    }
+
+    #[test]
+    fn non_wasm_input() {
+        let json = br#"{"name": "not a module"}"#;
+        let unknown_version = b"\0asm\x02\0\0\0";
+        for bytes in &[&json[..], &unknown_version[..]] {
+            match extract_claims(bytes) {
+                Err(e) => match e.kind() {
+                    ErrorKind::NotAWasmModule(preamble) => assert_eq!(preamble, &bytes[..8]),
+                    _ => panic!("failed to assert errors::ErrorKind::NotAWasmModule"),
+                },
+                Ok(_) => panic!("accepted input that isn't a module"),
+            }
+        }
+        assert!(extract_claims(decode(WASM_BASE64).unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn component_entry_points() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let mut component = wasm_encoder::Component::new();
+        component.section(&RawSection {
+            id: wasm_encoder::ComponentSectionId::CoreModule.into(),
+            data: &dec_module,
+        });
+        let component = component.finish();
+        assert!(is_component(&component).unwrap());
+        assert!(!is_component(&dec_module).unwrap());
+        assert!(is_component(b"").is_err());
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims_component(&component, &claims, &kp).unwrap();
+        assert!(is_component(&embedded).unwrap());
+        let token = extract_claims_component(&embedded).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert!(list_custom_sections(&embedded)
+            .unwrap()
+            .iter()
+            .any(|(name, _)| name == SECTION_WC_JWT));
+
+        for result in &[
+            embed_claims_component(&dec_module, &claims, &kp).map(|_| ()),
+            extract_claims_component(&dec_module).map(|_| ()),
+        ] {
+            match result {
+                Err(e) => match e.kind() {
+                    ErrorKind::WasmElement(_) => (),
+                    _ => panic!("failed to assert errors::ErrorKind::WasmElement"),
+                },
+                Ok(_) => panic!("accepted a core module as a component"),
+            }
+        }
+    }
+
+    #[test]
+    fn migrate_legacy_section() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let legacy_opts = EmbedOptions {
+            section_name: SECTION_JWT.to_string(),
+            ..Default::default()
+        };
+        let legacy = embed_claims_with_options(&dec_module, &claims, &kp, &legacy_opts).unwrap();
+
+        let migrated = migrate_section_name(&legacy).unwrap().unwrap();
+        let names: Vec<String> = list_custom_sections(&migrated)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(names.contains(&SECTION_WC_JWT.to_string()));
+        assert!(!names.contains(&SECTION_JWT.to_string()));
+        let before = extract_claims(&legacy).unwrap().unwrap();
+        let after = extract_claims(&migrated).unwrap().unwrap();
+        assert_eq!(after.jwt, before.jwt);
+        assert!(after.hash_verified);
+
+        assert!(migrate_section_name(&migrated).unwrap().is_none());
+        assert!(migrate_section_name(&dec_module).unwrap().is_none());
+
+        let forged = forge_claims_in(SECTION_JWT, &legacy, |claims| {
+            claims.subject = KeyPair::new_module().public_key();
+        });
+        match migrate_section_name(&forged) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("migrated forged claims"),
+        }
+    }
+
+    #[test]
+    fn embed_with_legacy_section_name() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            metadata: Some(Actor::new(
+                "testing".to_string(),
+                Some(vec![MESSAGING.to_string()]),
+                Some(vec![]),
+                false,
+                Some(1),
+                Some("".to_string()),
+                None,
+            )),
+            expires: None,
+            id: nuid::next(),
+            issued_at: 0,
+            issuer: kp.public_key(),
+            subject: KeyPair::new_module().public_key(),
+            not_before: None,
+            wascap_revision: Some(WASCAP_INTERNAL_REVISION),
+        };
+        let opts = EmbedOptions {
+            section_name: SECTION_JWT.to_string(),
+            overwrite_existing: false,
+            ..Default::default()
+        };
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let legacy = embed_claims_with_options(&embedded, &claims, &kp, &opts).unwrap();
+
+        let names: Vec<String> = wasmparser::Parser::new(0)
+            .parse_all(&legacy)
+            .filter_map(|p| match p {
+                Ok(CustomSection(reader)) => Some(reader.name().to_string()),
+                _ => None,
+            })
+            .filter(|n| n == SECTION_JWT || n == SECTION_WC_JWT)
+            .collect();
+        assert_eq!(names, vec![SECTION_WC_JWT, SECTION_JWT]);
+
+        let overwritten = embed_claims_with_options(
+            &legacy,
+            &claims,
+            &kp,
+            &EmbedOptions {
+                overwrite_existing: true,
+                ..opts
+            },
+        )
+        .unwrap();
+        let token = extract_claims(&overwritten).unwrap().unwrap();
+        assert_eq!(claims.issuer, token.claims.issuer);
+    }
+
+    #[test]
+    fn module_hash_matches_claims() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims::<Actor>::new(
+            "testing".to_string(),
+            kp.public_key(),
+            KeyPair::new_module().public_key(),
+            Some(vec![MESSAGING.to_string()]),
+            None,
+            false,
+            Some(1),
+            None,
+            None,
+        );
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        let hash = compute_module_hash(&embedded).unwrap();
+        assert_eq!(hash, compute_module_hash(&dec_module).unwrap());
+        assert_eq!(hash, token.claims.metadata.unwrap().module_hash);
+    }
+
+    #[test]
+    fn sha512_claims_roundtrip() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let mut claims = Claims::<Actor>::new(
+            "testing".to_string(),
+            kp.public_key(),
+            KeyPair::new_module().public_key(),
+            Some(vec![MESSAGING.to_string()]),
+            None,
+            false,
+            Some(1),
+            None,
+            None,
+        );
+        if let Some(ref mut meta) = claims.metadata {
+            meta.hash_algorithm = HashAlgorithm::Sha512;
+        }
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        let meta = token.claims.metadata.unwrap();
+        assert_eq!(meta.hash_algorithm, HashAlgorithm::Sha512);
+        assert_eq!(
+            meta.module_hash,
+            compute_module_hash_with_algorithm(&dec_module, HashAlgorithm::Sha512).unwrap()
+        );
+        assert_ne!(meta.module_hash, compute_module_hash(&dec_module).unwrap());
+    }
+
+    #[test]
+    fn claims_from_reader() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let mut claims = Claims::<Actor>::new(
+            "testing".to_string(),
+            kp.public_key(),
+            KeyPair::new_module().public_key(),
+            Some(vec![MESSAGING.to_string()]),
+            None,
+            false,
+            Some(1),
+            None,
+            None,
+        );
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims_from_reader(std::io::Cursor::new(&embedded))
+            .unwrap()
+            .unwrap();
+        assert_eq!(token, extract_claims(&embedded).unwrap().unwrap());
+
+        if let Some(ref mut meta) = claims.metadata {
+            meta.hash_algorithm = HashAlgorithm::Sha512;
+        }
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims_from_reader(std::io::Cursor::new(&embedded))
+            .unwrap()
+            .unwrap();
+        assert_eq!(token, extract_claims(&embedded).unwrap().unwrap());
+
+        assert!(
+            extract_claims_from_reader(std::io::Cursor::new(&dec_module))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn claims_located() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = Claims::<Actor>::new(
+            "testing".to_string(),
+            kp.public_key(),
+            KeyPair::new_module().public_key(),
+            Some(vec![MESSAGING.to_string()]),
+            None,
+            false,
+            Some(1),
+            None,
+            None,
+        );
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let (token, range) = extract_claims_located(&embedded).unwrap().unwrap();
+
+        // The JWT section is the last one in the module, preceded by its length-prefixed name
+        assert_eq!(range.end, embedded.len());
+        let name_len = SECTION_WC_JWT.len();
+        assert_eq!(embedded[range.start] as usize, name_len);
+        assert_eq!(
+            &embedded[range.start + 1..range.start + 1 + name_len],
+            SECTION_WC_JWT.as_bytes()
+        );
+        assert_eq!(
+            &embedded[range.start + 1 + name_len..range.end],
+            token.jwt.as_bytes()
+        );
+    }
+
+    #[test]
+    fn embed_rejects_wrong_key_types() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let account = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let operator = KeyPair::new_operator();
+        let bad_issuer = Claims::<Actor>::new(
+            "testing".to_string(),
+            module.public_key(),
+            module.public_key(),
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+        );
+        let bad_subject = Claims::<Actor>::new(
+            "testing".to_string(),
+            account.public_key(),
+            operator.public_key(),
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+        );
+
+        for (claims, kp) in &[(bad_issuer, &module), (bad_subject, &account)] {
+            match embed_claims(&dec_module, claims, kp) {
+                Err(e) => match e.kind() {
+                    ErrorKind::InvalidKeyType => (),
+                    _ => panic!("failed to assert errors::ErrorKind::InvalidKeyType"),
+                },
+                Ok(_) => panic!("claims with the wrong key types were embedded"),
+            }
+        }
+
+        // Account and module keys swapped
+        let signed = sign_buffer_with_claims(
+            "testing".to_string(),
+            &dec_module,
+            account,
+            module,
+            None,
+            None,
+            vec![],
+            vec![],
+            false,
+            None,
+            None,
+            None,
+            None,
+        );
+        assert!(signed.is_err());
+    }
+
+    #[test]
+    fn sign_with_oci_reference() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let reference = "registry.example.com/echo:0.1.0";
+        let positional = sign_buffer_with_claims(
+            "testing".to_string(),
+            &dec_module,
+            KeyPair::new_module(),
+            KeyPair::new_account(),
+            None,
+            None,
+            vec![],
+            vec![],
+            false,
+            None,
+            None,
+            None,
+            Some(reference.to_string()),
+        )
+        .unwrap();
+        let built = sign_buffer_with_builder(
+            &dec_module,
+            &KeyPair::new_module(),
+            &KeyPair::new_account(),
+            ClaimsBuilder::<Actor>::new()
+                .name("testing")
+                .oci_reference(reference),
+        )
+        .unwrap();
+        for module in &[positional, built] {
+            let token = extract_claims(module).unwrap().unwrap();
+            assert!(verify_oci_reference(&token, reference));
+        }
+    }
+
+    #[test]
+    fn provider_claims_roundtrip() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let account = KeyPair::new_account();
+        let provider = KeyPair::new_service();
+        let mut hashes = HashMap::new();
+        hashes.insert("wasm32-unknown".to_string(), "abc12345".to_string());
+        let claims = ClaimsBuilder::new()
+            .issuer(&account.public_key())
+            .subject(&provider.public_key())
+            .with_metadata(CapabilityProvider::new(
+                "Test Provider".to_string(),
+                "wasmcloud:testing".to_string(),
+                "wasmCloud Internal".to_string(),
+                Some(1),
+                Some("v0.0.1".to_string()),
+                hashes,
+            ))
+            .build();
+
+        let embedded = embed_claims_generic(&dec_module, &claims, &account).unwrap();
+        let token = extract_claims_generic::<CapabilityProvider>(&embedded)
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.claims.subject, provider.public_key());
+        let meta = token.claims.metadata.unwrap();
+        assert_eq!(meta.capid, "wasmcloud:testing");
+        assert_eq!(meta.vendor, "wasmCloud Internal");
+        assert_eq!(
+            meta.module_hash,
+            Some(compute_module_hash(&dec_module).unwrap())
+        );
+
+        // Tampering with the module is detected just as it is for actors
+        let tampered = Claims {
+            metadata: Some(CapabilityProvider {
+                module_hash: Some("bad".to_string()),
+                ..claims.metadata.clone().unwrap()
+            }),
+            ..claims
+        }
+        .encode(&account)
+        .unwrap();
+        let mut bad = strip_claims(&dec_module).unwrap();
+        write_custom_section(&mut bad, SECTION_WC_JWT, tampered.as_bytes());
+        assert!(extract_claims_generic::<CapabilityProvider>(&bad).is_err());
+    }
+
+    #[test]
+    fn oversized_jwt_section() {
+        let mut module = decode(WASM_BASE64).unwrap();
+        write_custom_section(
+            &mut module,
+            SECTION_WC_JWT,
+            &vec![b'a'; DEFAULT_MAX_JWT_SECTION_SIZE + 1],
+        );
+
+        for result in &[
+            extract_claims(&module),
+            extract_claims_from_reader(std::io::Cursor::new(&module)),
+        ] {
+            match result {
+                Err(e) => match e.kind() {
+                    ErrorKind::JwtSectionTooLarge { size, max } => {
+                        assert_eq!(*size, DEFAULT_MAX_JWT_SECTION_SIZE + 1);
+                        assert_eq!(*max, DEFAULT_MAX_JWT_SECTION_SIZE);
+                    }
+                    _ => panic!("failed to assert errors::ErrorKind::JwtSectionTooLarge"),
+                },
+                Ok(_) => panic!("accepted an oversized JWT section"),
+            }
+        }
+        // A higher limit lets the section through to be parsed as a (malformed) token
+        match extract_claims_with_max_size(&module, 2 * DEFAULT_MAX_JWT_SECTION_SIZE) {
+            Err(e) => assert!(!matches!(e.kind(), ErrorKind::JwtSectionTooLarge { .. })),
+            Ok(_) => panic!("extracted claims from a malformed token"),
+        }
+    }
+
+    #[test]
+    fn co_signatures() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let author = KeyPair::new_account();
+        let subject = KeyPair::new_module().public_key();
+        let claims_for = |kp: &KeyPair| {
+            ClaimsBuilder::<Actor>::new()
+                .name("test")
+                .issuer(&kp.public_key())
+                .subject(&subject)
+                .build()
+        };
+        assert!(embed_additional_signature(&dec_module, &claims_for(&author), &author).is_err());
+
+        let signed = embed_claims(&dec_module, &claims_for(&author), &author).unwrap();
+        let security = KeyPair::new_account();
+        let cosigned =
+            embed_additional_signature(&signed, &claims_for(&security), &security).unwrap();
+
+        let primary = extract_claims(&cosigned).unwrap().unwrap();
+        assert!(primary.hash_verified);
+        assert_eq!(primary.claims.issuer, author.public_key());
+        let issuers: Vec<String> = extract_all_claims(&cosigned)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.claims.issuer)
+            .collect();
+        assert_eq!(issuers, vec![author.public_key(), security.public_key()]);
+
+        // Co-signed claims are checked and normalized like the primary ones
+        let expired = Claims {
+            expires: Some(1),
+            ..claims_for(&security)
+        };
+        match embed_additional_signature(&signed, &expired, &security) {
+            Err(e) => match e.kind() {
+                ErrorKind::AlreadyExpired(1) => (),
+                _ => panic!("failed to assert errors::ErrorKind::AlreadyExpired"),
+            },
+            Ok(_) => panic!("co-signed claims that were already expired"),
+        }
+        let mut unsorted = claims_for(&security);
+        unsorted.metadata.as_mut().unwrap().caps = Some(vec![
+            MESSAGING.to_string(),
+            caps::LOGGING.to_string(),
+            MESSAGING.to_string(),
+        ]);
+        let normalized = embed_additional_signature(&signed, &unsorted, &security).unwrap();
+        assert_eq!(
+            extract_all_claims(&normalized).unwrap()[1]
+                .claims
+                .metadata
+                .as_ref()
+                .unwrap()
+                .caps,
+            Some(vec![caps::LOGGING.to_string(), MESSAGING.to_string()])
+        );
+
+        // Re-signing the primary claims keeps the co-signature, stripping removes both
+        let other = KeyPair::new_account();
+        let resigned = re_sign(&cosigned, &other, true).unwrap();
+        assert_eq!(extract_all_claims(&resigned).unwrap().len(), 2);
+        assert_eq!(
+            strip_claims(&cosigned).unwrap(),
+            strip_claims(&dec_module).unwrap()
+        );
+
+        // A co-signature for another module is refused, and a forged one is ignored
+        let mut wrong = claims_for(&security);
+        wrong.subject = KeyPair::new_module().public_key();
+        assert!(embed_additional_signature(&signed, &wrong, &security).is_err());
+        let mut forged = cosigned.clone();
+        write_custom_section(&mut forged, SECTION_COSIGNATURE, b"not.a.jwt");
+        assert_eq!(extract_all_claims(&forged).unwrap().len(), 2);
+
+        // Primary claims that were edited after signing aren't co-signed
+        let edited = forge_claims(&signed, |claims| {
+            claims.metadata.as_mut().unwrap().name = Some("edited".to_string());
+        });
+        match embed_additional_signature(&edited, &claims_for(&security), &security) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("co-signed edited claims"),
+        }
+    }
+
+    #[test]
+    fn strict_hash_for_legacy_tokens() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            wascap_revision: Some(MIN_WASCAP_INTERNAL_REVISION - 1),
+            ..ClaimsBuilder::<Actor>::new()
+                .name("test")
+                .issuer(&kp.public_key())
+                .subject(&KeyPair::new_module().public_key())
+                .build()
+        };
+        let mut embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert!(extract_claims_strict(&embedded).unwrap().is_some());
+        assert!(extract_claims_strict(&dec_module).unwrap().is_none());
+
+        write_custom_section(&mut embedded, "extra", b"changes the module hash");
+        assert!(!extract_claims(&embedded).unwrap().unwrap().hash_verified);
+        match extract_claims_strict(&embedded) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidModuleHash => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidModuleHash"),
+            },
+            Ok(_) => panic!("accepted a legacy token with a mismatched hash"),
+        }
+
+        // Tokens whose payload was edited, or whose signature was removed, are refused before
+        // the hash is considered
+        let signed = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let tampered = forge_claims(&signed, |claims| claims.issued_at -= 1);
+        let jwt = extract_claims(&signed).unwrap().unwrap().jwt;
+        let mut unsigned = strip_claims(&signed).unwrap();
+        let unsigned_jwt = &jwt[..=jwt.rfind('.').unwrap()];
+        write_custom_section(&mut unsigned, SECTION_WC_JWT, unsigned_jwt.as_bytes());
+        for (module, expected) in &[
+            (tampered, ErrorKind::SignatureInvalid),
+            (unsigned, ErrorKind::SignatureMissing),
+        ] {
+            match extract_claims_strict(module) {
+                Err(e) => assert_eq!(
+                    std::mem::discriminant(e.kind()),
+                    std::mem::discriminant(expected)
+                ),
+                Ok(_) => panic!("accepted a token without a valid signature"),
+            }
+        }
+    }
+
+    #[test]
+    fn stats_for_module() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let expected = ModuleStats {
+            total_size: dec_module.len(),
+            num_functions: 3,
+            num_exports: 2,
+            num_imports: 4,
+            num_custom_sections: 1,
+            has_start: false,
+        };
+        assert_eq!(module_stats(&dec_module).unwrap(), expected);
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert_eq!(
+            module_stats(&embedded).unwrap(),
+            ModuleStats {
+                total_size: embedded.len(),
+                num_custom_sections: 2,
+                ..expected
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_module_hash() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embed_hash = |hash: &str| {
+            let mut claims = claims.clone();
+            claims.metadata.as_mut().unwrap().module_hash = hash.to_string();
+            let mut module = strip_claims(&dec_module).unwrap();
+            write_custom_section(
+                &mut module,
+                SECTION_WC_JWT,
+                claims.encode(&kp).unwrap().as_bytes(),
+            );
+            module
+        };
+
+        match extract_claims(embed_hash("0123456789")) {
+            Err(e) => match e.kind() {
+                ErrorKind::MalformedHash(hash) => assert_eq!(hash, "0123456789"),
+                _ => panic!("failed to assert errors::ErrorKind::MalformedHash"),
+            },
+            Ok(_) => panic!("accepted a truncated module hash"),
+        }
+        // A well-formed hash of some other module is an ordinary mismatch
+        let other = compute_module_hash(b"\0asm\x01\0\0\0").unwrap();
+        match extract_claims(embed_hash(&other)) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidModuleHash => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidModuleHash"),
+            },
+            Ok(_) => panic!("accepted a mismatched module hash"),
+        }
+    }
+
+    #[test]
+    fn reader_error_kind() {
+        struct TimingOut;
+
+        impl Read for TimingOut {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::TimedOut.into())
+            }
+        }
+
+        impl Seek for TimingOut {
+            fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+                Ok(0)
+            }
+        }
+
+        let err = extract_claims_from_reader(TimingOut).unwrap_err();
+        assert_eq!(err.io_kind(), Some(std::io::ErrorKind::TimedOut));
+        let err = extract_claims(b"not a module").unwrap_err();
+        assert_eq!(err.io_kind(), None);
+    }
+
+    #[test]
+    fn constant_time_hash_comparison() {
+        let hash = compute_module_hash(&decode(WASM_BASE64).unwrap()).unwrap();
+        let other = compute_module_hash(b"\0asm\x01\0\0\0").unwrap();
+        assert!(hashes_match(&hash, &hash, HashAlgorithm::Sha256).unwrap());
+        assert!(!hashes_match(&other, &hash, HashAlgorithm::Sha256).unwrap());
+        // Hashes are compared as digests, so both have to be valid for the algorithm
+        assert!(hashes_match(&hash[..10], &hash, HashAlgorithm::Sha256).is_err());
+        assert!(hashes_match(&hash, &hash, HashAlgorithm::Sha512).is_err());
+    }
+
+    #[test]
+    fn missing_metadata() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = Claims::<Actor> {
+            metadata: None,
+            ..ClaimsBuilder::<Actor>::new()
+                .issuer(&kp.public_key())
+                .subject(&KeyPair::new_module().public_key())
+                .build()
+        };
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        match extract_claims(&embedded) {
+            Err(e) => match e.kind() {
+                ErrorKind::MissingMetadata => (),
+                _ => panic!("failed to assert errors::ErrorKind::MissingMetadata"),
+            },
+            Ok(_) => panic!("extracted claims without metadata"),
+        }
+    }
+
+    #[test]
+    fn strict_capabilities() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(KEY_VALUE)
+            .with_cap("wasmcloud:keyvlaue")
+            .build();
+        let strict = EmbedOptions {
+            strict_capabilities: true,
+            ..Default::default()
+        };
+
+        assert!(embed_claims(&dec_module, &claims, &kp).is_ok());
+        match embed_claims_with_options(&dec_module, &claims, &kp, &strict) {
+            Err(e) => match e.kind() {
+                ErrorKind::UnknownCapability(cap) => assert_eq!(cap, "wasmcloud:keyvlaue"),
+                _ => panic!("failed to assert errors::ErrorKind::UnknownCapability"),
+            },
+            Ok(_) => panic!("unknown capability was embedded in strict mode"),
+        }
+    }
+
+    #[test]
+    fn strict_capabilities_expand_aliases() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(caps::STANDARD_IO)
+            .with_cap(caps::LOGGING)
+            .with_cap(KEY_VALUE)
+            .build();
+        let strict = EmbedOptions {
+            strict_capabilities: true,
+            ..Default::default()
+        };
+
+        let embedded = embed_claims_with_options(&dec_module, &claims, &kp, &strict).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(
+            token.claims.metadata.unwrap().caps.unwrap(),
+            vec![caps::LOGGING, caps::NUMBERGEN, KEY_VALUE]
+        );
+
+        // The registry is shared with the other tests, so the alias is removed again
+        let bundle = vec!["test-bundle".to_string(), "custom".to_string()];
+        caps::register_capability_alias("test-bundle", &[MESSAGING, "wasmcloud:unknown"]);
+        let expanded = caps::expand_capability_aliases(&bundle);
+        assert!(caps::unregister_capability_alias("test-bundle"));
+        assert_eq!(expanded, vec![MESSAGING, "wasmcloud:unknown", "custom"]);
+        assert_eq!(caps::expand_capability_aliases(&bundle), bundle);
+        assert!(!caps::unregister_capability_alias("test-bundle"));
+    }
+
+    struct PreEpochClock;
+
+    impl Clock for PreEpochClock {
+        fn now_secs(&self) -> u64 {
+            0
+        }
+
+        fn now(&self) -> std::time::SystemTime {
+            std::time::UNIX_EPOCH - std::time::Duration::from_secs(60)
+        }
+    }
+
+    struct FixedClock(u64);
+
+    impl Clock for FixedClock {
+        fn now_secs(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn fixed_clock_timestamps() {
+        assert_eq!(
+            days_from_now_to_jwt_time_with_clock(&FixedClock(1_000), Some(2)).unwrap(),
+            Some(1_000 + 2 * SECS_PER_DAY)
+        );
+    }
+
+    #[test]
+    fn pre_epoch_clock() {
+        assert_eq!(
+            days_from_now_to_jwt_time_with_clock(&PreEpochClock, None).unwrap(),
+            None
+        );
+        match days_from_now_to_jwt_time_with_clock(&PreEpochClock, Some(1)) {
+            Err(e) => match e.kind() {
+                ErrorKind::ClockError => (),
+                _ => panic!("failed to assert errors::ErrorKind::ClockError"),
+            },
+            Ok(_) => panic!("pre-epoch clock produced a timestamp"),
+        }
+        assert!(days_from_now_to_jwt_time(Some(1)).unwrap().is_some());
+
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let builder = ClaimsBuilder::<Actor>::new().name("testing").clone();
+        let (module, account) = (KeyPair::new_module(), KeyPair::new_account());
+        match sign_buffer_with_builder_at(&PreEpochClock, &dec_module, &module, &account, &builder)
+        {
+            Err(e) => match e.kind() {
+                ErrorKind::ClockError => (),
+                _ => panic!("failed to assert errors::ErrorKind::ClockError"),
+            },
+            Ok(_) => panic!("signed claims with a pre-epoch clock"),
+        }
+        let signed =
+            sign_buffer_with_builder_at(&SystemClock, &dec_module, &module, &account, &builder)
+                .unwrap();
+        assert!(extract_claims(&signed).unwrap().unwrap().claims.issued_at > 0);
+    }
+
+    #[test]
+    fn detached_signature() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .build();
+        let jwt = sign_detached(&dec_module, &claims, &kp).unwrap();
+        let token = verify_detached(&dec_module, &jwt).unwrap();
+        assert_eq!(token.claims.issuer, kp.public_key());
+
+        // The claims are checked and normalized as they are when embedded
+        let expired = Claims {
+            expires: Some(1),
+            ..claims.clone()
+        };
+        match sign_detached(&dec_module, &expired, &kp) {
+            Err(e) => match e.kind() {
+                ErrorKind::AlreadyExpired(1) => (),
+                _ => panic!("failed to assert errors::ErrorKind::AlreadyExpired"),
+            },
+            Ok(_) => panic!("signed claims that were already expired"),
+        }
+        let mut unsorted = claims.clone();
+        unsorted.metadata.as_mut().unwrap().caps = Some(vec![
+            MESSAGING.to_string(),
+            caps::LOGGING.to_string(),
+            MESSAGING.to_string(),
+        ]);
+        let detached = sign_detached(&dec_module, &unsorted, &kp).unwrap();
+        assert_eq!(
+            verify_detached(&dec_module, &detached)
+                .unwrap()
+                .claims
+                .metadata
+                .unwrap()
+                .caps,
+            Some(vec![caps::LOGGING.to_string(), MESSAGING.to_string()])
+        );
+
+        let mut other_module = dec_module.clone();
+        write_custom_section(&mut other_module, "extra", b"tampered");
+        match verify_detached(&other_module, &jwt) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidModuleHash => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidModuleHash"),
+            },
+            Ok(_) => panic!("detached token verified against the wrong module"),
+        }
+
+        // Re-signing with a different key invalidates the signature
+        let forged = jwt.rsplit_once('.').unwrap().0.to_string()
+            + "."
+            + sign_detached(&dec_module, &claims, &KeyPair::new_account())
+                .unwrap()
+                .rsplit_once('.')
+                .unwrap()
+                .1;
+        assert!(verify_detached(&dec_module, &forged).is_err());
+    }
+
+    // Signs with ring directly rather than through nkeys, standing in for a key held elsewhere
+    struct ExternalSigner {
+        seed: [u8; 32],
+        signatures: std::cell::Cell<usize>,
+    }
+
+    impl WascapSigner for ExternalSigner {
+        fn public_key(&self) -> String {
+            KeyPair::new_from_raw(nkeys::KeyPairType::Account, self.seed)
+                .unwrap()
+                .public_key()
+        }
+
+        fn sign(&self, input: &[u8]) -> Result<Vec<u8>> {
+            self.signatures.set(self.signatures.get() + 1);
+            let kp = ring::signature::Ed25519KeyPair::from_seed_unchecked(&self.seed).unwrap();
+            Ok(kp.sign(input).as_ref().to_vec())
+        }
+    }
+
+    #[test]
+    fn embed_with_external_signer() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let signer = ExternalSigner {
+            seed: [42; 32],
+            signatures: Default::default(),
+        };
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&signer.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        let embedded = embed_claims(&dec_module, &claims, &signer).unwrap();
+        assert_eq!(signer.signatures.get(), 1);
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(token.claims.issuer, signer.public_key());
+
+        // The signatures are the same as those of the equivalent key pair
+        let kp = KeyPair::new_from_raw(nkeys::KeyPairType::Account, signer.seed).unwrap();
+        assert_eq!(
+            embedded,
+            embed_claims(&dec_module, &token.claims, &kp).unwrap()
+        );
+    }
+
+    #[test]
+    fn detached_binding() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let jwt = sign_detached(&dec_module, &claims, &kp).unwrap();
+        let token = verify_detached_binding(&dec_module, &jwt).unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(token.claims.subject, claims.subject);
+
+        let mut other_module = dec_module.clone();
+        write_custom_section(&mut other_module, "extra", b"tampered");
+        match verify_detached_binding(&other_module, &jwt) {
+            Err(e) => match e.kind() {
+                ErrorKind::HashMismatch => (),
+                _ => panic!("failed to assert errors::ErrorKind::HashMismatch"),
+            },
+            Ok(_) => panic!("detached token verified against the wrong module"),
+        }
+
+        // sign_detached refuses other subjects, so the token is signed by hand
+        let account = KeyPair::new_account().public_key();
+        claims.subject = account.clone();
+        let jwt = with_module_hash(&dec_module, &claims)
+            .unwrap()
+            .encode(&kp)
+            .unwrap();
+        assert!(verify_detached(&dec_module, &jwt).is_ok());
+        match verify_detached_binding(&dec_module, &jwt) {
+            Err(e) => match e.kind() {
+                ErrorKind::SubjectNotModuleKey(subject) => assert_eq!(*subject, account),
+                _ => panic!("failed to assert errors::ErrorKind::SubjectNotModuleKey"),
+            },
+            Ok(_) => panic!("detached token bound to an account key"),
+        }
+    }
+
+    #[test]
+    fn claims_inserted_before_name_section() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        write_custom_section(&mut dec_module, "name", b"\x00\x01a");
+        write_custom_section(&mut dec_module, ".debug_info", b"");
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let reembedded = embed_claims(&embedded, &claims, &kp).unwrap();
+
+        for module in &[embedded, reembedded] {
+            let customs: Vec<String> = Parser::new(0)
+                .parse_all(module)
+                .filter_map(|p| match p.unwrap() {
+                    CustomSection(reader) => Some(reader.name().to_string()),
+                    _ => None,
+                })
+                .collect();
+            let n = customs.len();
+            assert_eq!(
+                &customs[n - 3..],
+                &[
+                    SECTION_WC_JWT.to_string(),
+                    "name".into(),
+                    ".debug_info".into()
+                ]
+            );
+            assert!(extract_claims(module).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn invalid_jwt_encoding() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        write_custom_section(&mut dec_module, SECTION_WC_JWT, &[0xc3, 0x28, 0xff]);
+
+        match extract_claims(&dec_module) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidJwtEncoding(section, _) => assert_eq!(section, SECTION_WC_JWT),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidJwtEncoding"),
+            },
+            Ok(_) => panic!("extracted claims from a corrupted section"),
+        }
+        let err = extract_claims_from_reader(std::io::Cursor::new(&dec_module)).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidJwtEncoding(..)));
+    }
+
+    #[test]
+    fn legacy_hash_not_verified() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert!(extract_claims(&embedded).unwrap().unwrap().hash_verified);
+
+        // Tokens from before hash verification are still accepted, but flagged
+        let legacy = Claims {
+            metadata: Some(Actor {
+                module_hash: "legacy".to_string(),
+                ..claims.metadata.clone().unwrap()
+            }),
+            wascap_revision: None,
+            ..claims
+        }
+        .encode(&kp)
+        .unwrap();
+        let mut legacy_module = dec_module.clone();
+        write_custom_section(&mut legacy_module, SECTION_JWT, legacy.as_bytes());
+        let token = extract_claims(&legacy_module).unwrap().unwrap();
+        assert!(!token.hash_verified);
+        let token = extract_claims_from_reader(std::io::Cursor::new(&legacy_module))
+            .unwrap()
+            .unwrap();
+        assert!(!token.hash_verified);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn embed_async() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let issuer = kp.public_key();
+
+        let embedded = embed_claims_async(dec_module, claims, kp).await.unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(token.claims.issuer, issuer);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn batch_signing() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let template = ClaimsTemplate {
+            caps: vec![MESSAGING.to_string()],
+            ver: Some("v1.0.0".to_string()),
+            ..Default::default()
+        };
+        let modules: Vec<_> = (0..5)
+            .map(|i| (format!("module-{}", i), dec_module.clone()))
+            .chain(std::iter::once(("broken".to_string(), vec![0, 1, 2])))
+            .map(|(name, buf)| (name, buf, KeyPair::new_module()))
+            .collect();
+        let subjects: Vec<_> = modules.iter().map(|(_, _, kp)| kp.public_key()).collect();
+
+        let signed = sign_batch(modules, &kp, &template);
+        assert_eq!(signed.len(), 6);
+        for (i, result) in signed[..5].iter().enumerate() {
+            let token = extract_claims(result.as_ref().unwrap()).unwrap().unwrap();
+            let meta = token.claims.metadata.unwrap();
+            assert_eq!(meta.name, Some(format!("module-{}", i)));
+            assert_eq!(meta.caps, Some(vec![MESSAGING.to_string()]));
+            assert_eq!(token.claims.issuer, kp.public_key());
+            assert_eq!(token.claims.subject, subjects[i]);
+        }
+        assert!(signed[5].is_err());
+        assert!(sign_batch(vec![], &kp, &template).is_empty());
+    }
+
+    #[test]
+    fn module_integrity() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        assert!(!verify_module_integrity(&dec_module).unwrap());
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert!(verify_module_integrity(&embedded).unwrap());
+
+        let mut corrupted = embedded.clone();
+        write_custom_section(&mut corrupted, "extra", b"corrupted");
+        assert!(!verify_module_integrity(&corrupted).unwrap());
+    }
+
+    #[test]
+    fn forbidden_start_function() {
+        let mut types = wasm_encoder::TypeSection::new();
+        types.function(vec![], vec![]);
+        let mut functions = wasm_encoder::FunctionSection::new();
+        functions.function(0);
+        let mut code = wasm_encoder::CodeSection::new();
+        let mut body = wasm_encoder::Function::new(vec![]);
+        body.instruction(&wasm_encoder::Instruction::End);
+        code.function(&body);
+        let mut module = wasm_encoder::Module::new();
+        module
+            .section(&types)
+            .section(&functions)
+            .section(&wasm_encoder::StartSection { function_index: 0 })
+            .section(&code);
+        let module = module.finish();
+        assert!(module_stats(&module).unwrap().has_start);
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&module, &claims, &kp).unwrap();
+        assert!(full_report(&embedded, &VerifyOptions::default())
+            .unwrap()
+            .is_valid());
+
+        let opts = VerifyOptions {
+            forbid_start: true,
+            ..Default::default()
+        };
+        match full_report(&embedded, &opts) {
+            Err(e) => match e.kind() {
+                ErrorKind::StartFunctionForbidden => (),
+                _ => panic!("failed to assert errors::ErrorKind::StartFunctionForbidden"),
+            },
+            Ok(_) => panic!("accepted a module with a start function"),
+        }
+        let without_start = embed_claims(&decode(WASM_BASE64).unwrap(), &claims, &kp).unwrap();
+        assert!(full_report(&without_start, &opts).unwrap().is_valid());
+
+        // A component's own start function runs at instantiation just the same
+        let mut component = wasm_encoder::Component::new();
+        component.section(&wasm_encoder::ComponentStartSection {
+            function_index: 0,
+            args: [],
+            results: 0,
+        });
+        let component = embed_claims(&component.finish(), &claims, &kp).unwrap();
+        assert!(matches!(
+            full_report(&component, &opts).unwrap_err().kind(),
+            ErrorKind::StartFunctionForbidden
+        ));
+    }
+
+    // Serves HTTP on a local port, answering each request with the status and headers, and the
+    // body, that `respond` returns for its method, path and body. Connections are closed after
+    // each response, which marks the end of bodies sent without a `Content-Length`
+    #[cfg(feature = "reqwest")]
+    fn serve<F>(respond: F) -> String
+    where
+        F: Fn(&str, &str, &[u8]) -> (String, Vec<u8>) + Send + 'static,
+    {
+        use std::{io::BufRead, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = std::io::BufReader::new(&stream);
+                let mut line = String::new();
+                request.read_line(&mut line).unwrap();
+                let mut parts = line.split(' ').map(str::to_string);
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap());
+                let mut length = 0;
+                while line != "\r\n" {
+                    line.clear();
+                    request.read_line(&mut line).unwrap();
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                request.read_exact(&mut body).unwrap();
+                let (head, body) = respond(&method, &path, &body);
+                let _ = write!(stream, "HTTP/1.1 {}\r\nConnection: close\r\n\r\n", head);
+                let _ = stream.write_all(&body);
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[tokio::test]
+    async fn verify_downloaded_module() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        // With a `Content-Length` at `/module`, and without one at `/unsized`
+        let module = embedded.clone();
+        let base = serve(move |_, path, _| match path {
+            "/module" => (
+                format!("200 OK\r\nContent-Length: {}", module.len()),
+                module.clone(),
+            ),
+            "/unsized" => ("200 OK".to_string(), module.clone()),
+            _ => ("404 Not Found\r\nContent-Length: 0".to_string(), Vec::new()),
+        });
+        let opts = VerifyOptions::default();
+
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&verify_url(&base, embedded.len(), &opts));
+
+        for path in &["/module", "/unsized"] {
+            let url = format!("{}{}", base, path);
+            let token = verify_url(&url, embedded.len(), &opts).await.unwrap();
+            assert_eq!(
+                token.claims,
+                extract_claims(&embedded).unwrap().unwrap().claims
+            );
+            assert!(token.hash_verified);
+
+            match verify_url(&url, embedded.len() - 1, &opts).await {
+                Err(e) => match e.kind() {
+                    ErrorKind::ArtifactTooLarge(max) => assert_eq!(*max, embedded.len() - 1),
+                    _ => panic!("failed to assert errors::ErrorKind::ArtifactTooLarge"),
+                },
+                Ok(_) => panic!("downloaded past the size limit"),
+            }
+        }
+
+        let untrusted = VerifyOptions {
+            trusted_issuers: Some(vec![KeyPair::new_account().public_key()]),
+            ..Default::default()
+        };
+        match verify_url(&format!("{}/module", base), embedded.len(), &untrusted).await {
+            Err(e) => match e.kind() {
+                ErrorKind::UntrustedIssuer(issuer) => assert_eq!(*issuer, kp.public_key()),
+                _ => panic!("failed to assert errors::ErrorKind::UntrustedIssuer"),
+            },
+            Ok(_) => panic!("verified a module from an untrusted issuer"),
+        }
+        match verify_url(&format!("{}/missing", base), embedded.len(), &opts).await {
+            Err(e) => match e.kind() {
+                ErrorKind::Http(err) => assert_eq!(err.status().unwrap().as_u16(), 404),
+                _ => panic!("failed to assert errors::ErrorKind::Http"),
+            },
+            Ok(_) => panic!("verified a missing module"),
+        }
+    }
+
+    #[test]
+    fn minimum_revision() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let opts = VerifyOptions {
+            min_revision: Some(WASCAP_INTERNAL_REVISION),
+            ..Default::default()
+        };
+        assert!(full_report(&embedded, &opts).unwrap().is_valid());
+
+        let too_new = VerifyOptions {
+            min_revision: Some(WASCAP_INTERNAL_REVISION + 1),
+            ..Default::default()
+        };
+        claims.wascap_revision = None;
+        let unrevised = embed_claims(&dec_module, &claims, &kp).unwrap();
+        for (module, opts, expected) in &[
+            (&embedded, &too_new, WASCAP_INTERNAL_REVISION),
+            (&unrevised, &opts, 0),
+        ] {
+            match full_report(module, opts) {
+                Err(e) => match e.kind() {
+                    ErrorKind::RevisionTooOld { revision, .. } => assert_eq!(revision, expected),
+                    _ => panic!("failed to assert errors::ErrorKind::RevisionTooOld"),
+                },
+                Ok(_) => panic!("accepted a token below the minimum revision"),
+            }
+        }
+    }
+
+    #[test]
+    fn external_signing() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+
+        let (header_payload, bytes) = signing_payload(&dec_module, &claims).unwrap();
+        assert_eq!(header_payload.matches('.').count(), 1);
+        // Stands in for the HSM, which only ever sees the bytes
+        let signature = kp.sign(&bytes).unwrap();
+        let signed = assemble_signed_module(&dec_module, &header_payload, &signature).unwrap();
+        let token = extract_claims(&signed).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(token.claims.subject, claims.subject);
+        assert_eq!(
+            strip_claims(&signed).unwrap(),
+            strip_claims(&dec_module).unwrap()
+        );
+
+        let forged = KeyPair::new_account().sign(&bytes).unwrap();
+        match assemble_signed_module(&dec_module, &header_payload, &forged) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("assembled a module with a forged signature"),
+        }
+
+        let mut other_module = dec_module;
+        write_custom_section(&mut other_module, "extra", b"other");
+        match assemble_signed_module(&other_module, &header_payload, &signature) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidModuleHash => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidModuleHash"),
+            },
+            Ok(_) => panic!("assembled claims prepared for a different module"),
+        }
+    }
+
+    #[test]
+    fn normalized_lists() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap("wasmcloud:Logging")
+            .with_cap("wasmcloud:logging")
+            .with_cap(crate::caps::STANDARD_IO)
+            .with_tag("zeta")
+            .with_tag("Alpha")
+            .with_tag("zeta")
+            .build();
+
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let meta = extract_claims(&embedded)
+            .unwrap()
+            .unwrap()
+            .claims
+            .metadata
+            .unwrap();
+        assert_eq!(
+            meta.caps.unwrap(),
+            vec![crate::caps::STANDARD_IO, "wasmcloud:logging"]
+        );
+        assert_eq!(meta.tags.unwrap(), vec!["Alpha", "zeta"]);
+
+        let verbatim = EmbedOptions {
+            preserve_lists: true,
+            ..Default::default()
+        };
+        let embedded = embed_claims_with_options(&dec_module, &claims, &kp, &verbatim).unwrap();
+        let meta = extract_claims(&embedded)
+            .unwrap()
+            .unwrap()
+            .claims
+            .metadata
+            .unwrap();
+        assert_eq!(meta.caps, claims.metadata.as_ref().unwrap().caps);
+        assert_eq!(meta.tags, claims.metadata.as_ref().unwrap().tags);
+    }
+
+    #[test]
+    fn peek_unverified_issuer() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        assert_eq!(peek_issuer(&dec_module).unwrap(), None);
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert_eq!(peek_issuer(&embedded).unwrap(), Some(kp.public_key()));
+
+        // Claims signed by someone other than the issuer they name still show that issuer
+        let forged = embed_claims(&dec_module, &claims, &KeyPair::new_account()).unwrap();
+        assert_eq!(peek_issuer(&forged).unwrap(), Some(kp.public_key()));
+        assert!(extract_claims(&forged).is_err());
+    }
+
+    #[test]
+    fn peek_tampered_claims() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        assert!(peek_claims(&dec_module).unwrap().is_none());
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let (peeked, hash_matches) = peek_claims(&embedded).unwrap().unwrap();
+        assert!(hash_matches);
+        assert_eq!(peeked.subject, claims.subject);
+
+        let mut tampered = embedded;
+        write_custom_section(&mut tampered, "extra", b"tampered");
+        assert!(extract_claims(&tampered).is_err());
+        let (peeked, hash_matches) = peek_claims(&tampered).unwrap().unwrap();
+        assert!(!hash_matches);
+        assert_eq!(peeked.subject, claims.subject);
+        assert_eq!(peeked.issuer, kp.public_key());
+    }
+
+    #[test]
+    fn verification_report() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        claims.not_before = Some(1_000);
+        claims.expires = Some(2_000);
+        let unchecked = EmbedOptions {
+            check_dates: false,
+            ..Default::default()
+        };
+        let embedded = embed_claims_with_options(&dec_module, &claims, &kp, &unchecked).unwrap();
+
+        let clock = FixedClock(1_500);
+        let opts = VerifyOptions {
+            trusted_issuers: Some(vec![kp.public_key()]),
+            clock: &clock,
+            ..Default::default()
+        };
+        let report = full_report(&embedded, &opts).unwrap();
+        assert!(report.is_valid(), "{:?}", report.failures());
+        assert!(report.token.hash_verified);
+
+        // Every failing check is reported, not just the first
+        let mut corrupted = embedded;
+        write_custom_section(&mut corrupted, "extra", b"corrupted");
+        let late = FixedClock(3_000);
+        let opts = VerifyOptions {
+            trusted_issuers: Some(vec![KeyPair::new_account().public_key()]),
+            clock: &late,
+            ..Default::default()
+        };
+        let report = full_report(&corrupted, &opts).unwrap();
+        assert!(report.signature.is_none());
+        assert!(report.module_hash.is_some());
+        assert!(report.expiry.is_some());
+        assert!(report.not_before.is_none());
+        assert!(report.issuer.is_some());
+        assert_eq!(report.failures().len(), 3);
+        assert!(!report.token.hash_verified);
+
+        let early = FixedClock(500);
+        let opts = VerifyOptions {
+            clock: &early,
+            ..Default::default()
+        };
+        let report = full_report(&corrupted, &opts).unwrap();
+        assert!(report.not_before.is_some());
+        assert!(report.expiry.is_none());
+        assert!(report.issuer.is_none());
+
+        match full_report(&dec_module, &VerifyOptions::default()) {
+            Err(e) => match e.kind() {
+                ErrorKind::NoEmbeddedClaims => (),
+                _ => panic!("failed to assert errors::ErrorKind::NoEmbeddedClaims"),
+            },
+            Ok(_) => panic!("reported on a module without claims"),
+        }
+    }
+
+    #[test]
+    fn recorded_imports() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let imports = scan_imports(&dec_module).unwrap();
+
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let opts = EmbedOptions {
+            record_imports: true,
+            ..Default::default()
+        };
+        let embedded = embed_claims_with_options(&dec_module, &claims, &kp, &opts).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(
+            token.claims.metadata.as_ref().unwrap().imports,
+            Some(imports)
         );
-        if let Some(token) = extract_claims(&modified_bytecode).unwrap() {
-            assert_eq!(claims.issuer, token.claims.issuer);
-            assert_eq!(claims.subject, token.claims.subject);
-        } else {
-            unreachable!()
+        assert!(undeclared_imports(&embedded, &token.claims)
+            .unwrap()
+            .is_empty());
+
+        // Imports are only recorded when asked for
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(token.claims.metadata.unwrap().imports, None);
+
+        // A module importing more than its claims declare is reported
+        let mut declared = claims.clone();
+        declared.metadata.as_mut().unwrap().imports = Some(vec![]);
+        let mut module = wasm_encoder::Module::new();
+        let mut types = wasm_encoder::TypeSection::new();
+        types.function(vec![], vec![]);
+        module.section(&types);
+        let mut imports = wasm_encoder::ImportSection::new();
+        imports.import(
+            "wasmbus",
+            "__host_call",
+            wasm_encoder::EntityType::Function(0),
+        );
+        module.section(&imports);
+        let module = module.finish();
+        assert_eq!(scan_imports(&module).unwrap(), vec!["wasmbus::__host_call"]);
+        assert_eq!(
+            undeclared_imports(&module, &declared).unwrap(),
+            vec!["wasmbus::__host_call"]
+        );
+
+        let mut component = wasm_encoder::Component::new();
+        let mut imports = wasm_encoder::ComponentImportSection::new();
+        imports.import(
+            "wasi:http/outgoing-handler",
+            wasm_encoder::ComponentTypeRef::Func(0),
+        );
+        component.section(&imports);
+        assert_eq!(
+            scan_imports(&component.finish()).unwrap(),
+            vec!["wasi:http/outgoing-handler"]
+        );
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn extract_compressed() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Cursor;
+
+        fn gzip(data: &[u8]) -> Vec<u8> {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let expected = extract_claims(&embedded).unwrap().unwrap();
+
+        let gz = gzip(&embedded);
+        assert!(gz.len() < embedded.len());
+        let token = extract_claims_gz(Cursor::new(&gz)).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(token, expected);
+
+        // Split across two members and padded to a block size, as some tools write it
+        let mut padded = gzip(&embedded[..embedded.len() / 2]);
+        padded.extend(gzip(&embedded[embedded.len() / 2..]));
+        padded.resize(padded.len() + 512 - padded.len() % 512, 0);
+        assert_eq!(
+            extract_claims_gz(Cursor::new(&padded)).unwrap().unwrap(),
+            expected
+        );
+
+        // Claims hashed with SHA-512 are verified by decompressing the module a second time,
+        // from wherever the compressed module starts in the reader
+        let mut claims = claims;
+        if let Some(ref mut meta) = claims.metadata {
+            meta.hash_algorithm = HashAlgorithm::Sha512;
+        }
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let mut prefixed = b"prefix".to_vec();
+        prefixed.extend(gzip(&embedded));
+        let mut reader = Cursor::new(&prefixed);
+        reader.set_position(6);
+        assert!(extract_claims_gz(reader).unwrap().unwrap().hash_verified);
+
+        match extract_claims_gz_with_max_size(Cursor::new(&gz), embedded.len() / 2) {
+            Err(e) => match e.kind() {
+                ErrorKind::DecompressedTooLarge(max) => assert_eq!(*max, embedded.len() / 2),
+                _ => panic!("failed to assert errors::ErrorKind::DecompressedTooLarge"),
+            },
+            Ok(_) => panic!("decompressed a module over the limit"),
+        }
+        match extract_claims_gz(Cursor::new(&embedded)) {
+            Err(e) => match e.kind() {
+                ErrorKind::Decompression(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::Decompression"),
+            },
+            Ok(_) => panic!("extracted claims from a stream that isn't gzip"),
+        }
+    }
+
+    #[test]
+    fn re_sign_module() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let old_kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&old_kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &old_kp).unwrap();
+        let original = extract_claims(&embedded).unwrap().unwrap().claims;
+
+        let new_kp = KeyPair::new_account();
+        let kept = re_sign(&embedded, &new_kp, true).unwrap();
+        let token = extract_claims(&kept).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(
+            token.claims,
+            Claims {
+                issuer: new_kp.public_key(),
+                ..original.clone()
+            }
+        );
+        assert_eq!(
+            list_custom_sections(&kept).unwrap(),
+            list_custom_sections(&embedded).unwrap()
+        );
+
+        let renamed = re_sign(&embedded, &new_kp, false).unwrap();
+        let token = extract_claims(&renamed).unwrap().unwrap();
+        assert_ne!(token.claims.id, original.id);
+        assert_eq!(token.claims.issued_at, original.issued_at);
+
+        // Lists embedded without normalization are re-signed as they are
+        let unsorted = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&old_kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .with_cap(MESSAGING)
+            .with_cap(LOGGING)
+            .with_cap(MESSAGING)
+            .build();
+        let opts = EmbedOptions {
+            preserve_lists: true,
+            ..Default::default()
+        };
+        let embedded_unsorted =
+            embed_claims_with_options(&dec_module, &unsorted, &old_kp, &opts).unwrap();
+        let original = extract_claims(&embedded_unsorted).unwrap().unwrap().claims;
+        let resigned = re_sign(&embedded_unsorted, &new_kp, false).unwrap();
+        let token = extract_claims(&resigned).unwrap().unwrap();
+        assert_eq!(
+            token.claims,
+            Claims {
+                issuer: new_kp.public_key(),
+                id: token.claims.id.clone(),
+                ..original
+            }
+        );
+        assert_eq!(
+            token.claims.metadata.unwrap().caps.unwrap(),
+            vec![MESSAGING, LOGGING, MESSAGING]
+        );
+
+        match re_sign(&dec_module, &new_kp, true) {
+            Err(e) => match e.kind() {
+                ErrorKind::NoEmbeddedClaims => (),
+                _ => panic!("failed to assert errors::ErrorKind::NoEmbeddedClaims"),
+            },
+            Ok(_) => panic!("re-signed a module without claims"),
+        }
+
+        let forged = forge_claims(&embedded, |claims| {
+            claims.metadata.as_mut().unwrap().caps = Some(vec![KEY_VALUE.to_string()]);
+        });
+        match re_sign(&forged, &new_kp, true) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("re-signed edited claims"),
+        }
+    }
+
+    #[test]
+    fn verified_extraction() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        assert!(extract_claims(&embedded).unwrap().is_some());
+        assert!(extract_claims(&dec_module).unwrap().is_none());
+
+        let expired = Claims {
+            expires: Some(1),
+            ..claims
+        };
+        match embed_claims(&dec_module, &expired, &kp) {
+            Err(e) => match e.kind() {
+                ErrorKind::AlreadyExpired(1) => (),
+                _ => panic!("failed to assert errors::ErrorKind::AlreadyExpired"),
+            },
+            Ok(_) => panic!("embedded claims that were already expired"),
+        }
+        let unchecked = EmbedOptions {
+            check_dates: false,
+            ..Default::default()
+        };
+        let embedded = embed_claims_with_options(&dec_module, &expired, &kp, &unchecked).unwrap();
+        assert!(extract_claims_unverified(&embedded).unwrap().is_some());
+        match extract_claims(&embedded) {
+            Err(e) => match e.kind() {
+                ErrorKind::TokenExpired(1) => (),
+                _ => panic!("failed to assert errors::ErrorKind::TokenExpired"),
+            },
+            Ok(_) => panic!("extracted expired claims"),
+        }
+    }
+
+    #[test]
+    fn trusted_issuers() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        let other = KeyPair::new_account().public_key();
+        let trusted = vec![other.clone(), kp.public_key()];
+        assert_eq!(
+            extract_claims_trusted(&embedded, &trusted)
+                .unwrap()
+                .unwrap()
+                .claims
+                .issuer,
+            kp.public_key()
+        );
+        for untrusted in &[vec![other], vec![]] {
+            match extract_claims_trusted(&embedded, untrusted) {
+                Err(e) => match e.kind() {
+                    ErrorKind::UntrustedIssuer(iss) => assert_eq!(*iss, kp.public_key()),
+                    _ => panic!("failed to assert errors::ErrorKind::UntrustedIssuer"),
+                },
+                Ok(_) => panic!("extracted claims from an untrusted issuer"),
+            }
+        }
+        for trusted in &[trusted, vec![]] {
+            assert!(extract_claims_trusted(&dec_module, trusted)
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn rotated_issuer_keys() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let old_kp = KeyPair::new_account();
+        let new_kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&old_kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &old_kp).unwrap();
+
+        let mut history = HashMap::new();
+        history.insert(
+            new_kp.public_key(),
+            vec![new_kp.public_key(), old_kp.public_key()],
+        );
+        let token = extract_claims_with_key_history(&embedded, &history)
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.claims.issuer, old_kp.public_key());
+
+        history.insert(new_kp.public_key(), vec![new_kp.public_key()]);
+        match extract_claims_with_key_history(&embedded, &history) {
+            Err(e) => match e.kind() {
+                ErrorKind::UntrustedIssuer(iss) => assert_eq!(*iss, old_kp.public_key()),
+                _ => panic!("failed to assert errors::ErrorKind::UntrustedIssuer"),
+            },
+            Ok(_) => panic!("extracted claims from a key missing from the history"),
+        }
+        assert!(extract_claims_with_key_history(&dec_module, &history)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn revoked_claims() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&module.public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        let mut list = RevocationList::new();
+        list.revoke_subject(&KeyPair::new_module().public_key());
+        assert!(extract_claims_with_revocation(&embedded, &list)
+            .unwrap()
+            .is_some());
+
+        let mut by_subject = list.clone();
+        by_subject.revoke_subject(&module.public_key());
+        let mut by_issuer = list.clone();
+        by_issuer.revoke_issuer(&kp.public_key());
+        for revoked in &[by_subject, by_issuer] {
+            // Lists are distributed as JSON
+            let revoked: RevocationList =
+                serde_json::from_str(&serde_json::to_string(revoked).unwrap()).unwrap();
+            match extract_claims_with_revocation(&embedded, &revoked) {
+                Err(e) => match e.kind() {
+                    ErrorKind::Revoked => (),
+                    _ => panic!("failed to assert errors::ErrorKind::Revoked"),
+                },
+                Ok(_) => panic!("extracted revoked claims"),
+            }
+        }
+
+        // Claims edited to name a subject that isn't revoked don't get past the check
+        let mut revoked = list;
+        revoked.revoke_subject(&module.public_key());
+        let forged = forge_claims(&embedded, |claims| {
+            claims.subject = KeyPair::new_module().public_key();
+            claims.id = nuid::next();
+        });
+        match extract_claims_with_revocation(&forged, &revoked) {
+            Err(e) => match e.kind() {
+                ErrorKind::SignatureInvalid => (),
+                _ => panic!("failed to assert errors::ErrorKind::SignatureInvalid"),
+            },
+            Ok(_) => panic!("extracted forged claims"),
+        }
+    }
+
+    #[test]
+    fn short_lived_expiry() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let before = since_the_epoch().unwrap().as_secs();
+        let signed = sign_buffer_with_expiry(
+            "testing".to_string(),
+            &dec_module,
+            KeyPair::new_module(),
+            KeyPair::new_account(),
+            Some(Duration::from_secs(15 * 60)),
+            None,
+            vec![],
+            vec![],
+            false,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        let hour = hours_from_now_to_jwt_time(Some(1)).unwrap().unwrap();
+        let minutes = minutes_from_now_to_jwt_time(Some(15)).unwrap().unwrap();
+        let after = since_the_epoch().unwrap().as_secs();
+
+        let claims = extract_claims(&signed).unwrap().unwrap().claims;
+        let exp = claims.expires.unwrap();
+        assert!(exp >= before + 900 && exp <= after + 900);
+        assert_eq!(claims.not_before, None);
+        assert!(hour >= before + 3600 && hour <= after + 3600);
+        assert!(minutes >= before + 900 && minutes <= after + 900);
+        assert_eq!(minutes_from_now_to_jwt_time(None).unwrap(), None);
+    }
+
+    #[test]
+    fn timestamp_overflow() {
+        let overflowed = |result: Result<Option<u64>>| match result {
+            Err(e) => match e.kind() {
+                ErrorKind::TimestampOverflow => (),
+                _ => panic!("failed to assert errors::ErrorKind::TimestampOverflow"),
+            },
+            Ok(_) => panic!("computed a timestamp past the largest one"),
+        };
+        overflowed(days_from_now_to_jwt_time(Some(u64::MAX / SECS_PER_DAY + 1)));
+        overflowed(hours_from_now_to_jwt_time(Some(u64::MAX / SECS_PER_HOUR)));
+        overflowed(minutes_from_now_to_jwt_time(Some(u64::MAX)));
+
+        let signed = sign_buffer_with_expiry(
+            "testing".to_string(),
+            decode(WASM_BASE64).unwrap(),
+            KeyPair::new_module(),
+            KeyPair::new_account(),
+            None,
+            Some(Duration::MAX),
+            vec![],
+            vec![],
+            false,
+            None,
+            None,
+            None,
+            None,
+        );
+        match signed {
+            Err(e) => match e.kind() {
+                ErrorKind::TimestampOverflow => (),
+                _ => panic!("failed to assert errors::ErrorKind::TimestampOverflow"),
+            },
+            Ok(_) => panic!("signed claims with an unrepresentable not_before"),
+        }
+
+        // The builder can't fail, so it stops at the largest timestamp instead
+        let claims = ClaimsBuilder::<Actor>::new()
+            .expires_in(Duration::MAX)
+            .valid_in(Duration::MAX)
+            .build();
+        assert_eq!(claims.expires, Some(u64::MAX));
+        assert_eq!(claims.not_before, Some(u64::MAX));
+    }
+
+    #[test]
+    fn future_issued_at() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = Claims {
+            issued_at: since_the_epoch().unwrap().as_secs() + 3600,
+            ..ClaimsBuilder::<Actor>::new()
+                .name("test")
+                .issuer(&kp.public_key())
+                .subject(&KeyPair::new_module().public_key())
+                .build()
+        };
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        let lenient = ValidationOptions::default();
+        assert!(extract_claims_with_validation(&embedded, &lenient)
+            .unwrap()
+            .is_some());
+        let strict = ValidationOptions {
+            reject_future_issued_at: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            extract_claims_with_validation(&embedded, &strict)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::IssuedInFuture(_)
+        ));
+
+        // Moving the issue time back by hand breaks the signature instead
+        let forged = forge_claims(&embedded, |claims| {
+            claims.issued_at = since_the_epoch().unwrap().as_secs();
+        });
+        assert!(matches!(
+            extract_claims_with_validation(&forged, &strict)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::SignatureInvalid
+        ));
+    }
+
+    #[test]
+    fn sign_from_seed_files() {
+        let dir = std::env::temp_dir().join(format!("wascap-{}", nuid::next()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let account = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let acct_path = dir.join("account.nk");
+        let mod_path = dir.join("module.nk");
+        std::fs::write(&acct_path, format!("{}\n", account.seed().unwrap())).unwrap();
+        std::fs::write(&mod_path, module.seed().unwrap()).unwrap();
+        let in_path = dir.join("unsigned.wasm");
+        let out_path = dir.join("signed.wasm");
+        std::fs::write(&in_path, decode(WASM_BASE64).unwrap()).unwrap();
+
+        assert_eq!(
+            load_keypair_from_file(&acct_path).unwrap().public_key(),
+            account.public_key()
+        );
+        sign_file(
+            &in_path,
+            &out_path,
+            &acct_path,
+            &mod_path,
+            "test",
+            &ClaimsTemplate::default(),
+        )
+        .unwrap();
+        let token = extract_claims(std::fs::read(&out_path).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.claims.issuer, account.public_key());
+        assert_eq!(token.claims.subject, module.public_key());
+
+        let bad_path = dir.join("bad.nk");
+        std::fs::write(&bad_path, "not a seed").unwrap();
+        match load_keypair_from_file(&bad_path) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidSeed => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidSeed"),
+            },
+            Ok(_) => panic!("loaded a key pair from an invalid seed"),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keypair_from_environment() {
+        let account = KeyPair::new_account();
+        let var = format!("WASCAP_TEST_SEED_{}", nuid::next());
+        std::env::set_var(&var, format!("  {}\n", account.seed().unwrap()));
+        assert_eq!(
+            keypair_from_env(&var).unwrap().public_key(),
+            account.public_key()
+        );
+
+        std::env::set_var(&var, "not a seed");
+        match keypair_from_env(&var) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidSeed => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidSeed"),
+            },
+            Ok(_) => panic!("loaded a key pair from an invalid seed"),
+        }
+
+        std::env::remove_var(&var);
+        match keypair_from_env(&var) {
+            Err(e) => match e.kind() {
+                ErrorKind::MissingEnvVar(name) => assert_eq!(*name, var),
+                _ => panic!("failed to assert errors::ErrorKind::MissingEnvVar"),
+            },
+            Ok(_) => panic!("loaded a key pair from an unset variable"),
+        }
+    }
+
+    #[test]
+    fn sign_files_atomically() {
+        let dir = std::env::temp_dir().join(format!("wascap-{}", nuid::next()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let in_path = dir.join("unsigned.wasm");
+        let out_path = dir.join("signed.wasm");
+        std::fs::write(&in_path, decode(WASM_BASE64).unwrap()).unwrap();
+
+        sign_file_with_claims(&in_path, &out_path, &claims, &kp).unwrap();
+        let token = extract_claims(std::fs::read(&out_path).unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(token.hash_verified);
+
+        sign_file_in_place(&in_path, &claims, &kp).unwrap();
+        assert!(extract_claims(std::fs::read(&in_path).unwrap())
+            .unwrap()
+            .is_some());
+
+        // A failed signing leaves the existing output alone
+        let garbage = dir.join("garbage.wasm");
+        std::fs::write(&garbage, b"not a module").unwrap();
+        let before = std::fs::read(&out_path).unwrap();
+        assert!(sign_file_with_claims(&garbage, &out_path, &claims, &kp).is_err());
+        assert_eq!(std::fs::read(&out_path).unwrap(), before);
+
+        let mut entries: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec!["garbage.wasm", "signed.wasm", "unsigned.wasm"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reproducible_embedding() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let module = KeyPair::new_module();
+        let mut constraints = HashMap::new();
+        for cap in &[MESSAGING, KEY_VALUE, LOGGING] {
+            constraints.insert(cap.to_string(), CapConstraint { expires: Some(1) });
+        }
+        let sign = || {
+            let mut claims = Claims {
+                id: "build-1234".to_string(),
+                issued_at: 1_700_000_000,
+                ..ClaimsBuilder::<Actor>::new()
+                    .name("test")
+                    .issuer(&kp.public_key())
+                    .subject(&module.public_key())
+                    .with_cap(MESSAGING)
+                    .build()
+            };
+            // Collected from a fresh hash map each time, which may iterate in a different order
+            claims.metadata.as_mut().unwrap().cap_constraints =
+                Some(constraints.clone().into_iter().collect());
+            embed_claims_reproducible(&dec_module, &claims, &kp).unwrap()
+        };
+        assert_eq!(sign(), sign());
+
+        let generated = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&module.public_key())
+            .build();
+        let unset = Claims {
+            id: String::new(),
+            ..generated
+        };
+        assert!(embed_claims_reproducible(&dec_module, &unset, &kp).is_err());
+    }
+
+    #[test]
+    fn embedded_module_validates() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let reembedded = embed_claims(&embedded, &claims, &kp).unwrap();
+
+        for module in &[&dec_module, &embedded, &reembedded] {
+            wasmparser::Validator::new().validate_all(module).unwrap();
+        }
+    }
+
+    #[test]
+    fn custom_section_listing() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        let existing = list_custom_sections(&dec_module).unwrap();
+        write_custom_section(&mut dec_module, "producers", b"12345");
+        write_custom_section(&mut dec_module, ".debug_line", b"");
+
+        let sections = list_custom_sections(&dec_module).unwrap();
+        assert_eq!(sections[..existing.len()], existing[..]);
+        assert_eq!(
+            sections[existing.len()..],
+            [("producers".to_string(), 5), (".debug_line".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn custom_section_iteration() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        write_custom_section(&mut dec_module, "producers", b"12345");
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let embedded = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        let sections: Vec<(String, &[u8])> =
+            custom_sections(&embedded).collect::<Result<_>>().unwrap();
+        let listed = list_custom_sections(&embedded).unwrap();
+        assert_eq!(
+            sections
+                .iter()
+                .map(|(name, data)| (name.clone(), data.len()))
+                .collect::<Vec<_>>(),
+            listed
+        );
+        let producers = sections.iter().find(|(name, _)| name == "producers");
+        assert_eq!(producers.unwrap().1, b"12345");
+        let jwt = sections.iter().find(|(name, _)| name == SECTION_WC_JWT);
+        let token = extract_claims(&embedded).unwrap().unwrap();
+        assert_eq!(jwt.unwrap().1, token.jwt.as_bytes());
+
+        let truncated = &embedded[..embedded.len() - 1];
+        let results: Vec<_> = custom_sections(truncated).collect();
+        assert!(results.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn hash_scope_excludes_sections() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let unscoped = embed_claims(&dec_module, &claims, &kp).unwrap();
+        claims.metadata.as_mut().unwrap().hash_scope = HashScope {
+            excluded_sections: vec!["producers".to_string(), ".debug_*".to_string()],
+            ..Default::default()
+        };
+        let scoped = embed_claims(&dec_module, &claims, &kp).unwrap();
+
+        let rebuild = |module: &[u8]| {
+            let mut module = module.to_vec();
+            write_custom_section(&mut module, "producers", b"rustc 1.0");
+            write_custom_section(&mut module, ".debug_info", b"dwarf");
+            module
+        };
+        let token = extract_claims(rebuild(&scoped)).unwrap().unwrap();
+        assert!(token.hash_verified);
+        assert_eq!(
+            token.claims.metadata.unwrap().hash_scope,
+            claims.metadata.as_ref().unwrap().hash_scope
+        );
+        let token = extract_claims_from_reader(std::io::Cursor::new(rebuild(&scoped)))
+            .unwrap()
+            .unwrap();
+        assert!(token.hash_verified);
+
+        match extract_claims(rebuild(&unscoped)) {
+            Err(e) => match e.kind() {
+                ErrorKind::InvalidModuleHash => (),
+                _ => panic!("failed to assert errors::ErrorKind::InvalidModuleHash"),
+            },
+            Ok(_) => panic!("unscoped hash ignored added sections"),
+        }
+
+        // Sections that aren't excluded are still covered
+        let mut extra = scoped.clone();
+        write_custom_section(&mut extra, "extra", b"data");
+        assert!(extract_claims(&extra).is_err());
+    }
+
+    #[test]
+    fn hash_scope_excludes_data() {
+        use wasm_encoder::Instruction::{Drop, End, I32Const, Nop};
+        let module = |data: &[u8], body: &[wasm_encoder::Instruction]| {
+            let mut types = wasm_encoder::TypeSection::new();
+            types.function(vec![], vec![]);
+            let mut functions = wasm_encoder::FunctionSection::new();
+            functions.function(0);
+            let mut memories = wasm_encoder::MemorySection::new();
+            memories.memory(wasm_encoder::MemoryType {
+                minimum: 1,
+                maximum: None,
+                memory64: false,
+                shared: false,
+            });
+            let mut code = wasm_encoder::CodeSection::new();
+            let mut function = wasm_encoder::Function::new(vec![]);
+            for instruction in body {
+                function.instruction(instruction);
+            }
+            code.function(&function);
+            let mut segments = wasm_encoder::DataSection::new();
+            segments.active(
+                0,
+                &wasm_encoder::ConstExpr::i32_const(0),
+                data.iter().copied(),
+            );
+            let mut module = wasm_encoder::Module::new();
+            module
+                .section(&types)
+                .section(&functions)
+                .section(&memories)
+                .section(&code)
+                .section(&segments);
+            module.finish()
+        };
+        let original = module(b"config v1", &[Nop, End]);
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let full = embed_claims(&original, &claims, &kp).unwrap();
+        claims.metadata.as_mut().unwrap().hash_scope = HashScope::code_and_custom_only();
+        let code_only = embed_claims(&original, &claims, &kp).unwrap();
+        let token = extract_claims(&code_only).unwrap().unwrap();
+        assert!(token.claims.metadata.unwrap().hash_scope.exclude_data);
+
+        // Re-signing the patched module onto the original claims stands in for patching in place
+        let resign = |patched: &[u8], signed: &[u8]| {
+            let jwt = extract_claims(signed).unwrap().unwrap().jwt;
+            let mut patched = patched.to_vec();
+            write_custom_section(&mut patched, SECTION_WC_JWT, jwt.as_bytes());
+            patched
+        };
+        let patched_data = module(b"config v2", &[Nop, End]);
+        assert!(
+            extract_claims(resign(&patched_data, &code_only))
+                .unwrap()
+                .unwrap()
+                .hash_verified
+        );
+        assert!(extract_claims(resign(&patched_data, &full)).is_err());
+
+        let patched_code = module(b"config v1", &[I32Const(1), Drop, End]);
+        assert!(extract_claims(resign(&patched_code, &code_only)).is_err());
+    }
+
+    #[test]
+    fn truncated_module() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let truncated = &dec_module[..dec_module.len() / 2];
+
+        let malformed = |e: errors::Error| match e.into_kind() {
+            ErrorKind::MalformedModule { offset, detail } => {
+                assert!(offset <= truncated.len());
+                assert!(!detail.is_empty());
+            }
+            _ => panic!("failed to assert errors::ErrorKind::MalformedModule"),
+        };
+        malformed(compute_module_hash(truncated).unwrap_err());
+        malformed(extract_claims(truncated).unwrap_err());
+        malformed(extract_claims_from_reader(std::io::Cursor::new(truncated)).unwrap_err());
+    }
+    #[test]
+    fn unsigned_bytes_match_module_hash() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        write_custom_section(&mut dec_module, "producers", b"rustc 1.0");
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let signed = embed_claims(&dec_module, &claims, &kp).unwrap();
+        let cosigner = KeyPair::new_account();
+        claims.issuer = cosigner.public_key();
+        let cosigned = embed_additional_signature(&signed, &claims, &cosigner).unwrap();
+
+        for module in &[signed, cosigned] {
+            let unsigned = unsigned_bytes(module).unwrap();
+            wasmparser::Validator::new()
+                .validate_all(&unsigned)
+                .unwrap();
+            assert!(extract_claims(&unsigned).unwrap().is_none());
+            assert!(list_custom_sections(&unsigned)
+                .unwrap()
+                .iter()
+                .any(|(name, _)| name == "producers"));
+
+            let token = extract_claims(module).unwrap().unwrap();
+            let meta = token.claims.metadata.unwrap();
+            assert_eq!(
+                compute_module_hash_with_scope(&unsigned, meta.hash_algorithm, &meta.hash_scope)
+                    .unwrap(),
+                meta.module_hash
+            );
+        }
+        assert!(unsigned_bytes(b"").is_err());
+    }
+
+    #[test]
+    fn embed_with_precomputed_hash() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let hash = compute_module_hash(&dec_module).unwrap();
+
+        for expires in &[Some(since_the_epoch().unwrap().as_secs() + 3600), None] {
+            claims.expires = *expires;
+            let embedded = embed_claims_with_hash(&dec_module, &claims, &kp, &hash).unwrap();
+            let token = extract_claims(&embedded).unwrap().unwrap();
+            assert!(token.hash_verified);
+            assert_eq!(token.claims.expires, *expires);
+            assert_eq!(token.claims.metadata.unwrap().module_hash, hash);
+        }
+
+        match embed_claims_with_hash(&dec_module, &claims, &kp, "NOTAHASH") {
+            Err(e) => match e.kind() {
+                ErrorKind::MalformedHash(_) => (),
+                _ => panic!("failed to assert errors::ErrorKind::MalformedHash"),
+            },
+            Ok(_) => panic!("embedded claims with a malformed hash"),
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "precomputed module hash doesn't match the module")]
+    fn embed_with_wrong_precomputed_hash() {
+        let dec_module = decode(WASM_BASE64).unwrap();
+        let kp = KeyPair::new_account();
+        let claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        let mut other_module = dec_module.clone();
+        write_custom_section(&mut other_module, "extra", b"data");
+        let hash = compute_module_hash(&other_module).unwrap();
+        let _ = embed_claims_with_hash(&dec_module, &claims, &kp, &hash);
+    }
+
+    #[test]
+    fn embedded_hash_matches_separate_pass() {
+        let mut dec_module = decode(WASM_BASE64).unwrap();
+        write_custom_section(&mut dec_module, "producers", b"rustc 1.0");
+        let kp = KeyPair::new_account();
+        let mut claims = ClaimsBuilder::<Actor>::new()
+            .name("test")
+            .issuer(&kp.public_key())
+            .subject(&KeyPair::new_module().public_key())
+            .build();
+        {
+            let meta = claims.metadata.as_mut().unwrap();
+            meta.hash_algorithm = HashAlgorithm::Sha512;
+            meta.hash_scope = HashScope {
+                excluded_sections: vec!["producers".to_string()],
+                ..Default::default()
+            };
+        }
+
+        let signed = embed_claims(&dec_module, &claims, &kp).unwrap();
+        // Re-signing an already signed module hashes it without the existing JWT
+        let resigned = embed_claims(&signed, &claims, &kp).unwrap();
+        let meta = claims.metadata.as_ref().unwrap();
+        let expected =
+            compute_module_hash_with_scope(&dec_module, meta.hash_algorithm, &meta.hash_scope)
+                .unwrap();
+        for module in &[signed, resigned] {
+            let token = extract_claims(module).unwrap().unwrap();
+            assert!(token.hash_verified);
+            assert_eq!(token.claims.metadata.unwrap().module_hash, expected);
         }
     }
 }